@@ -1,7 +1,9 @@
 use std::{
   borrow::Cow,
-  env, io,
-  path::PathBuf,
+  env,
+  fs::{File, OpenOptions},
+  io::{self, BufReader, Write},
+  path::{Path, PathBuf},
   process::{exit, Command},
   time::Instant,
 };
@@ -9,15 +11,19 @@ use std::{
 use argus_ext::ty::TyCtxtExt;
 use argus_lib::{
   analysis,
-  find_bodies::{find_bodies, find_enclosing_bodies},
-  types::{ObligationHash, ToTarget},
+  find_bodies::{
+    find_bodies, find_enclosing_bodies, find_enclosing_items, find_items,
+  },
+  types::{BodyBundle, ObligationHash, ToTarget},
+  weights::{Label, RankMetrics, Sample, Weights},
 };
-use clap::{Parser, Subcommand};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
 use fluid_let::fluid_set;
-use rustc_hir::BodyId;
+use rustc_hir::{def_id::LocalDefId, BodyId};
 use rustc_interface::interface::Result as RustcResult;
 use rustc_middle::ty::TyCtxt;
 use rustc_plugin::{CrateFilter, RustcPlugin, RustcPluginArgs, Utf8Path};
+use rustc_session::{config::ErrorOutputType, EarlyDiagCtxt};
 use rustc_span::{FileName, RealFileName};
 use rustc_utils::{
   source_map::{
@@ -38,6 +44,25 @@ pub struct ArgusPluginArgs {
 
   #[clap(long)]
   show_stderr: bool,
+
+  /// Encoding of the analysis results.
+  #[clap(long, value_enum, default_value_t = OutputFormat::Json)]
+  format: OutputFormat,
+
+  /// Write the results to this file instead of stdout.
+  ///
+  /// As on stdout, each analyzed crate writes its own document. The file
+  /// is truncated once per invocation, and is required by binary formats,
+  /// output on stdout passes through Cargo which expects valid UTF-8.
+  #[clap(long)]
+  out_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum, Serialize, Deserialize)]
+enum OutputFormat {
+  Json,
+  /// Binary encoding with the same schema as JSON.
+  Msgpack,
 }
 
 #[derive(Subcommand, Serialize, Deserialize)]
@@ -57,6 +82,18 @@ enum ArgusCommand {
     end_line: usize,
     end_column: usize,
   },
+  /// Rank the labeled root causes of the workspace's failures with the
+  /// configured weights, and search for weights that rank them better.
+  Tune {
+    /// JSON array of `{ "file": ..., "hash": ... }` root causes.
+    labels: PathBuf,
+  },
+  /// Record the correction-set searches of each crate's labeled files, which
+  /// `tune` then reruns across all crates.
+  #[clap(hide = true)]
+  TuneSamples {
+    labels: PathBuf,
+  },
 }
 
 trait ArgusAnalysis: Sized + Send + Sync {
@@ -66,11 +103,34 @@ trait ArgusAnalysis: Sized + Send + Sync {
     tcx: TyCtxt,
     id: BodyId,
   ) -> anyhow::Result<Self::Output>;
+
+  /// Analyze the obligations of an item outside of its body, `None` if it
+  /// has nothing to report.
+  fn analyze_item(
+    &mut self,
+    tcx: TyCtxt,
+    id: LocalDefId,
+  ) -> anyhow::Result<Option<Self::Output>>;
 }
 
-impl<O, F> ArgusAnalysis for F
+/// What an analysis runs on, a body or the signature of an item.
+#[derive(Debug, Clone, Copy)]
+enum Owner {
+  Body(BodyId),
+  Item(LocalDefId),
+}
+
+/// An analysis of bodies, with its counterpart for items.
+struct Analysis<B, I> {
+  body: B,
+  item: I,
+}
+
+impl<O, B, I> ArgusAnalysis for Analysis<B, I>
 where
-  for<'tcx> F: Fn(TyCtxt<'tcx>, BodyId) -> anyhow::Result<O> + Send + Sync,
+  for<'tcx> B: Fn(TyCtxt<'tcx>, BodyId) -> anyhow::Result<O> + Send + Sync,
+  for<'tcx> I:
+    Fn(TyCtxt<'tcx>, LocalDefId) -> anyhow::Result<Option<O>> + Send + Sync,
   O: Serialize + Send + Sync,
 {
   type Output = O;
@@ -79,7 +139,15 @@ where
     tcx: TyCtxt,
     id: BodyId,
   ) -> anyhow::Result<Self::Output> {
-    (self)(tcx, id)
+    (self.body)(tcx, id)
+  }
+
+  fn analyze_item(
+    &mut self,
+    tcx: TyCtxt,
+    id: LocalDefId,
+  ) -> anyhow::Result<Option<Self::Output>> {
+    (self.item)(tcx, id)
   }
 }
 
@@ -115,11 +183,11 @@ impl RustcPlugin for ArgusPlugin {
 
   fn args(&self, target_dir: &Utf8Path) -> RustcPluginArgs<ArgusPluginArgs> {
     use ArgusCommand as AC;
-    let args = ArgusPluginArgs::parse_from(env::args().skip(1));
+    let mut args = ArgusPluginArgs::parse_from(env::args().skip(1));
     let cargo_path =
       env::var("CARGO_PATH").unwrap_or_else(|_| "cargo".to_string());
 
-    match &args.command {
+    match &mut args.command {
       AC::Preload => {
         let mut cmd = Command::new(cargo_path);
         // NOTE: this command must share certain parameters with rustc_plugin so Cargo will not recompute
@@ -136,14 +204,60 @@ impl RustcPlugin for ArgusPlugin {
         println!("{commit_hash}");
         exit(0);
       }
-      AC::Obligations { .. } | AC::Tree { .. } | AC::Bundle => {}
+      // The driver doesn't run in the current directory.
+      AC::Tune { labels } => match labels.canonicalize() {
+        Ok(path) => *labels = path,
+        Err(e) => ArgusPluginArgs::command()
+          .error(
+            ErrorKind::Io,
+            format!("could not find {}: {e}", labels.display()),
+          )
+          .exit(),
+      },
+      AC::Obligations { .. }
+      | AC::Tree { .. }
+      | AC::Bundle
+      | AC::TuneSamples { .. } => {}
+    }
+
+    if matches!(args.format, OutputFormat::Msgpack) && args.out_file.is_none() {
+      ArgusPluginArgs::command()
+        .error(
+          ErrorKind::MissingRequiredArgument,
+          "`--format msgpack` requires `--out-file`",
+        )
+        .exit();
+    }
+
+    // Drivers of each crate append to the file, and don't run in the
+    // current directory.
+    if let Some(out_file) = &mut args.out_file {
+      match File::create(&*out_file).and_then(|_| out_file.canonicalize()) {
+        Ok(path) => *out_file = path,
+        Err(e) => ArgusPluginArgs::command()
+          .error(
+            ErrorKind::Io,
+            format!("could not create {}: {e}", out_file.display()),
+          )
+          .exit(),
+      }
+    }
+
+    if let AC::Tune { labels } = &args.command {
+      let samples = target_dir.join("argus-tune-samples.json");
+      let report = collect_samples(labels, samples.as_std_path(), &args)
+        .map(|samples| tune(&samples));
+      match postprocess(report, &args) {
+        Ok(()) => exit(0),
+        Err(_) => exit(1),
+      }
     }
 
     let file = match &args.command {
       AC::Tree { file, .. } => Some(file),
       AC::Obligations { file } => file.as_ref(),
-      AC::Bundle => None,
-      AC::Preload | AC::RustcVersion => unreachable!(),
+      AC::Bundle | AC::TuneSamples { .. } => None,
+      AC::Preload | AC::RustcVersion | AC::Tune { .. } => unreachable!(),
     };
 
     let filter = file.map_or(CrateFilter::OnlyWorkspace, |file| {
@@ -184,40 +298,144 @@ impl RustcPlugin for ArgusPlugin {
         };
 
         let v = run(
-          analysis::tree,
+          Analysis {
+            body: analysis::tree,
+            item: analysis::item_tree,
+          },
           Some(PathBuf::from(&file)),
           compute_target,
           &plugin_args,
           &compiler_args,
         );
-        postprocess(v)
+        postprocess(v, &plugin_args)
       }
       AC::Obligations { file, .. } => {
         let v = run(
-          analysis::obligations,
+          Analysis {
+            body: analysis::obligations,
+            item: analysis::item_obligations,
+          },
           file.as_ref().map(PathBuf::from),
           no_target,
           &plugin_args,
           &compiler_args,
         );
-        postprocess(v)
+        postprocess(v, &plugin_args)
       }
       AC::Bundle => {
         log::warn!("Bundling takes an enormous amount of time.");
         let v = run(
-          analysis::bundle,
+          Analysis {
+            body: analysis::bundle,
+            item: analysis::item_bundle,
+          },
           None,
           no_target,
           &plugin_args,
           &compiler_args,
         );
-        postprocess(v)
+        postprocess(v, &plugin_args)
       }
-      AC::Preload | AC::RustcVersion => unreachable!(),
+      AC::TuneSamples { labels } => {
+        let v = run(
+          Analysis {
+            body: analysis::bundle,
+            item: analysis::item_bundle,
+          },
+          None,
+          no_target,
+          &plugin_args,
+          &compiler_args,
+        )
+        .and_then(|bundles| samples(&bundles, labels));
+        postprocess(v, &plugin_args)
+      }
+      AC::Preload | AC::RustcVersion | AC::Tune { .. } => unreachable!(),
     }
   }
 }
 
+#[derive(Serialize)]
+struct Evaluation {
+  weights: Weights,
+  metrics: RankMetrics,
+}
+
+#[derive(Serialize)]
+struct TuneReport {
+  configured: Evaluation,
+  tuned: Evaluation,
+}
+
+/// The labeled samples of one crate.
+fn samples(bundles: &[BodyBundle], labels: &Path) -> ArgusResult<Vec<Sample>> {
+  let labels = std::fs::read_to_string(labels)
+    .map_err(anyhow::Error::from)
+    .and_then(|s| Ok(serde_json::from_str::<Vec<Label>>(&s)?))
+    .map_err(|e| ArgusError::AnalysisError {
+      error: format!("invalid labels: {e:?}"),
+    })?;
+
+  Ok(Sample::collect(bundles, &labels))
+}
+
+/// Run the drivers for `tune-samples`, each crate appends its samples to
+/// `out_file`, and merge them.
+fn collect_samples(
+  labels: &Path,
+  out_file: &Path,
+  args: &ArgusPluginArgs,
+) -> ArgusResult<Vec<Sample>> {
+  let analysis_error = |error: String| ArgusError::AnalysisError { error };
+
+  if let Some(dir) = out_file.parent() {
+    std::fs::create_dir_all(dir).map_err(|e| analysis_error(e.to_string()))?;
+  }
+
+  let mut cmd = Command::new(
+    env::current_exe().map_err(|e| analysis_error(e.to_string()))?,
+  );
+  cmd.arg("argus").arg("--out-file").arg(out_file);
+  if args.show_stderr {
+    cmd.arg("--show-stderr");
+  }
+  cmd.arg("tune-samples").arg(labels);
+
+  let status = cmd.status().map_err(|e| analysis_error(e.to_string()))?;
+  if !status.success() {
+    return Err(analysis_error(format!(
+      "collecting samples failed: {status}"
+    )));
+  }
+
+  let file = File::open(out_file).map_err(|e| analysis_error(e.to_string()))?;
+  let crates = serde_json::Deserializer::from_reader(BufReader::new(file))
+    .into_iter::<Result<Vec<Sample>, serde_json::Value>>()
+    .map(|samples| match samples {
+      Ok(Ok(samples)) => Ok(samples),
+      Ok(Err(error)) => Err(analysis_error(error.to_string())),
+      Err(e) => Err(analysis_error(format!("invalid samples: {e}"))),
+    })
+    .collect::<ArgusResult<Vec<_>>>()?;
+
+  Ok(Sample::merge(crates))
+}
+
+fn tune(samples: &[Sample]) -> TuneReport {
+  let configured = Weights::configured();
+  let tuned = configured.tune(samples);
+  TuneReport {
+    configured: Evaluation {
+      weights: configured,
+      metrics: configured.evaluate(samples),
+    },
+    tuned: Evaluation {
+      weights: tuned,
+      metrics: tuned.evaluate(samples),
+    },
+  }
+}
+
 #[allow(clippy::unnecessary_wraps)]
 fn run<A: ArgusAnalysis, T: ToTarget>(
   analysis: A,
@@ -266,9 +484,44 @@ pub fn run_with_callbacks(
   Ok(())
 }
 
-#[allow(clippy::unnecessary_wraps)]
-fn postprocess<T: Serialize>(result: T) -> RustcResult<()> {
-  serde_json::to_writer(io::stdout(), &result).unwrap();
+fn postprocess<T: Serialize>(
+  result: T,
+  plugin_args: &ArgusPluginArgs,
+) -> RustcResult<()> {
+  let write = || -> anyhow::Result<()> {
+    let mut out: Box<dyn Write> = match &plugin_args.out_file {
+      Some(path) => {
+        Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+      }
+      None => Box::new(io::stdout()),
+    };
+    encode(&result, plugin_args.format, &mut out)?;
+    out.flush()?;
+    Ok(())
+  };
+
+  write().map_err(|e| {
+    EarlyDiagCtxt::new(ErrorOutputType::default())
+      .early_err(format!("could not write the results: {e}"))
+  })
+}
+
+/// Write `result` to `out` in the requested format.
+fn encode<T: Serialize>(
+  result: &T,
+  format: OutputFormat,
+  out: impl Write,
+) -> anyhow::Result<()> {
+  match format {
+    OutputFormat::Json => serde_json::to_writer(out, result)?,
+    OutputFormat::Msgpack => {
+      // Human-readable so that, e.g., UUIDs are strings just as in JSON.
+      let mut ser = rmp_serde::Serializer::new(out)
+        .with_struct_map()
+        .with_human_readable();
+      result.serialize(&mut ser)?;
+    }
+  }
   Ok(())
 }
 
@@ -297,16 +550,25 @@ impl<A: ArgusAnalysis, T: ToTarget, F: FnOnce() -> Option<T>>
     let mut analysis = self.analysis.take().unwrap();
     let target_file = self.file.as_ref();
 
-    let mut inner = |(_, body)| {
-      if let FileName::Real(RealFileName::LocalPath(p)) =
-        tcx.body_filename(body)
-      {
+    let inner = |owner: Owner| {
+      let filename = match owner {
+        Owner::Body(body) => tcx.body_filename(body),
+        Owner::Item(item) => {
+          tcx.sess.source_map().span_to_filename(tcx.def_span(item))
+        }
+      };
+
+      if let FileName::Real(RealFileName::LocalPath(p)) = filename {
         if target_file.is_none_or(|f| f.ends_with(&p)) {
-          log::info!("analyzing {body:?}");
-          match analysis.analyze(tcx, body) {
-            Ok(v) => Some(v),
+          log::info!("analyzing {owner:?}");
+          let result = match owner {
+            Owner::Body(body) => analysis.analyze(tcx, body).map(Some),
+            Owner::Item(item) => analysis.analyze_item(tcx, item),
+          };
+          match result {
+            Ok(v) => v,
             Err(e) => {
-              log::error!("Error analyzing body {body:?} {e:?}");
+              log::error!("Error analyzing {owner:?} {e:?}");
               None
             }
           }
@@ -330,11 +592,19 @@ impl<A: ArgusAnalysis, T: ToTarget, F: FnOnce() -> Option<T>>
         fluid_set!(analysis::OBLIGATION_TARGET, target);
 
         find_enclosing_bodies(tcx, body_span)
-          .filter_map(|b| inner((body_span, b)))
+          .map(Owner::Body)
+          .chain(find_enclosing_items(tcx, body_span).map(Owner::Item))
+          .filter_map(inner)
           .collect::<Vec<_>>()
       }
       None => find_bodies(tcx)
         .into_iter()
+        .map(|(_, body)| Owner::Body(body))
+        .chain(
+          find_items(tcx)
+            .into_iter()
+            .map(|(_, item)| Owner::Item(item)),
+        )
         .filter_map(inner)
         .collect::<Vec<_>>(),
     };
@@ -342,3 +612,32 @@ impl<A: ArgusAnalysis, T: ToTarget, F: FnOnce() -> Option<T>>
     rustc_driver::Compilation::Stop
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The document `format` encodes `result` as, decoded into JSON.
+  fn round_trip<T: Serialize>(
+    result: &T,
+    format: OutputFormat,
+  ) -> serde_json::Value {
+    let mut bytes = vec![];
+    encode(result, format, &mut bytes).unwrap();
+    match format {
+      OutputFormat::Json => serde_json::from_slice(&bytes).unwrap(),
+      OutputFormat::Msgpack => rmp_serde::from_slice(&bytes).unwrap(),
+    }
+  }
+
+  #[test]
+  fn msgpack_matches_json() {
+    let results: [ArgusResult<TuneReport>; 2] =
+      [Ok(tune(&[])), Err(ArgusError::BuildError { range: None })];
+    for result in results {
+      let json = serde_json::to_value(&result).unwrap();
+      assert_eq!(round_trip(&result, OutputFormat::Json), json);
+      assert_eq!(round_trip(&result, OutputFormat::Msgpack), json);
+    }
+  }
+}
@@ -70,8 +70,17 @@ impl<'tcx> TyExt<'tcx> for Ty<'tcx> {
     match self.kind() {
       ty::TyKind::Ref(_, ty, _) | ty::TyKind::RawPtr(ty, ..) => ty.is_local(),
 
+      // Fundamental types, e.g. `Box<T>`, are local when what they wrap is.
+      ty::TyKind::Adt(def, args) if def.is_fundamental() => {
+        args.types().any(|ty| ty.is_local())
+      }
+
       ty::TyKind::Adt(def, ..) => def.did().is_local(),
 
+      ty::TyKind::Dynamic(preds, ..) => {
+        preds.principal_def_id().is_some_and(DefId::is_local)
+      }
+
       ty::TyKind::Foreign(def_id)
       | ty::TyKind::FnDef(def_id, ..)
       | ty::TyKind::Closure(def_id, ..)
@@ -89,7 +98,6 @@ impl<'tcx> TyExt<'tcx> for Ty<'tcx> {
       | ty::TyKind::FnPtr(..)
       | ty::TyKind::Array(..)
       | ty::TyKind::Slice(..)
-      | ty::TyKind::Dynamic(..)
       | ty::TyKind::Never
       | ty::TyKind::Alias(..)
       | ty::TyKind::Param(..)
@@ -16,7 +16,7 @@ use ts_rs::TS;
 use super::*;
 use crate::ty as myty;
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, Clone)]
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
 /// A `DefLocation` definition equivalent to that provided by VSCode's LSP.
@@ -27,9 +27,13 @@ pub struct DefLocation {
 
 impl DefLocation {
   pub fn from_def_id_tcx(def_id: DefId, tcx: ty::TyCtxt) -> Option<Self> {
+    Self::from_span(tcx.def_span(def_id), tcx)
+  }
+
+  /// Location of an arbitrary span, only spans in local files have a location.
+  pub fn from_span(span: rustc_span::Span, tcx: ty::TyCtxt) -> Option<Self> {
     use rustc_span::{FileName, RealFileName};
 
-    let span = tcx.def_span(def_id);
     let source_map = tcx.sess.source_map();
     let r = CharRange::from_span(span, source_map).ok()?;
     let f = match &source_map.lookup_source_file(span.lo()).name {
@@ -97,6 +101,23 @@ pub struct GroupedClauses<'tcx> {
   pub other: Vec<ty::Clause<'tcx>>,
 }
 
+impl<'tcx> GroupedClauses<'tcx> {
+  /// Group a single clause, e.g., one picked out of a `ParamEnv`.
+  ///
+  /// Projections are left ungrouped, an `FnOnce::Output` projection
+  /// can't be folded into its trait bound without the bound present.
+  pub fn from_clause(tcx: ty::TyCtxt<'tcx>, clause: ty::Clause<'tcx>) -> Self {
+    if clause.as_projection_clause().is_some() {
+      return GroupedClauses {
+        grouped: vec![],
+        other: vec![clause],
+      };
+    }
+
+    group_predicates_by_ty(tcx, [clause])
+  }
+}
+
 // FIXME this definition *SHOULD* get generated by the `Poly` macro, but
 // the `Poly` and `Many` macros do not compose (yet).
 pub struct PolyClauseWithBoundsDefs;
@@ -88,6 +88,17 @@ pub fn to_value<'a, 'tcx: 'a, T: Serialize + 'a>(
   })
 }
 
+/// Intern a type without serializing it, its value is stored in the interner.
+pub fn intern_ty<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  ty_interner: &TyInterner<'tcx>,
+  value: rustc_middle::ty::Ty<'tcx>,
+) -> interner::TyIdx {
+  TyInterner::invoke_in(ty_interner, || {
+    InferCtxt::invoke_in(infcx, || crate::ty::TyDef::intern(&value))
+  })
+}
+
 trait InferCtxtSerializeExt {
   fn should_print_verbose(&self) -> bool;
 }
@@ -79,6 +79,12 @@ impl TyDef<'_> {
   where
     S: serde::Serializer,
   {
+    Self::new(Self::intern(value)).serialize(s)
+  }
+
+  /// NOTE: this must be called within the dynamic context of
+  /// the `argus-ser` crate, use `crate::intern_ty` instead.
+  pub(crate) fn intern(value: &ty::Ty) -> TyIdx {
     let ty_idx;
     if let Some(tyidx) =
       TyInterner::access(|interner| interner.borrow().get_idx(value))
@@ -99,7 +105,7 @@ impl TyDef<'_> {
         interner.borrow_mut().insert(*value, ty_val)
       });
     }
-    Self::new(ty_idx).serialize(s)
+    ty_idx
   }
 }
 
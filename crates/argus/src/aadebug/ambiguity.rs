@@ -0,0 +1,406 @@
+//! Explanations for ambiguous goals.
+//!
+//! A goal is ambiguous when inference variables leave more than one way to
+//! prove it. The variables are traced back to what introduced them in the
+//! body, and fixed with a turbofish or type annotation where possible.
+
+use argus_ext::ty::EvaluationResultExt;
+use rustc_hir::{
+  self as hir,
+  def::DefKind,
+  def_id::DefId,
+  intravisit::{self, Visitor},
+};
+use rustc_infer::{infer::InferCtxt, traits::ObligationCause};
+use rustc_middle::ty::{
+  self, print::ForceTrimmedGuard, TyCtxt, TypeSuperVisitable, TypeVisitable,
+  TypeVisitableExt, TypeVisitor,
+};
+use rustc_span::Span;
+use rustc_trait_selection::traits::ObligationCtxt;
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+#[cfg(feature = "testing")]
+use ts_rs::TS;
+
+use super::{
+  fixes::{naming_applicability, Applicability, Fix, FixKind, SourceEdit},
+  tree::{Goal, I, T},
+};
+use crate::proof_tree::CandidateIdx;
+
+/// Goals like `_: Debug` can have hundreds of applicable impls, past this
+/// many listing them doesn't help pick a type.
+const MAX_CANDIDATES: usize = 32;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct Ambiguity {
+  /// Inference variables preventing the root goal from making progress.
+  pub vars: Vec<UnconstrainedVar>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct UnconstrainedVar {
+  /// Name of the generic parameter the variable was instantiated for.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub param: Option<String>,
+
+  /// Path of the item declaring `param`, e.g. `Iterator::collect`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub param_owner: Option<String>,
+
+  /// Expression, or pattern, that introduced the variable.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "CharRange | undefined"))]
+  pub introduced_at: Option<CharRange>,
+
+  /// Ambiguous goals where the variable is a main component.
+  #[cfg_attr(feature = "testing", ts(type = "ProofNodeIdx[]"))]
+  pub goals: Vec<I>,
+
+  /// Impls that could still apply to the first of `goals`, at most
+  /// `MAX_CANDIDATES` of them are included.
+  #[cfg_attr(feature = "testing", ts(type = "CandidateIdx[]"))]
+  pub candidates: Vec<CandidateIdx>,
+
+  /// Number of impls that could still apply, including those omitted.
+  pub num_candidates: usize,
+
+  /// Edit pinning the variable to a concrete type.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "Fix | undefined"))]
+  pub suggestion: Option<Fix>,
+}
+
+/// Explain the ambiguity of the tree's root goal, `None` if the root
+/// isn't ambiguous or has no inference variables.
+pub(super) fn explain<'tcx>(
+  tree: &T<'_, 'tcx>,
+  mut intern_impl: impl FnMut(&InferCtxt<'tcx>, DefId) -> CandidateIdx,
+) -> Option<Ambiguity> {
+  let root = tree.goal(tree.root)?;
+  if !root.result().is_maybe() {
+    return None;
+  }
+
+  let infcx = root.infcx();
+  let predicate = infcx.resolve_vars_if_possible(root.predicate());
+
+  // Prefer the variable the solver is stuck on, e.g. `_: FromIterator<u32>`,
+  // otherwise any variable in the root could be blocking progress.
+  let mut vids = vec![];
+  if let Some(vid) = main_var(predicate) {
+    vids.push(infcx.root_var(vid));
+  } else {
+    let mut visitor = TyVarCollector(vec![]);
+    predicate.visit_with(&mut visitor);
+    for vid in visitor.0 {
+      let vid = infcx.root_var(vid);
+      if !vids.contains(&vid) {
+        vids.push(vid);
+      }
+    }
+  }
+
+  if vids.is_empty() {
+    return None;
+  }
+
+  // The topology is hash-based, nodes are sorted by index to list goals in
+  // the order the solver visited them.
+  let mut nodes = tree
+    .topology
+    .iter()
+    .filter(|&idx| idx != tree.root)
+    .collect::<Vec<_>>();
+  nodes.sort_unstable();
+  let ambiguous_goals = std::iter::once(tree.root)
+    .chain(nodes)
+    .filter_map(|idx| tree.goal(idx))
+    .filter(|goal| goal.result().is_maybe())
+    .collect::<Vec<_>>();
+
+  let vars = vids
+    .into_iter()
+    .map(|vid| {
+      let goals = ambiguous_goals
+        .iter()
+        .filter(|goal| {
+          // Nested goals may have unified the variable with a newer one,
+          // both are related in the goal's own context.
+          let infcx = goal.infcx();
+          main_var(infcx.resolve_vars_if_possible(goal.predicate()))
+            .is_some_and(|v| infcx.root_var(v) == infcx.root_var(vid))
+        })
+        .collect::<Vec<_>>();
+
+      let candidates = goals
+        .first()
+        .map(|goal| could_apply(goal))
+        .unwrap_or_default();
+      let suggestion = goals
+        .first()
+        .and_then(|goal| pin_var(tree.body_owner(), goal, vid, &candidates));
+
+      let num_candidates = candidates.len();
+      let candidates = candidates
+        .into_iter()
+        .take(MAX_CANDIDATES)
+        .map(|impl_| intern_impl(infcx, impl_))
+        .collect();
+
+      UnconstrainedVar {
+        goals: goals.iter().map(|goal| goal.idx()).collect(),
+        candidates,
+        num_candidates,
+        suggestion,
+        ..origin_of(infcx, vid)
+      }
+    })
+    .collect();
+
+  Some(Ambiguity { vars })
+}
+
+/// Describe where the variable came from, other fields are left empty.
+fn origin_of(infcx: &InferCtxt, vid: ty::TyVid) -> UnconstrainedVar {
+  let tcx = infcx.tcx;
+  let origin = infcx.type_var_origin(vid);
+  UnconstrainedVar {
+    param: origin.param_def_id.map(|p| tcx.item_name(p).to_string()),
+    param_owner: origin.param_def_id.map(|p| tcx.def_path_str(tcx.parent(p))),
+    introduced_at: (!origin.span.is_dummy())
+      .then(|| CharRange::from_span(origin.span, tcx.sess.source_map()).ok())
+      .flatten(),
+    goals: vec![],
+    candidates: vec![],
+    num_candidates: 0,
+    suggestion: None,
+  }
+}
+
+struct TyVarCollector(Vec<ty::TyVid>);
+
+impl<'tcx> TypeVisitor<TyCtxt<'tcx>> for TyVarCollector {
+  fn visit_ty(&mut self, ty: ty::Ty<'tcx>) {
+    if let ty::Infer(ty::TyVar(vid)) = ty.kind() {
+      self.0.push(*vid);
+    }
+
+    ty.super_visit_with(self);
+  }
+}
+
+/// The type variable in the main position of a predicate, the self type
+/// of a trait clause or either side of a projection.
+fn main_var(predicate: ty::Predicate) -> Option<ty::TyVid> {
+  let ty = match predicate.kind().skip_binder() {
+    ty::PredicateKind::Clause(ty::ClauseKind::Trait(t)) => t.self_ty(),
+    ty::PredicateKind::Clause(ty::ClauseKind::Projection(p)) => {
+      match p.term.as_type() {
+        Some(ty) if ty.is_ty_var() => ty,
+        _ => p.self_ty(),
+      }
+    }
+    _ => return None,
+  };
+
+  ty.ty_vid()
+}
+
+/// Impls of the goal's trait whose header unifies with the goal.
+fn could_apply(goal: &Goal) -> Vec<DefId> {
+  let infcx = goal.infcx();
+  let tcx = infcx.tcx;
+  let Some(t) = goal.predicate().as_trait_clause() else {
+    return vec![];
+  };
+
+  let t = tcx.instantiate_bound_regions_with_erased(t);
+  let t = infcx.resolve_vars_if_possible(t);
+  let t = ty::fold::fold_regions(tcx, t, |_, _| tcx.lifetimes.re_static);
+
+  tcx
+    .all_impls(t.def_id())
+    .filter(|&impl_def_id| {
+      let Some(header) = tcx.impl_trait_header(impl_def_id) else {
+        return false;
+      };
+
+      header.polarity == ty::ImplPolarity::Positive
+        && tcx.is_user_visible_dep(impl_def_id.krate)
+        && infcx.probe(|_| {
+          let args =
+            infcx.fresh_args_for_item(rustc_span::DUMMY_SP, impl_def_id);
+          let impl_trait_ref = header.trait_ref.instantiate(tcx, args);
+
+          // Aliases in the goal are related lazily, the nested goals
+          // need to be evaluated to rule out an impl.
+          let ocx = ObligationCtxt::new(infcx);
+          ocx
+            .eq(
+              &ObligationCause::dummy(),
+              goal.param_env(),
+              t.trait_ref,
+              impl_trait_ref,
+            )
+            .is_ok()
+            && ocx.select_where_possible().is_empty()
+        })
+    })
+    .collect()
+}
+
+/// A turbofish or type annotation fixing the variable where it was
+/// introduced. The type is only filled in when a single impl could apply.
+fn pin_var(
+  body_owner: DefId,
+  goal: &Goal,
+  vid: ty::TyVid,
+  candidates: &[DefId],
+) -> Option<Fix> {
+  let infcx = goal.infcx();
+  let tcx = infcx.tcx;
+  let origin = infcx.type_var_origin(vid);
+  if origin.span.is_dummy() {
+    return None;
+  }
+
+  let ty = match candidates {
+    [impl_def_id] => {
+      let _guard = ForceTrimmedGuard::new();
+      infcx.probe(|_| {
+        let args =
+          infcx.fresh_args_for_item(rustc_span::DUMMY_SP, *impl_def_id);
+        let self_ty = tcx
+          .impl_trait_ref(*impl_def_id)?
+          .instantiate(tcx, args)
+          .self_ty();
+        let self_ty = tcx.erase_regions(self_ty);
+        (!self_ty.has_escaping_bound_vars()).then(|| {
+          let applicability =
+            naming_applicability(tcx, body_owner, None, [self_ty.into()]);
+          (self_ty.to_string(), applicability)
+        })
+      })
+    }
+    _ => None,
+  };
+  let (ty, applicability) = ty.unwrap_or_else(|| {
+    ("/* Type */".to_string(), Applicability::HasPlaceholders)
+  });
+
+  let body = tcx.hir_maybe_body_owned_by(body_owner.as_local()?)?;
+  let mut finder = SiteFinder {
+    span: origin.span,
+    site: None,
+  };
+  finder.visit_body(body);
+
+  let (kind, span, replacement) = match finder.site? {
+    Site::Segment(segment) => {
+      let owner = tcx.parent(origin.param_def_id?);
+      if !matches!(tcx.def_kind(owner), DefKind::Fn | DefKind::AssocFn) {
+        return None;
+      }
+
+      // Generic arguments are positional, other parameters are inferred.
+      // A turbofish isn't allowed with `impl Trait` arguments, and const
+      // arguments can't be inferred with `_`.
+      let mut args = vec![];
+      for param in &tcx.generics_of(owner).own_params {
+        match param.kind {
+          ty::GenericParamDefKind::Lifetime => {}
+          ty::GenericParamDefKind::Type {
+            synthetic: false, ..
+          } if Some(param.def_id) == origin.param_def_id => {
+            args.push(ty.clone());
+          }
+          ty::GenericParamDefKind::Type {
+            synthetic: false, ..
+          } => args.push("_".to_string()),
+          _ => return None,
+        }
+      }
+
+      (
+        FixKind::Turbofish,
+        segment.shrink_to_hi(),
+        format!("::<{}>", args.join(", ")),
+      )
+    }
+    Site::Let(pat) => (
+      FixKind::TypeAnnotation,
+      pat.shrink_to_hi(),
+      format!(": {ty}"),
+    ),
+  };
+
+  let edit = SourceEdit::new(tcx, span, replacement)?;
+  Some(Fix {
+    kind,
+    applicability,
+    edits: vec![edit],
+  })
+}
+
+enum Site {
+  /// The last path segment, without generic arguments, of a call.
+  Segment(Span),
+  /// A let binding without a type annotation.
+  Let(Span),
+}
+
+/// Find where a turbofish or type annotation could be placed for
+/// a variable introduced at `span`.
+struct SiteFinder {
+  span: Span,
+  site: Option<Site>,
+}
+
+impl<'tcx> Visitor<'tcx> for SiteFinder {
+  fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+    if self.site.is_some() {
+      return;
+    }
+
+    match expr.kind {
+      hir::ExprKind::MethodCall(segment, ..)
+        if segment.args.is_none() && segment.ident.span == self.span =>
+      {
+        self.site = Some(Site::Segment(segment.ident.span));
+      }
+
+      hir::ExprKind::Path(hir::QPath::Resolved(_, path))
+        if expr.span == self.span =>
+      {
+        if let Some(segment) = path.segments.last() {
+          if segment.args.is_none() {
+            self.site = Some(Site::Segment(segment.ident.span));
+          }
+        }
+      }
+
+      _ => {}
+    }
+
+    intravisit::walk_expr(self, expr);
+  }
+
+  fn visit_local(&mut self, local: &'tcx hir::LetStmt<'tcx>) {
+    if self.site.is_none() && local.ty.is_none() && local.pat.span == self.span
+    {
+      self.site = Some(Site::Let(local.pat.span));
+    }
+
+    intravisit::walk_local(self, local);
+  }
+}
@@ -0,0 +1,201 @@
+//! How the signature of a closure failing an `Fn*` bound was inferred.
+//!
+//! A closure's parameter types come from annotations, from the signature
+//! expected where the closure is passed, or from its body. Integer and
+//! float variables left unconstrained by all of these fall back to their
+//! defaults. The inferred signature is compared with the one the bound
+//! expects, parameter by parameter.
+
+use argus_ser as ser;
+use rustc_hir::{self as hir, def_id::DefId};
+use rustc_infer::{
+  infer::{DefineOpaqueTypes, InferCtxt},
+  traits::ObligationCause,
+};
+use rustc_middle::ty::{self, Ty, TypeVisitableExt};
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+use serde_json as json;
+#[cfg(feature = "testing")]
+use ts_rs::TS;
+
+use super::tree::function_param_spans;
+use crate::tls;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct ClosureSignature {
+  /// The closure's signature as inferred, a function pointer type, e.g.,
+  /// `fn(u32) -> bool`.
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub inferred: json::Value,
+
+  /// The signature expected by the `Fn*` bound, if the tree contains it.
+  /// The output is `()` if the bound doesn't constrain it.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "Ty | undefined"))]
+  pub expected: Option<json::Value>,
+
+  pub params: Vec<ClosureParam>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct ClosureParam {
+  pub position: usize,
+
+  /// Range of the parameter in the closure, if it's local.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "CharRange | undefined"))]
+  pub range: Option<CharRange>,
+
+  pub source: SignatureSource,
+
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub inferred: json::Value,
+
+  /// The type expected at this position, missing for extra parameters.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "Ty | undefined"))]
+  pub expected: Option<json::Value>,
+
+  /// Whether the inferred type unifies with the expected one.
+  pub matches: bool,
+}
+
+/// Where a closure parameter's type came from.
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum SignatureSource {
+  /// The parameter has a type annotation.
+  Annotated,
+  /// The type matches the expected one, so it was likely deduced from the
+  /// bound. This is a guess, a type inferred from the body that happens to
+  /// match is reported the same way.
+  Expected,
+  /// The type was inferred from the uses of the parameter in the body.
+  Body,
+  /// The type is unconstrained, or an integer or float variable which
+  /// falls back to its default.
+  Default,
+}
+
+/// The signature a `Fn*` bound expects of a closure.
+///
+/// Bounds come from other goals of the tree, whose inference contexts
+/// differ, so the types are resolved and free of inference variables.
+pub(super) struct ExpectedSig<'tcx> {
+  pub inputs: Vec<Ty<'tcx>>,
+  pub output: Option<Ty<'tcx>>,
+}
+
+/// Trace the signature of `closure` against the `expected` one.
+pub(super) fn trace<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  closure: Ty<'tcx>,
+  expected: Option<&ExpectedSig<'tcx>>,
+) -> Option<ClosureSignature> {
+  let tcx = infcx.tcx;
+  let ty::Closure(def_id, args) = closure.kind() else {
+    return None;
+  };
+
+  // Closure signatures take their parameters as a tuple.
+  let sig = tcx.signature_unclosure(args.as_closure().sig(), hir::Safety::Safe);
+  let sig = infcx.resolve_vars_if_possible(sig);
+  let sig = tcx.instantiate_bound_regions_with_erased(sig);
+
+  // Regions don't affect whether a parameter matches, they're replaced
+  // so the types can be related without introducing constraints.
+  let matches = |inferred: Ty<'tcx>, expected: Ty<'tcx>| {
+    let erase =
+      |ty| ty::fold::fold_regions(tcx, ty, |_, _| tcx.lifetimes.re_static);
+    infcx.probe(|_| {
+      infcx
+        .at(&ObligationCause::dummy(), param_env)
+        .eq(DefineOpaqueTypes::No, erase(inferred), erase(expected))
+        .is_ok()
+    })
+  };
+
+  let annotated = annotated_params(tcx, *def_id);
+  let ranges = function_param_spans(tcx, closure);
+  let source_map = tcx.sess.source_map();
+
+  let params = sig
+    .inputs()
+    .iter()
+    .enumerate()
+    .map(|(position, &inferred)| {
+      let expected_ty =
+        expected.and_then(|expected| expected.inputs.get(position).copied());
+      let matches = expected_ty.is_some_and(|e| matches(inferred, e));
+      let source = if annotated.get(position).copied().unwrap_or(false) {
+        SignatureSource::Annotated
+      } else if inferred.has_non_region_infer() {
+        SignatureSource::Default
+      } else if matches {
+        SignatureSource::Expected
+      } else {
+        SignatureSource::Body
+      };
+
+      ClosureParam {
+        position,
+        range: ranges
+          .get(position)
+          .and_then(|&span| CharRange::from_span(span, source_map).ok()),
+        source,
+        inferred: to_value(infcx, inferred),
+        expected: expected_ty.map(|ty| to_value(infcx, ty)),
+        matches,
+      }
+    })
+    .collect();
+
+  Some(ClosureSignature {
+    inferred: to_value(infcx, Ty::new_fn_ptr(tcx, ty::Binder::dummy(sig))),
+    expected: expected.map(|expected| {
+      let sig = tcx.mk_fn_sig(
+        expected.inputs.iter().copied(),
+        expected.output.unwrap_or(tcx.types.unit),
+        sig.c_variadic,
+        sig.safety,
+        sig.abi,
+      );
+      to_value(infcx, Ty::new_fn_ptr(tcx, ty::Binder::dummy(sig)))
+    }),
+    params,
+  })
+}
+
+/// Whether each parameter of the local closure has a type annotation.
+fn annotated_params(tcx: ty::TyCtxt, def_id: DefId) -> Vec<bool> {
+  def_id
+    .as_local()
+    .and_then(|local_id| tcx.hir_node_by_def_id(local_id).fn_decl())
+    .map(|decl| {
+      decl
+        .inputs
+        .iter()
+        .map(|ty| !matches!(ty.kind, hir::TyKind::Infer(..)))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn to_value<'tcx>(infcx: &InferCtxt<'tcx>, ty: Ty<'tcx>) -> json::Value {
+  #[derive(Serialize)]
+  struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+  tls::unsafe_access_interner(|ty_interner| {
+    ser::to_value_expect(infcx, ty_interner, &TyWrapper(ty))
+  })
+}
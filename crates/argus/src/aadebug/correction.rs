@@ -0,0 +1,449 @@
+//! Best-first enumeration of minimal correction sets.
+//!
+//! A failed goal is fixed by fixing *any* of its failed candidates, and a
+//! candidate is fixed by fixing *all* of its failed subgoals. Expanding this
+//! AND-OR tree into disjunctive normal form grows exponentially with the
+//! branching of failed candidates, instead each node lazily produces its
+//! correction sets in order of increasing cost. Only as many sets as
+//! requested by the root are ever materialized.
+
+use std::{
+  cmp::Reverse,
+  collections::{BinaryHeap, HashMap, HashSet},
+  rc::Rc,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Index of a node's stream in the `Search`.
+pub type StreamIdx = usize;
+
+/// A sorted set of goals that, when all fixed, fix the root goal.
+pub type Set<I> = Rc<[I]>;
+
+#[derive(Clone)]
+struct Item<I> {
+  cost: usize,
+  set: Set<I>,
+}
+
+enum Kind<I> {
+  /// A failed goal without failed candidates, it must be fixed directly.
+  Leaf(I, usize),
+  /// Any of the streams fix the node.
+  Or(Vec<StreamIdx>),
+  /// All of the streams must be fixed to fix the node.
+  And(Vec<StreamIdx>),
+}
+
+/// Frontier entries, ordered by cost then the positions into the children.
+type Entry = Reverse<(usize, Vec<usize>)>;
+
+struct Stream<I> {
+  kind: Kind<I>,
+  items: Vec<Item<I>>,
+  frontier: BinaryHeap<Entry>,
+  seen: HashSet<Vec<usize>>,
+  started: bool,
+  exhausted: bool,
+}
+
+pub struct Search<I> {
+  streams: Vec<Stream<I>>,
+  leaves: HashMap<I, StreamIdx>,
+  budget: usize,
+}
+
+impl<I: Copy + Ord + std::hash::Hash> Search<I> {
+  /// Create an empty search, `budget` bounds the number of items produced
+  /// across all streams so that pathological trees still terminate quickly.
+  pub fn new(budget: usize) -> Self {
+    Self {
+      streams: vec![],
+      leaves: HashMap::default(),
+      budget,
+    }
+  }
+
+  pub fn leaf(&mut self, goal: I, weight: usize) -> StreamIdx {
+    if let Some(&idx) = self.leaves.get(&goal) {
+      return idx;
+    }
+
+    let idx = self.push(Kind::Leaf(goal, weight));
+    self.leaves.insert(goal, idx);
+    idx
+  }
+
+  pub fn or(&mut self, streams: Vec<StreamIdx>) -> StreamIdx {
+    self.compound(streams, Kind::Or)
+  }
+
+  pub fn and(&mut self, streams: Vec<StreamIdx>) -> StreamIdx {
+    self.compound(streams, Kind::And)
+  }
+
+  fn compound(
+    &mut self,
+    mut streams: Vec<StreamIdx>,
+    mk: impl FnOnce(Vec<StreamIdx>) -> Kind<I>,
+  ) -> StreamIdx {
+    streams.dedup();
+    if streams.len() == 1 {
+      return streams[0];
+    }
+
+    self.push(mk(streams))
+  }
+
+  fn push(&mut self, kind: Kind<I>) -> StreamIdx {
+    self.streams.push(Stream {
+      kind,
+      items: vec![],
+      frontier: BinaryHeap::default(),
+      seen: HashSet::default(),
+      started: false,
+      exhausted: false,
+    });
+    self.streams.len() - 1
+  }
+
+  /// The `limit` cheapest minimal correction sets of `root`, in order of cost.
+  ///
+  /// Sets that are a superset of an already returned set are pruned.
+  pub fn minimal_sets(&mut self, root: StreamIdx, limit: usize) -> Vec<Set<I>> {
+    let mut sets: Vec<Set<I>> = vec![];
+    let mut i = 0;
+    while sets.len() < limit {
+      let Some(item) = self.get(root, i) else {
+        break;
+      };
+
+      i += 1;
+      // Costs of unions are estimates, a subset may come after its superset.
+      if !sets.iter().any(|s| is_subset(s, &item.set)) {
+        sets.retain(|s| !is_subset(&item.set, s));
+        sets.push(item.set);
+      }
+    }
+
+    sets
+  }
+
+  /// The `i`-th cheapest correction set of a stream, expanding lazily.
+  fn get(&mut self, idx: StreamIdx, i: usize) -> Option<Item<I>> {
+    while self.streams[idx].items.len() <= i {
+      if self.streams[idx].exhausted || !self.advance(idx) {
+        self.streams[idx].exhausted = true;
+        return None;
+      }
+    }
+
+    Some(self.streams[idx].items[i].clone())
+  }
+
+  /// Produce the next item of a stream, returns `false` if there are none.
+  fn advance(&mut self, idx: StreamIdx) -> bool {
+    if self.budget == 0 {
+      log::debug!("correction set search ran out of budget");
+      return false;
+    }
+
+    self.budget -= 1;
+
+    if !self.streams[idx].started {
+      self.streams[idx].started = true;
+      self.start(idx);
+    }
+
+    while let Some(Reverse((cost, positions))) =
+      self.streams[idx].frontier.pop()
+    {
+      let item = match &self.streams[idx].kind {
+        Kind::Leaf(goal, weight) => Some(Item {
+          cost: *weight,
+          set: Rc::from([*goal]),
+        }),
+        Kind::Or(children) => {
+          let (child, pos) = (children[positions[0]], positions[1]);
+          self.enqueue(idx, vec![positions[0], pos + 1]);
+          self.get(child, pos)
+        }
+        Kind::And(children) => {
+          let children = children.clone();
+          for i in 0 .. positions.len() {
+            let mut next = positions.clone();
+            next[i] += 1;
+            self.enqueue(idx, next);
+          }
+
+          let mut set = children
+            .iter()
+            .zip(&positions)
+            .flat_map(|(&child, &pos)| {
+              self.streams[child].items[pos].set.iter().copied()
+            })
+            .collect::<Vec<_>>();
+          set.sort_unstable();
+          set.dedup();
+
+          Some(Item {
+            cost,
+            set: set.into(),
+          })
+        }
+      };
+
+      // Only keep minimal sets, anything built from a superset of an
+      // item is a superset of what's built from the item itself.
+      let Some(item) = item else { continue };
+      let stream = &mut self.streams[idx];
+      if stream
+        .items
+        .iter()
+        .any(|prev| is_subset(&prev.set, &item.set))
+      {
+        continue;
+      }
+
+      stream.items.push(Item { cost, ..item });
+      return true;
+    }
+
+    false
+  }
+
+  fn start(&mut self, idx: StreamIdx) {
+    match &self.streams[idx].kind {
+      Kind::Leaf(_, weight) => {
+        let weight = *weight;
+        self.streams[idx].frontier.push(Reverse((weight, vec![])));
+      }
+      Kind::Or(children) => {
+        for i in 0 .. children.len() {
+          self.enqueue(idx, vec![i, 0]);
+        }
+      }
+      Kind::And(children) => {
+        let zeros = vec![0; children.len()];
+        self.enqueue(idx, zeros);
+      }
+    }
+  }
+
+  /// Add the combination at `positions` to the frontier of `idx`, if the
+  /// children have items at those positions.
+  fn enqueue(&mut self, idx: StreamIdx, positions: Vec<usize>) {
+    if self.streams[idx].seen.contains(&positions) {
+      return;
+    }
+
+    let cost = match &self.streams[idx].kind {
+      Kind::Leaf(..) => unreachable!("leaves have no children"),
+      Kind::Or(children) => {
+        let child = children[positions[0]];
+        self.get(child, positions[1]).map(|item| item.cost)
+      }
+      Kind::And(children) => {
+        let children = children.clone();
+        children
+          .iter()
+          .zip(&positions)
+          .map(|(&child, &pos)| self.get(child, pos).map(|item| item.cost))
+          .sum::<Option<usize>>()
+      }
+    };
+
+    let stream = &mut self.streams[idx];
+    if let Some(cost) = cost {
+      stream.frontier.push(Reverse((cost, positions.clone())));
+    }
+    stream.seen.insert(positions);
+  }
+}
+
+/// The AND-OR structure of a search, recorded so that it can be searched
+/// again with other costs for its leaves.
+///
+/// Nodes only refer to nodes recorded before them, the last node is the root.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Problem<L> {
+  nodes: Vec<Node>,
+  leaves: Vec<L>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum Node {
+  Leaf(usize),
+  Or(Vec<usize>),
+  And(Vec<usize>),
+}
+
+impl<L> Default for Problem<L> {
+  fn default() -> Self {
+    Self {
+      nodes: vec![],
+      leaves: vec![],
+    }
+  }
+}
+
+impl<L> Problem<L> {
+  pub fn leaves(&self) -> &[L] {
+    &self.leaves
+  }
+
+  pub fn leaf(&mut self, leaf: L) -> usize {
+    self.leaves.push(leaf);
+    self.push(Node::Leaf(self.leaves.len() - 1))
+  }
+
+  pub fn or(&mut self, nodes: Vec<usize>) -> usize {
+    self.push(Node::Or(nodes))
+  }
+
+  pub fn and(&mut self, nodes: Vec<usize>) -> usize {
+    self.push(Node::And(nodes))
+  }
+
+  fn push(&mut self, node: Node) -> usize {
+    self.nodes.push(node);
+    self.nodes.len() - 1
+  }
+
+  /// The `limit` cheapest minimal correction sets of the root, as indices
+  /// into the leaves.
+  pub fn minimal_sets(
+    &self,
+    cost: impl Fn(&L) -> usize,
+    budget: usize,
+    limit: usize,
+  ) -> Vec<Set<usize>> {
+    let mut search = Search::new(budget);
+    let mut streams = Vec::with_capacity(self.nodes.len());
+    for node in &self.nodes {
+      let children = |nodes: &[usize]| -> Vec<StreamIdx> {
+        nodes.iter().map(|&n| streams[n]).collect()
+      };
+      let stream = match node {
+        Node::Leaf(i) => search.leaf(*i, cost(&self.leaves[*i])),
+        Node::Or(nodes) => search.or(children(nodes)),
+        Node::And(nodes) => search.and(children(nodes)),
+      };
+      streams.push(stream);
+    }
+
+    streams
+      .last()
+      .map(|&root| search.minimal_sets(root, limit))
+      .unwrap_or_default()
+  }
+}
+
+/// Is `a` a subset of `b`, both sets are sorted.
+fn is_subset<I: Ord>(a: &[I], b: &[I]) -> bool {
+  let mut b = b.iter();
+  a.iter().all(|x| b.any(|y| y == x))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sets(
+    search: &mut Search<usize>,
+    root: StreamIdx,
+    limit: usize,
+  ) -> Vec<Vec<usize>> {
+    search
+      .minimal_sets(root, limit)
+      .iter()
+      .map(|set| set.to_vec())
+      .collect()
+  }
+
+  #[test]
+  fn alternatives_in_order_of_cost() {
+    let mut search = Search::new(usize::MAX);
+    let (a, b, c) = (search.leaf(0, 5), search.leaf(1, 1), search.leaf(2, 3));
+    let root = search.or(vec![a, b, c]);
+
+    assert_eq!(sets(&mut search, root, 10), vec![vec![1], vec![2], vec![0]]);
+  }
+
+  #[test]
+  fn conjunctions_union_their_children() {
+    let mut search = Search::new(usize::MAX);
+    let left = [search.leaf(0, 1), search.leaf(1, 2)];
+    let right = [search.leaf(2, 1), search.leaf(3, 5)];
+    let (left, right) = (search.or(left.to_vec()), search.or(right.to_vec()));
+    let root = search.and(vec![left, right]);
+
+    assert_eq!(sets(&mut search, root, 10), vec![
+      vec![0, 2],
+      vec![1, 2],
+      vec![0, 3],
+      vec![1, 3]
+    ]);
+  }
+
+  #[test]
+  fn supersets_are_pruned() {
+    let mut search = Search::new(usize::MAX);
+    let (a, b) = (search.leaf(0, 1), search.leaf(1, 2));
+    let both = search.and(vec![a, b]);
+    let root = search.or(vec![a, both]);
+
+    assert_eq!(sets(&mut search, root, 10), vec![vec![0]]);
+  }
+
+  #[test]
+  fn later_subsets_replace_supersets() {
+    // The cost of `{a}` through `a_or_c` and `a_or_d` is estimated as twice that of
+    // `a`, it comes after its superset `{a, e}`.
+    let mut search = Search::new(usize::MAX);
+    let (a, e) = (search.leaf(0, 3), search.leaf(1, 1));
+    let (c, d) = (search.leaf(2, 100), search.leaf(3, 100));
+    let superset = search.and(vec![a, e]);
+    let (a_or_c, a_or_d) = (search.or(vec![a, c]), search.or(vec![a, d]));
+    let subset = search.and(vec![a_or_c, a_or_d]);
+    let root = search.or(vec![superset, subset]);
+
+    let found = sets(&mut search, root, 10);
+    assert_eq!(found[0], vec![0]);
+    assert!(found.iter().all(|set| set == &[0] || !set.contains(&0)));
+  }
+
+  #[test]
+  fn problems_are_searched_with_each_cost() {
+    let mut problem = Problem::default();
+    let (a, b) = (problem.leaf(5), problem.leaf(1));
+    let c = problem.leaf(3);
+    let both = problem.and(vec![b, c]);
+    problem.or(vec![a, both]);
+
+    let sets = |cost: fn(&usize) -> usize| {
+      problem
+        .minimal_sets(cost, usize::MAX, 10)
+        .iter()
+        .map(|set| set.to_vec())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(sets(|&w| w), vec![vec![1, 2], vec![0]]);
+    assert_eq!(sets(|&w| 10 - w), vec![vec![0], vec![1, 2]]);
+  }
+
+  #[test]
+  fn limits_bound_the_sets() {
+    let mut search = Search::new(usize::MAX);
+    let leaves = (0 .. 10).map(|i| search.leaf(i, i)).collect();
+    let root = search.or(leaves);
+    assert_eq!(sets(&mut search, root, 3), vec![vec![0], vec![1], vec![2]]);
+
+    let mut search = Search::new(3);
+    let leaves = (0 .. 10).map(|i| search.leaf(i, i)).collect();
+    let root = search.or(leaves);
+    let found = sets(&mut search, root, 10);
+    assert!(!found.is_empty() && found.len() < 10, "{found:?}");
+  }
+}
@@ -0,0 +1,497 @@
+//! Impls compiled out of dependencies by disabled cargo features.
+//!
+//! Dependency sources are parsed from disk, e.g. the cargo registry, to
+//! find impls gated behind `#[cfg(feature = "...")]`. Items are matched by
+//! name, the gated code was never compiled so there is nothing to resolve.
+//! Impls enabled by any of several features are suggested once for each.
+
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  path::{Path, PathBuf},
+  rc::Rc,
+};
+
+use rustc_ast::{self as ast, ptr::P};
+use rustc_errors::Diag;
+use rustc_hir::def_id::{CrateNum, LOCAL_CRATE};
+use rustc_middle::ty::{self, TyCtxt};
+use rustc_parse::exp;
+use rustc_session::parse::ParseSess;
+use rustc_span::{sym, FileName};
+use serde::Serialize;
+#[cfg(feature = "testing")]
+use ts_rs::TS;
+
+use super::tree::I;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct FeatureSuggestion {
+  /// The goal the gated impl would satisfy.
+  #[cfg_attr(feature = "testing", ts(type = "ProofNodeIdx"))]
+  pub goal: I,
+  /// Package declaring the features, as named in `Cargo.toml`.
+  pub package: String,
+  /// Features that all need to be enabled for the impl to exist.
+  pub features: Vec<String>,
+  /// Source file of the gated impl.
+  pub file: String,
+}
+
+/// An impl in dependency source that's only compiled with some features,
+/// one for each alternative set of features that enables it.
+#[derive(Debug)]
+struct GatedImpl {
+  trait_name: String,
+  self_name: String,
+  features: Vec<String>,
+  file: PathBuf,
+}
+
+thread_local! {
+  // Dependency sources don't change during a compilation, each package is
+  // parsed once and shared across bodies.
+  static GATED_IMPLS: RefCell<HashMap<PathBuf, Rc<[GatedImpl]>>> =
+    RefCell::default();
+}
+
+/// Features of the trait's or self type's crate that would enable an impl
+/// for the failed goal, if the impl exists in the crate's sources.
+pub(super) fn suggest<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  goal: I,
+  predicate: ty::Predicate<'tcx>,
+) -> Vec<FeatureSuggestion> {
+  let Some(t) = predicate.as_trait_clause() else {
+    return vec![];
+  };
+  let t = t.skip_binder();
+  if t.polarity != ty::PredicatePolarity::Positive {
+    return vec![];
+  }
+  let ty::Adt(def, _) = t.self_ty().peel_refs().kind() else {
+    return vec![];
+  };
+
+  // An impl that's compiled in failed for another reason.
+  let self_did = def.did();
+  let has_impl = tcx.all_impls(t.def_id()).any(|impl_def_id| {
+    tcx.impl_trait_ref(impl_def_id).is_some_and(|trait_ref| {
+      matches!(trait_ref.skip_binder().self_ty().kind(),
+        ty::Adt(other, _) if other.did() == self_did)
+    })
+  });
+  if has_impl {
+    return vec![];
+  }
+
+  let trait_name = tcx.item_name(t.def_id()).to_string();
+  let self_name = tcx.item_name(self_did).to_string();
+
+  let mut crates = vec![self_did.krate, t.def_id().krate];
+  crates.dedup();
+  crates
+    .into_iter()
+    .filter_map(|krate| package_root(tcx, krate))
+    .flat_map(|(root, lib)| {
+      let package = package_name(&root);
+      gated_impls(&root, &lib)
+        .iter()
+        .filter(|gated| {
+          gated.trait_name == trait_name && gated.self_name == self_name
+        })
+        .map(|gated| FeatureSuggestion {
+          goal,
+          package: package.clone(),
+          features: gated.features.clone(),
+          file: gated.file.display().to_string(),
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+/// Directory containing the `Cargo.toml` of a dependency, and the path of its
+/// crate root. Crates of the local workspace and the sysroot are excluded,
+/// their features aren't toggled as a dependency's are.
+fn package_root(tcx: TyCtxt, krate: CrateNum) -> Option<(PathBuf, PathBuf)> {
+  if krate == LOCAL_CRATE {
+    return None;
+  }
+
+  let FileName::Real(name) = tcx
+    .sess
+    .source_map()
+    .span_to_filename(tcx.def_span(krate.as_def_id()))
+  else {
+    return None;
+  };
+  let lib = name.local_path()?.to_path_buf();
+  if lib.starts_with(&tcx.sess.sysroot) {
+    return None;
+  }
+
+  let root = lib
+    .ancestors()
+    .find(|dir| dir.join("Cargo.toml").is_file())?
+    .to_path_buf();
+  Some((root, lib))
+}
+
+/// Name of the package in `root`, registry sources live in
+/// `<package>-<version>` directories.
+fn package_name(root: &Path) -> String {
+  let dir = root
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  match dir.rsplit_once('-') {
+    Some((name, version))
+      if version.starts_with(|c: char| c.is_ascii_digit()) =>
+    {
+      name.to_string()
+    }
+    _ => dir,
+  }
+}
+
+fn gated_impls(root: &Path, lib: &Path) -> Rc<[GatedImpl]> {
+  GATED_IMPLS.with(|cache| {
+    if let Some(impls) = cache.borrow().get(root) {
+      return Rc::clone(impls);
+    }
+
+    let mut impls = vec![];
+    scan_file(&parse_session(), lib, &[vec![]], &mut impls);
+    let impls = Rc::<[GatedImpl]>::from(impls);
+    cache
+      .borrow_mut()
+      .insert(root.to_path_buf(), Rc::clone(&impls));
+    impls
+  })
+}
+
+/// Collect the gated impls of a module file and its descendants. The
+/// `features` are the alternatives gating the module itself.
+fn scan_file(
+  psess: &ParseSess,
+  path: &Path,
+  features: &[Vec<String>],
+  out: &mut Vec<GatedImpl>,
+) {
+  let Ok(source) = std::fs::read_to_string(path) else {
+    return;
+  };
+  let Some(items) = parse_items(psess, path, source) else {
+    log::debug!("failed to parse dependency source {}", path.display());
+    return;
+  };
+
+  // Children of `lib.rs` and `mod.rs` live next to them, other modules
+  // keep theirs in a directory named after the module.
+  let dir = match path.file_stem().and_then(|stem| stem.to_str()) {
+    Some("lib" | "main" | "mod") | None => path.parent().map(Path::to_path_buf),
+    Some(stem) => path.parent().map(|parent| parent.join(stem)),
+  }
+  .unwrap_or_default();
+
+  scan_items(psess, &items, path, &dir, features, out);
+}
+
+/// Dependencies may not parse with this compiler, errors are dropped.
+fn parse_session() -> ParseSess {
+  ParseSess::with_silent_emitter(
+    vec![rustc_parse::DEFAULT_LOCALE_RESOURCE],
+    String::new(),
+    false,
+  )
+}
+
+fn parse_items(
+  psess: &ParseSess,
+  path: &Path,
+  source: String,
+) -> Option<Vec<P<ast::Item>>> {
+  let name = FileName::Custom(path.display().to_string());
+  let mut parser =
+    match rustc_parse::new_parser_from_source_str(psess, name, source) {
+      Ok(parser) => parser,
+      Err(errors) => {
+        errors.into_iter().for_each(Diag::cancel);
+        return None;
+      }
+    };
+
+  match parser.parse_mod(exp!(Eof)) {
+    Ok((_, items, _)) => Some(items.into_iter().collect()),
+    Err(error) => {
+      error.cancel();
+      None
+    }
+  }
+}
+
+fn scan_items(
+  psess: &ParseSess,
+  items: &[P<ast::Item>],
+  file: &Path,
+  dir: &Path,
+  features: &[Vec<String>],
+  out: &mut Vec<GatedImpl>,
+) {
+  for item in items {
+    let features = gated_by(features, &item.attrs);
+    match &item.kind {
+      ast::ItemKind::Impl(imp) => {
+        let Some(trait_ref) = &imp.of_trait else {
+          continue;
+        };
+        if !matches!(imp.polarity, ast::ImplPolarity::Positive) {
+          continue;
+        }
+        let (Some(trait_name), Some(self_name)) =
+          (trait_ref.path.segments.last(), type_name(&imp.self_ty))
+        else {
+          continue;
+        };
+
+        // Ungated impls are compiled in, they aren't suggested.
+        if features.iter().any(Vec::is_empty) {
+          continue;
+        }
+        out.extend(features.into_iter().map(|features| GatedImpl {
+          trait_name: trait_name.ident.to_string(),
+          self_name: self_name.clone(),
+          features,
+          file: file.to_path_buf(),
+        }));
+      }
+
+      // Impls are often hidden in anonymous constants.
+      ast::ItemKind::Const(cnst) => {
+        let Some(ast::ExprKind::Block(block, _)) =
+          cnst.expr.as_ref().map(|expr| &expr.kind)
+        else {
+          continue;
+        };
+        let items = block
+          .stmts
+          .iter()
+          .filter_map(|stmt| match &stmt.kind {
+            ast::StmtKind::Item(item) => Some(item.clone()),
+            _ => None,
+          })
+          .collect::<Vec<_>>();
+        scan_items(psess, &items, file, dir, &features, out);
+      }
+
+      ast::ItemKind::Mod(_, module) => {
+        let name = item.ident.to_string();
+        match module {
+          ast::ModKind::Loaded(items, ..) => {
+            scan_items(psess, items, file, &dir.join(&name), &features, out);
+          }
+          ast::ModKind::Unloaded => {
+            if let Some(path) = module_path(&item.attrs, file, dir, &name) {
+              scan_file(psess, &path, &features, out);
+            }
+          }
+        }
+      }
+
+      _ => {}
+    }
+  }
+}
+
+/// File of an out-of-line module declared in `file`.
+fn module_path(
+  attrs: &[ast::Attribute],
+  file: &Path,
+  dir: &Path,
+  name: &str,
+) -> Option<PathBuf> {
+  let explicit = attrs
+    .iter()
+    .find(|attr| attr.has_name(sym::path))
+    .and_then(ast::Attribute::value_str);
+
+  if let Some(explicit) = explicit {
+    return Some(file.parent()?.join(explicit.as_str()));
+  }
+
+  [
+    dir.join(format!("{name}.rs")),
+    dir.join(name).join("mod.rs"),
+  ]
+  .into_iter()
+  .find(|path| path.is_file())
+}
+
+/// Alternative sets of features that each enable an item with `attrs`,
+/// given those enabling its parent. An empty set means the item isn't
+/// gated by features.
+fn gated_by(
+  features: &[Vec<String>],
+  attrs: &[ast::Attribute],
+) -> Vec<Vec<String>> {
+  attrs
+    .iter()
+    .filter(|attr| attr.has_name(sym::cfg))
+    .filter_map(|attr| cfg_features(attr.meta_item_list()?.first()?))
+    .fold(features.to_vec(), |features, cfg| all_of(&features, &cfg))
+}
+
+/// Alternative sets of features that satisfy a `cfg` predicate, `None` if
+/// it doesn't depend on features. Negated features and other predicates
+/// are ignored, they're taken to hold in `all` and fail in `any`.
+fn cfg_features(cfg: &ast::MetaItemInner) -> Option<Vec<Vec<String>>> {
+  let meta = cfg.meta_item()?;
+  if meta.has_name(sym::feature) {
+    return Some(vec![vec![meta.value_str()?.to_string()]]);
+  }
+
+  let nested = meta.meta_item_list()?.iter().filter_map(cfg_features);
+  if meta.has_name(sym::all) {
+    Some(nested.fold(vec![vec![]], |all, cfg| all_of(&all, &cfg)))
+  } else if meta.has_name(sym::any) {
+    let mut any = nested.flatten().collect::<Vec<_>>();
+    any.sort();
+    any.dedup();
+    (!any.is_empty()).then_some(any)
+  } else {
+    None
+  }
+}
+
+/// Alternatives satisfying both `lhs` and `rhs`, each pair is combined.
+fn all_of(lhs: &[Vec<String>], rhs: &[Vec<String>]) -> Vec<Vec<String>> {
+  let mut all = lhs
+    .iter()
+    .flat_map(|lhs| {
+      rhs.iter().map(move |rhs| {
+        let mut features = [lhs.as_slice(), rhs].concat();
+        features.sort();
+        features.dedup();
+        features
+      })
+    })
+    .collect::<Vec<_>>();
+  all.sort();
+  all.dedup();
+  all
+}
+
+/// Name of the type an impl is for, looking through references.
+fn type_name(ty: &ast::Ty) -> Option<String> {
+  match &ty.kind {
+    ast::TyKind::Path(_, path) => {
+      path.segments.last().map(|seg| seg.ident.to_string())
+    }
+    ast::TyKind::Ref(_, mut_ty) => type_name(&mut_ty.ty),
+    ast::TyKind::Paren(ty) => type_name(ty),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Gated impls in `source` as `(trait, self type, features)`.
+  fn gated(source: &str) -> Vec<(String, String, Vec<String>)> {
+    rustc_span::create_default_session_globals_then(|| {
+      let psess = parse_session();
+      let file = Path::new("lib.rs");
+      let items =
+        parse_items(&psess, file, source.to_string()).expect("invalid source");
+      let mut impls = vec![];
+      scan_items(&psess, &items, file, Path::new(""), &[vec![]], &mut impls);
+      impls
+        .into_iter()
+        .map(|gated| (gated.trait_name, gated.self_name, gated.features))
+        .collect()
+    })
+  }
+
+  fn features(source: &str) -> Vec<Vec<String>> {
+    gated(source)
+      .into_iter()
+      .map(|(_, _, features)| features)
+      .collect()
+  }
+
+  #[test]
+  fn single_feature() {
+    assert_eq!(
+      gated(r#"#[cfg(feature = "serde")] impl Serialize for Point {}"#),
+      [("Serialize".into(), "Point".into(), vec!["serde".into()])]
+    );
+  }
+
+  #[test]
+  fn all_features_are_required() {
+    assert_eq!(
+      features(r#"#[cfg(all(feature = "b", feature = "a"))] impl T for X {}"#),
+      [vec!["a", "b"]]
+    );
+  }
+
+  #[test]
+  fn any_suggests_each_alternative() {
+    assert_eq!(
+      features(r#"#[cfg(any(feature = "a", feature = "b"))] impl T for X {}"#),
+      [vec!["a"], vec!["b"]]
+    );
+  }
+
+  #[test]
+  fn modules_gate_their_items() {
+    let source = r#"
+      #[cfg(feature = "std")]
+      mod imp {
+        const _: () = {
+          #[cfg(any(feature = "a", all(feature = "b", feature = "c")))]
+          impl T for &X {}
+        };
+      }
+    "#;
+    assert_eq!(gated(source), [
+      ("T".into(), "X".into(), vec!["a".into(), "std".into()]),
+      ("T".into(), "X".into(), vec![
+        "b".into(),
+        "c".into(),
+        "std".into()
+      ]),
+    ]);
+  }
+
+  #[test]
+  fn other_predicates_are_ignored() {
+    assert_eq!(
+      features(r#"#[cfg(all(unix, feature = "a"))] impl T for X {}"#),
+      [vec!["a"]]
+    );
+    assert_eq!(
+      features(r#"#[cfg(any(unix, feature = "a"))] impl T for X {}"#),
+      [vec!["a"]]
+    );
+    assert!(
+      features(r#"#[cfg(not(feature = "a"))] impl T for X {}"#).is_empty()
+    );
+    assert!(features("#[cfg(unix)] impl T for X {}").is_empty());
+    assert!(features("impl T for X {}").is_empty());
+  }
+
+  #[test]
+  fn invalid_sources_are_skipped() {
+    rustc_span::create_default_session_globals_then(|| {
+      let psess = parse_session();
+      let items = parse_items(&psess, Path::new("lib.rs"), "impl {".into());
+      assert!(items.is_none());
+    });
+  }
+}
@@ -0,0 +1,782 @@
+//! Source edits that could fix a failed goal.
+//!
+//! Fixes are best-effort, they're only generated when the code to change
+//! is local and the goal is concrete enough to print. Fixes that stub out
+//! code the user has to write are marked as having placeholders, and fixes
+//! naming items that may have to be imported as maybe incorrect.
+
+use std::fmt::Write;
+
+use argus_ext::ty::TyCtxtExt;
+use rustc_hir::{
+  self as hir,
+  def::DefKind,
+  def_id::DefId,
+  intravisit::{self, Visitor},
+  LangItem,
+};
+use rustc_infer::infer::InferCtxt;
+use rustc_middle::ty::{
+  self,
+  print::{ForceTrimmedGuard, PrintTraitRefExt},
+  TyCtxt, TypeVisitableExt,
+};
+use rustc_span::{sym, Span};
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+#[cfg(feature = "testing")]
+use ts_rs::TS;
+
+use super::tree::{impl_is_allowed, GoalKind};
+
+/// Traits that have a builtin derive macro.
+const DERIVABLE: &[&str] = &[
+  "Clone",
+  "Copy",
+  "Debug",
+  "Default",
+  "Eq",
+  "Hash",
+  "Ord",
+  "PartialEq",
+  "PartialOrd",
+];
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct Fix {
+  pub kind: FixKind,
+  pub applicability: Applicability,
+  /// Edits to apply together, they never overlap.
+  pub edits: Vec<SourceEdit>,
+}
+
+/// How much of a fix is left to the user, ordered from least to most.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum Applicability {
+  /// The edits can be applied as-is.
+  MachineApplicable,
+  /// The edits name items by their trimmed paths, which may not be in
+  /// scope where they're inserted without a `use`.
+  MaybeIncorrect,
+  /// The edits contain placeholders, e.g., `todo!()` or `/* Type */`, the
+  /// user has to fill in before the code compiles as intended.
+  HasPlaceholders,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum FixKind {
+  /// Add an `impl Trait for Type` block with the required items stubbed.
+  ImplTrait,
+  /// Add a `#[derive(Trait)]` attribute to the type definition.
+  Derive,
+  /// Add a where-clause to the item declaring the type parameter.
+  WhereClause,
+  /// Add or remove closure parameters.
+  ClosureParams,
+  /// Define a local wrapper type implementing the trait, and wrap the
+  /// values passed where the trait is required.
+  Newtype,
+  /// Add explicit generic arguments to a call.
+  Turbofish,
+  /// Add a type annotation to a let binding.
+  TypeAnnotation,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct SourceEdit {
+  /// Range to replace, empty for insertions.
+  pub range: CharRange,
+  pub replacement: String,
+}
+
+impl SourceEdit {
+  pub(super) fn new(
+    tcx: TyCtxt,
+    span: Span,
+    replacement: String,
+  ) -> Option<Self> {
+    let range = CharRange::from_span(span, tcx.sess.source_map()).ok()?;
+    Some(Self { range, replacement })
+  }
+}
+
+/// Suggested fixes for a failed goal of the given kind, cheapest first.
+///
+/// The `obligation_span` is given when the goal is the obligation itself,
+/// fixes may then change the expression that required it.
+pub(super) fn suggest<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  body_owner: DefId,
+  predicate: ty::Predicate<'tcx>,
+  kind: &GoalKind,
+  obligation_span: Option<Span>,
+) -> Vec<Fix> {
+  let tcx = infcx.tcx;
+  let ty::PredicateKind::Clause(ty::ClauseKind::Trait(t)) =
+    predicate.kind().skip_binder()
+  else {
+    return vec![];
+  };
+
+  if t.polarity != ty::PredicatePolarity::Positive {
+    return vec![];
+  }
+
+  let t = infcx.resolve_vars_if_possible(t);
+  if t.has_escaping_bound_vars() || t.has_non_region_infer() {
+    return vec![];
+  }
+
+  // Paths are printed by name only, as they would be written with
+  // the item in scope. Importing the item is left to the user.
+  let _guard = ForceTrimmedGuard::new();
+
+  match kind {
+    GoalKind::Trait { .. } => [
+      derive(tcx, t),
+      where_clause(tcx, body_owner, t),
+      impl_trait(infcx, t),
+    ]
+    .into_iter()
+    .flatten()
+    .collect(),
+
+    GoalKind::Newtype => newtype(tcx, body_owner, t, obligation_span)
+      .into_iter()
+      .collect(),
+
+    GoalKind::AddFnParams { .. } | GoalKind::DeleteFnParams { .. } => tcx
+      .fn_trait_arity(t)
+      .and_then(|arity| closure_params(tcx, t.self_ty(), arity))
+      .into_iter()
+      .collect(),
+
+    _ => vec![],
+  }
+}
+
+fn derive<'tcx>(tcx: TyCtxt<'tcx>, t: ty::TraitPredicate<'tcx>) -> Option<Fix> {
+  let ty::Adt(def, _) = t.self_ty().kind() else {
+    return None;
+  };
+
+  let local_id = def.did().as_local()?;
+
+  // Derives implement `PartialEq` and `PartialOrd` with `Self` as the `Rhs`.
+  let args = t.trait_ref.args;
+  if args.len() > 2
+    || args
+      .get(1)
+      .is_some_and(|rhs| rhs.as_type() != Some(t.self_ty()))
+    || has_impl(tcx, t.def_id(), def.did())
+  {
+    return None;
+  }
+
+  // Supertraits without an impl are derived as well, e.g., `Copy` requires
+  // `Clone`. They're listed first, as they're usually written.
+  let mut traits = vec![];
+  for def_id in ty::elaborate::supertrait_def_ids(tcx, t.def_id()) {
+    if def_id != t.def_id()
+      && (tcx.is_lang_item(def_id, LangItem::Sized)
+        || has_impl(tcx, def_id, def.did()))
+    {
+      continue;
+    }
+    let name = tcx.get_diagnostic_name(def_id)?;
+    if !DERIVABLE.contains(&name.as_str()) {
+      return None;
+    }
+    let supertraits = ty::elaborate::supertrait_def_ids(tcx, def_id).count();
+    traits.push((supertraits, name));
+  }
+  traits.reverse();
+  traits.sort_by_key(|(supertraits, _)| *supertraits);
+  let names = traits
+    .iter()
+    .map(|(_, name)| name.as_str())
+    .collect::<Vec<_>>();
+
+  let span = tcx.source_span(local_id).shrink_to_lo();
+  let indent = indentation(tcx, span);
+  let edit = SourceEdit::new(
+    tcx,
+    span,
+    format!("#[derive({})]\n{indent}", names.join(", ")),
+  )?;
+
+  Some(Fix {
+    kind: FixKind::Derive,
+    applicability: Applicability::MachineApplicable,
+    edits: vec![edit],
+  })
+}
+
+/// Whether the ADT `adt_def_id` has an impl of `trait_def_id`, even if its
+/// where-clauses don't hold.
+fn has_impl(tcx: TyCtxt, trait_def_id: DefId, adt_def_id: DefId) -> bool {
+  let self_ty = tcx.type_of(adt_def_id).instantiate_identity();
+  let mut found = false;
+  tcx.for_each_relevant_impl(trait_def_id, self_ty, |impl_def_id| {
+    found |= tcx
+      .type_of(impl_def_id)
+      .instantiate_identity()
+      .ty_adt_def()
+      .is_some_and(|def| def.did() == adt_def_id);
+  });
+  found
+}
+
+fn where_clause<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  body_owner: DefId,
+  t: ty::TraitPredicate<'tcx>,
+) -> Option<Fix> {
+  let ty::Param(param) = t.self_ty().kind() else {
+    return None;
+  };
+
+  if tcx.is_fn_trait(t.def_id()) {
+    return None;
+  }
+
+  // The parameter may be declared by a parent of the body, e.g. an impl.
+  let declared_by = tcx.generics_of(body_owner).type_param(*param, tcx).def_id;
+  let item = tcx.parent(declared_by).as_local()?;
+  let generics = tcx.hir_get_generics(item)?;
+  let replacement = format!(
+    "{} {}: {}",
+    generics.add_where_or_trailing_comma(),
+    t.self_ty(),
+    t.trait_ref.print_only_trait_path()
+  );
+  let edit = SourceEdit::new(
+    tcx,
+    generics.tail_span_for_predicate_suggestion(),
+    replacement,
+  )?;
+
+  Some(Fix {
+    kind: FixKind::WhereClause,
+    applicability: naming_applicability(
+      tcx,
+      item.to_def_id(),
+      Some(t.def_id()),
+      t.trait_ref.args,
+    ),
+    edits: vec![edit],
+  })
+}
+
+fn impl_trait<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  t: ty::TraitPredicate<'tcx>,
+) -> Option<Fix> {
+  let tcx = infcx.tcx;
+
+  // Blanket impls are rarely what's wanted, a where-clause is suggested instead.
+  if matches!(t.self_ty().peel_refs().kind(), ty::Param(..))
+    || !impl_is_allowed(infcx, t)
+  {
+    return None;
+  }
+
+  let t = tcx.erase_regions(t);
+
+  // Impls are placed after a local definition they're allowed to live next
+  // to, the self type is preferred over the trait.
+  let anchor = match t.self_ty().kind() {
+    ty::Adt(def, _) if def.did().is_local() => def.did(),
+    _ => t.def_id(),
+  };
+  let span = tcx.source_span(anchor.as_local()?).shrink_to_hi();
+  let indent = indentation(tcx, tcx.def_span(anchor));
+
+  let generics = type_params(t.trait_ref.args.iter());
+  let mut replacement = format!(
+    "\n\n{indent}impl{generics} {} for {} {{\n",
+    t.trait_ref.print_only_trait_path(),
+    t.self_ty()
+  );
+  let applicability =
+    push_item_stubs(tcx, t.trait_ref, &indent, &mut replacement).max(
+      naming_applicability(tcx, anchor, Some(t.def_id()), t.trait_ref.args),
+    );
+
+  let edit = SourceEdit::new(tcx, span, replacement)?;
+  Some(Fix {
+    kind: FixKind::ImplTrait,
+    applicability,
+    edits: vec![edit],
+  })
+}
+
+fn newtype<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  body_owner: DefId,
+  t: ty::TraitPredicate<'tcx>,
+  obligation_span: Option<Span>,
+) -> Option<Fix> {
+  // Wrapping a borrow would need a lifetime parameter, that's left to the
+  // user, and unsized types can't be stored by value.
+  if t.self_ty().has_free_regions()
+    || matches!(
+      t.self_ty().kind(),
+      ty::Str | ty::Slice(..) | ty::Dynamic(..)
+    )
+  {
+    return None;
+  }
+
+  let name = match t.self_ty().kind() {
+    ty::Adt(def, _) => format!("{}Wrapper", tcx.item_name(def.did())),
+    _ => "Wrapper".to_string(),
+  };
+  let wrapper = format!("{name}{}", type_params([t.self_ty().into()]));
+
+  // The wrapper is defined after the module item containing the body.
+  let mut item = body_owner;
+  while let Some(parent) = tcx.opt_parent(item) {
+    if tcx.def_kind(parent) == DefKind::Mod {
+      break;
+    }
+    item = parent;
+  }
+  let span = tcx.source_span(item.as_local()?).shrink_to_hi();
+  let indent = indentation(tcx, tcx.def_span(item));
+
+  let mut definition = format!(
+    "\n\n{indent}struct {wrapper}(pub {});\n\n{indent}impl{} {} for {wrapper} \
+     {{\n",
+    t.self_ty(),
+    type_params(t.trait_ref.args.iter()),
+    t.trait_ref.print_only_trait_path(),
+  );
+  let applicability =
+    push_item_stubs(tcx, t.trait_ref, &indent, &mut definition).max(
+      naming_applicability(tcx, item, Some(t.def_id()), t.trait_ref.args),
+    );
+
+  let source_map = tcx.sess.source_map();
+  let mut edits = vec![SourceEdit::new(tcx, span, definition)?];
+  for arg in obligation_span
+    .map(|span| required_args(tcx, body_owner, span, t.def_id()))
+    .unwrap_or_default()
+  {
+    let snippet = source_map.span_to_snippet(arg).ok()?;
+    edits.push(SourceEdit::new(tcx, arg, format!("{name}({snippet})"))?);
+  }
+
+  Some(Fix {
+    kind: FixKind::Newtype,
+    applicability,
+    edits,
+  })
+}
+
+/// Arguments of the call at `span` passed to parameters of a generic type
+/// that's required to implement `trait_def_id`.
+///
+/// Only calls to paths are supported, method calls need type information.
+fn required_args<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  body_owner: DefId,
+  span: Span,
+  trait_def_id: DefId,
+) -> Vec<Span> {
+  let Some(body) = body_owner
+    .as_local()
+    .and_then(|local| tcx.hir_maybe_body_owned_by(local))
+  else {
+    return vec![];
+  };
+
+  let mut finder = CallFinder { span, call: None };
+  finder.visit_body(body);
+  let Some((hir::def::Res::Def(DefKind::Fn | DefKind::AssocFn, callee), args)) =
+    finder.call
+  else {
+    return vec![];
+  };
+
+  let predicates = tcx.predicates_of(callee).instantiate_identity(tcx);
+  let is_required = |ty: ty::Ty<'tcx>| {
+    predicates.predicates.iter().any(|clause| {
+      clause.as_trait_clause().is_some_and(|t| {
+        t.def_id() == trait_def_id && t.self_ty().skip_binder() == ty
+      })
+    })
+  };
+
+  let sig = tcx.fn_sig(callee).instantiate_identity().skip_binder();
+  sig
+    .inputs()
+    .iter()
+    .zip(args)
+    .filter(|(ty, _)| matches!(ty.kind(), ty::Param(..)) && is_required(**ty))
+    .map(|(_, span)| span)
+    .collect()
+}
+
+/// Finds the call to a path at `span`, the obligation may point at the
+/// whole call, its callee, or one of its arguments.
+struct CallFinder {
+  span: Span,
+  call: Option<(hir::def::Res, Vec<Span>)>,
+}
+
+impl<'tcx> Visitor<'tcx> for CallFinder {
+  fn visit_expr(&mut self, expr: &'tcx hir::Expr<'tcx>) {
+    if self.call.is_some() {
+      return;
+    }
+
+    if let hir::ExprKind::Call(callee, args) = expr.kind {
+      if let hir::ExprKind::Path(hir::QPath::Resolved(_, path)) = callee.kind {
+        let mut spans = [expr.span, callee.span]
+          .into_iter()
+          .chain(args.iter().map(|a| a.span));
+        if spans.any(|span| span == self.span) {
+          self.call = Some((path.res, args.iter().map(|a| a.span).collect()));
+        }
+      }
+    }
+
+    intravisit::walk_expr(self, expr);
+  }
+}
+
+/// Generic parameters for the type parameters mentioned in `args`,
+/// e.g., `<T, U>`, or nothing if there are none.
+fn type_params<'tcx>(
+  args: impl IntoIterator<Item = ty::GenericArg<'tcx>>,
+) -> String {
+  let mut params = vec![];
+  for arg in args.into_iter().flat_map(ty::GenericArg::walk) {
+    if let ty::GenericArgKind::Type(ty) = arg.unpack() {
+      if let ty::Param(p) = ty.kind() {
+        if !params.contains(&p.name) {
+          params.push(p.name);
+        }
+      }
+    }
+  }
+
+  if params.is_empty() {
+    String::new()
+  } else {
+    let params = params.iter().map(ToString::to_string).collect::<Vec<_>>();
+    format!("<{}>", params.join(", "))
+  }
+}
+
+/// Append stubs for the required items of an impl, and close its block.
+///
+/// Every stub is a placeholder, the impl is only complete without them.
+fn push_item_stubs<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  trait_ref: ty::TraitRef<'tcx>,
+  indent: &str,
+  out: &mut String,
+) -> Applicability {
+  let mut applicability = Applicability::MachineApplicable;
+  for item in tcx.associated_items(trait_ref.def_id).in_definition_order() {
+    if item.defaultness(tcx).has_value() || item.is_impl_trait_in_trait() {
+      continue;
+    }
+
+    let stub = item_stub(tcx, item, trait_ref);
+    let _ = writeln!(out, "{indent}    {stub}");
+    applicability = Applicability::HasPlaceholders;
+  }
+  let _ = write!(out, "{indent}}}");
+  applicability
+}
+
+/// Placeholder code for a required associated item.
+fn item_stub<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  item: &ty::AssocItem,
+  trait_ref: ty::TraitRef<'tcx>,
+) -> String {
+  // Items are printed in terms of `Self`, as they would be written.
+  let trait_args = tcx.mk_args_from_iter(
+    std::iter::once(tcx.types.self_param.into())
+      .chain(trait_ref.args.iter().skip(1)),
+  );
+  let args = ty::GenericArgs::identity_for_item(tcx, item.def_id).rebase_onto(
+    tcx,
+    trait_ref.def_id,
+    trait_args,
+  );
+
+  match item.kind {
+    ty::AssocKind::Fn => {
+      let sig = tcx.liberate_late_bound_regions(
+        item.def_id,
+        tcx.fn_sig(item.def_id).instantiate(tcx, args),
+      );
+      let names = tcx.fn_arg_names(item.def_id);
+      let inputs = sig
+        .inputs()
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| {
+          if item.fn_has_self_parameter && i == 0 {
+            return match ty.kind() {
+              ty::Param(..) => "self".to_string(),
+              ty::Ref(_, inner, mutbl)
+                if matches!(inner.kind(), ty::Param(..)) =>
+              {
+                format!("&{}self", mutbl.prefix_str())
+              }
+              _ => format!("self: {ty}"),
+            };
+          }
+
+          match names.get(i) {
+            Some(name) if !name.name.is_empty() => format!("{name}: {ty}"),
+            _ => format!("_: {ty}"),
+          }
+        })
+        .collect::<Vec<_>>();
+
+      let generics = tcx
+        .generics_of(item.def_id)
+        .own_params
+        .iter()
+        .filter(|p| {
+          matches!(p.kind, ty::GenericParamDefKind::Type {
+            synthetic: false,
+            ..
+          })
+        })
+        .map(|p| p.name.to_string())
+        .collect::<Vec<_>>();
+      let generics = if generics.is_empty() {
+        String::new()
+      } else {
+        format!("<{}>", generics.join(", "))
+      };
+
+      let output = sig.output();
+      let output = if output.is_unit() {
+        String::new()
+      } else {
+        format!(" -> {output}")
+      };
+
+      format!(
+        "fn {}{generics}({}){output} {{ todo!() }}",
+        item.name,
+        inputs.join(", ")
+      )
+    }
+    ty::AssocKind::Type => format!("type {} = /* Type */;", item.name),
+    ty::AssocKind::Const => {
+      let ty = tcx.type_of(item.def_id).instantiate(tcx, args);
+      format!("const {}: {ty} = todo!();", item.name)
+    }
+  }
+}
+
+fn closure_params<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  ty: ty::Ty<'tcx>,
+  expected: usize,
+) -> Option<Fix> {
+  let ty::Closure(def_id, _) = ty.kind() else {
+    return None;
+  };
+
+  let hir::Node::Expr(hir::Expr {
+    kind: hir::ExprKind::Closure(closure),
+    ..
+  }) = tcx.hir_node_by_def_id(def_id.as_local()?)
+  else {
+    return None;
+  };
+
+  // Extra parameters are removed from the end and missing ones are
+  // added as wildcards, the types are left for inference.
+  let source_map = tcx.sess.source_map();
+  let mut params = tcx
+    .hir_body(closure.body)
+    .params
+    .iter()
+    .map(|p| source_map.span_to_snippet(p.span).ok())
+    .collect::<Option<Vec<_>>>()?;
+  params.resize_with(expected, || "_".to_string());
+
+  let edit = SourceEdit::new(
+    tcx,
+    closure.fn_arg_span?,
+    format!("|{}|", params.join(", ")),
+  )?;
+
+  Some(Fix {
+    kind: FixKind::ClosureParams,
+    applicability: Applicability::MachineApplicable,
+    edits: vec![edit],
+  })
+}
+
+/// Whether edits next to `item` can name the trait `trait_def_id` and the
+/// items in `args` as they're printed, by name only.
+///
+/// Names are in scope if they're defined in the same module as `item`, or
+/// they're in the standard prelude. Imports aren't considered.
+pub(super) fn naming_applicability<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  item: DefId,
+  trait_def_id: Option<DefId>,
+  args: impl IntoIterator<Item = ty::GenericArg<'tcx>>,
+) -> Applicability {
+  let mut named = trait_def_id.into_iter().collect::<Vec<_>>();
+  for arg in args.into_iter().flat_map(ty::GenericArg::walk) {
+    let Some(ty) = arg.as_type() else {
+      continue;
+    };
+    match ty.kind() {
+      ty::Adt(def, _) => named.push(def.did()),
+      ty::Foreign(def_id) => named.push(*def_id),
+      ty::Alias(ty::Projection, alias) => named.push(tcx.parent(alias.def_id)),
+      ty::Dynamic(predicates, ..) => {
+        named.extend(predicates.iter().filter_map(|p| match p.skip_binder() {
+          ty::ExistentialPredicate::Trait(t) => Some(t.def_id),
+          ty::ExistentialPredicate::AutoTrait(def_id) => Some(def_id),
+          ty::ExistentialPredicate::Projection(..) => None,
+        }));
+      }
+      // Printed, but not as anything that could be written.
+      ty::Closure(..)
+      | ty::CoroutineClosure(..)
+      | ty::Coroutine(..)
+      | ty::FnDef(..)
+      | ty::Alias(ty::Opaque, ..) => return Applicability::MaybeIncorrect,
+      _ => {}
+    }
+  }
+
+  let module = tcx
+    .parent_module_from_def_id(item.expect_local())
+    .to_def_id();
+  let prelude = prelude_items(tcx);
+  if named.iter().all(|def_id| {
+    tcx.opt_parent(*def_id) == Some(module) || prelude.contains(def_id)
+  }) {
+    Applicability::MachineApplicable
+  } else {
+    Applicability::MaybeIncorrect
+  }
+}
+
+/// Items of the standard prelude for the local crate's edition.
+fn prelude_items(tcx: TyCtxt) -> Vec<DefId> {
+  let child = |module: DefId, name: &str| {
+    tcx
+      .module_children(module)
+      .iter()
+      .find(|child| child.ident.name.as_str() == name)
+      .and_then(|child| child.res.opt_def_id())
+  };
+
+  let edition = format!("rust_{}", tcx.sess.edition());
+  tcx
+    .crates(())
+    .iter()
+    .find(|&&krate| tcx.crate_name(krate) == sym::std)
+    .and_then(|krate| child(krate.as_def_id(), "prelude"))
+    .and_then(|prelude| child(prelude, &edition))
+    .map(|prelude| {
+      tcx
+        .module_children(prelude)
+        .iter()
+        .filter_map(|child| child.res.opt_def_id())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Whitespace preceding the first line of `span`.
+fn indentation(tcx: TyCtxt, span: Span) -> String {
+  let margin = tcx.sess.source_map().span_to_margin(span).unwrap_or(0);
+  " ".repeat(margin)
+}
+
+#[cfg(test)]
+mod tests {
+  use rustc_span::Symbol;
+
+  use super::*;
+  use crate::test_utils as tu;
+
+  const ITEMS: &str = r"
+#[derive(Clone)]
+struct Cloned;
+struct Plain;
+";
+
+  #[test]
+  fn derives() {
+    tu::compile_normal(ITEMS, |tcx| {
+      let adt = |name: &str| {
+        let def_id = tcx
+          .hir_crate_items(())
+          .definitions()
+          .find(|def_id| {
+            tcx
+              .opt_item_name(def_id.to_def_id())
+              .is_some_and(|item| item.as_str() == name)
+          })
+          .unwrap();
+        tcx.type_of(def_id).instantiate_identity()
+      };
+      let derive = |name: &str, args: &[_]| {
+        let trait_def_id = tcx.get_diagnostic_item(Symbol::intern(name))?;
+        let trait_ref = ty::TraitRef::new(tcx, trait_def_id, args.to_vec());
+        let fix = derive(tcx, ty::TraitPredicate {
+          trait_ref,
+          polarity: ty::PredicatePolarity::Positive,
+        })?;
+        assert_eq!(fix.applicability, Applicability::MachineApplicable);
+        Some(fix.edits[0].replacement.trim().to_string())
+      };
+      let (cloned, plain) = (adt("Cloned"), adt("Plain"));
+
+      // `Rhs` defaults to `Self`, other types can't be derived.
+      assert_eq!(
+        derive("PartialEq", &[plain, plain]).as_deref(),
+        Some("#[derive(PartialEq)]")
+      );
+      assert_eq!(derive("PartialEq", &[plain, tcx.types.u8]), None);
+
+      // Supertraits are derived first, unless they're already implemented.
+      assert_eq!(
+        derive("Copy", &[plain]).as_deref(),
+        Some("#[derive(Clone, Copy)]")
+      );
+      assert_eq!(
+        derive("Copy", &[cloned]).as_deref(),
+        Some("#[derive(Copy)]")
+      );
+      assert_eq!(
+        derive("Ord", &[plain]).as_deref(),
+        Some("#[derive(PartialEq, Eq, PartialOrd, Ord)]")
+      );
+      assert_eq!(derive("Clone", &[cloned]), None);
+    });
+  }
+}
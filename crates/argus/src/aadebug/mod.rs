@@ -1,24 +1,36 @@
-mod dnf;
+mod ambiguity;
+mod closures;
+mod correction;
+mod features;
+mod fixes;
 pub(crate) mod tree;
+mod versions;
+pub mod weights;
 
 use std::time::Instant;
 
 use anyhow::Result;
-use argus_ext::ty::EvaluationResultExt;
+use argus_ext::ty::{EvaluationResultExt, TyCtxtExt};
 use index_vec::IndexVec;
-use rustc_infer::traits::solve::GoalSource;
+use rustc_hir::def_id::DefId;
+use rustc_infer::{infer::InferCtxt, traits::solve::GoalSource};
+use rustc_span::Span;
 use rustc_trait_selection::solve::inspect::{InspectCandidate, InspectGoal};
 use rustc_utils::timer;
 use serde::Serialize;
 #[cfg(feature = "testing")]
 use ts_rs::TS;
 
-use crate::proof_tree::{topology::TreeTopology, ProofNodeIdx};
+use crate::proof_tree::{topology::TreeTopology, CandidateIdx, ProofNodeIdx};
+
+/// Number of correction sets reported when `ARGUS_MAX_CORRECTION_SETS` isn't set.
+const DEFAULT_MAX_SETS: usize = 32;
 
 pub struct Storage<'tcx> {
   pub ns: IndexVec<ProofNodeIdx, tree::N<'tcx>>,
   maybe_ambiguous: bool,
   report_performance: bool,
+  max_sets: usize,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -27,15 +39,29 @@ pub struct Storage<'tcx> {
 #[cfg_attr(feature = "testing", ts(export))]
 pub struct AnalysisResults {
   pub problematic_sets: Vec<tree::SetHeuristic>,
+
+  /// Why the root goal is ambiguous, only present for ambiguous trees.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "Ambiguity | undefined"))]
+  pub ambiguity: Option<ambiguity::Ambiguity>,
+
+  /// The correction-set search, rerun with other weights while tuning.
+  #[serde(skip)]
+  pub replay: weights::Replay,
 }
 
 impl<'tcx> Storage<'tcx> {
   pub fn new(maybe_ambiguous: bool) -> Self {
     let report_performance = std::env::var("ARGUS_DNF_PERF").is_ok();
+    let max_sets = std::env::var("ARGUS_MAX_CORRECTION_SETS")
+      .ok()
+      .and_then(|v| v.parse().ok())
+      .unwrap_or(DEFAULT_MAX_SETS);
     Self {
       ns: IndexVec::new(),
       maybe_ambiguous,
       report_performance,
+      max_sets,
     }
   }
 
@@ -70,22 +96,28 @@ impl<'tcx> Storage<'tcx> {
     goal: &InspectGoal<'_, 'tcx>,
     candidate: &InspectCandidate<'_, 'tcx>,
   ) -> Result<()> {
-    let retain = (self.maybe_ambiguous && candidate.result().is_ok())
-      || goal.infcx().probe(|_| {
-        candidate
-          .instantiate_nested_goals(rustc_span::DUMMY_SP)
-          .iter()
-          .any(|nested_goal| {
-            matches!(
-              nested_goal.source(),
-              GoalSource::ImplWhereBound | GoalSource::InstantiateHigherRanked
-            ) && if self.maybe_ambiguous {
-              nested_goal.result().is_maybe()
-            } else {
-              nested_goal.result().is_no()
-            }
-          })
-      });
+    // Impls marked `#[diagnostic::do_not_recommend]` are opaque, the goal
+    // they were considered for is blamed instead of their where-clauses.
+    let do_not_recommend =
+      goal.infcx().tcx.is_annotated_do_not_recommend(candidate);
+    let retain = !do_not_recommend
+      && ((self.maybe_ambiguous && candidate.result().is_ok())
+        || goal.infcx().probe(|_| {
+          candidate
+            .instantiate_nested_goals(rustc_span::DUMMY_SP)
+            .iter()
+            .any(|nested_goal| {
+              matches!(
+                nested_goal.source(),
+                GoalSource::ImplWhereBound
+                  | GoalSource::InstantiateHigherRanked
+              ) && if self.maybe_ambiguous {
+                nested_goal.result().is_maybe()
+              } else {
+                nested_goal.result().is_no()
+              }
+            })
+        }));
 
     let new_idx = self.ns.push(tree::N::C {
       kind: candidate.kind(),
@@ -108,18 +140,36 @@ impl<'tcx> Storage<'tcx> {
     self,
     root: ProofNodeIdx,
     topo: &TreeTopology,
+    body_owner: DefId,
+    obligation_span: Span,
+    intern_impl: impl FnMut(&InferCtxt<'tcx>, DefId) -> CandidateIdx,
   ) -> AnalysisResults {
-    let tree =
-      &tree::T::new(root, &self.ns, topo, false, self.report_performance);
+    let tree = &tree::T::new(
+      root,
+      &self.ns,
+      topo,
+      false,
+      body_owner,
+      obligation_span,
+      self.report_performance,
+    );
     let tree_start = Instant::now();
 
-    let mut sets = vec![];
-    tree.for_correction_set(|conjunct| sets.push(tree.weight(conjunct)));
+    let (sets, replay) = tree.correction_sets(self.max_sets);
+    let sets = sets.iter().map(|set| tree.weight(set)).collect();
+
+    let ambiguity = if self.maybe_ambiguous {
+      ambiguity::explain(tree, intern_impl)
+    } else {
+      None
+    };
 
     timer::elapsed("aadeg::into_results", tree_start);
 
     AnalysisResults {
       problematic_sets: sets,
+      ambiguity,
+      replay,
     }
   }
 }
@@ -1,22 +1,45 @@
-use std::{cell::RefCell, ops::Deref, time::Instant};
+use std::{
+  cell::RefCell,
+  collections::{HashMap, HashSet},
+  time::Instant,
+};
 
-use argus_ext::ty::{EvaluationResultExt, TyCtxtExt, TyExt};
+use argus_ext::{
+  infer::InferCtxtExt,
+  ty::{EvaluationResultExt, TyCtxtExt, TyExt},
+};
 use index_vec::IndexVec;
-use rustc_infer::infer::InferCtxt;
+use rustc_hir::{def_id::DefId, LangItem};
+use rustc_infer::{
+  infer::{DefineOpaqueTypes, InferCtxt},
+  traits::ObligationCause,
+};
 use rustc_middle::{
   traits::solve::{CandidateSource, Goal as RGoal},
-  ty::{self, TyCtxt},
+  ty::{self, TyCtxt, TypeFoldable, TypeVisitableExt},
 };
-use rustc_trait_selection::solve::inspect::ProbeKind;
-use rustc_utils::timer;
+use rustc_span::{Span, DUMMY_SP};
+use rustc_trait_selection::{
+  solve::inspect::ProbeKind,
+  traits::{orphan_check_trait_ref, InCrate, OrphanCheckMode},
+};
+use rustc_utils::{source_map::range::CharRange, timer};
 use serde::Serialize;
 #[cfg(feature = "testing")]
 use ts_rs::TS;
 
-use super::dnf::{And, Dnf};
+use super::{
+  closures::{self, ClosureSignature, ExpectedSig},
+  correction::{Problem, Set},
+  features::{self, FeatureSuggestion},
+  fixes::{self, Fix},
+  versions::{self, VersionMismatch},
+  weights::{Leaf, Replay, Weights},
+};
 use crate::{
   analysis::EvaluationResult,
-  proof_tree::{topology::TreeTopology, ProofNodeIdx},
+  proof_tree::{self, topology::TreeTopology, ProofNodeIdx},
+  types::ObligationHash,
 };
 
 pub type I = ProofNodeIdx;
@@ -28,7 +51,11 @@ pub type I = ProofNodeIdx;
 pub struct SetHeuristic {
   pub momentum: usize,
   pub velocity: usize,
-  goals: Vec<Heuristic>,
+  /// Combination of momentum and velocity the sets are ranked by.
+  pub inertia: usize,
+  pub(super) goals: Vec<Heuristic>,
+  /// Dependency features that would enable an impl for one of the goals.
+  features: Vec<FeatureSuggestion>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -37,14 +64,21 @@ pub struct SetHeuristic {
 #[cfg_attr(feature = "testing", ts(export))]
 pub struct Heuristic {
   idx: I,
-  kind: GoalKind,
+  pub(super) kind: GoalKind,
+  /// Hash of the goal's predicate, computed as for obligations.
+  pub(super) hash: ObligationHash,
+  /// Whether the goal's trait has an author-provided diagnostic, as
+  /// surfaced with the goal.
+  pub(super) on_unimplemented: bool,
+  /// Source edits that would fix the goal, cheapest first.
+  fixes: Vec<Fix>,
 }
 
 #[derive(Serialize, Debug, Clone)]
 #[serde(tag = "type")]
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
-enum Location {
+pub(super) enum Location {
   Local,
   External,
 }
@@ -53,56 +87,81 @@ enum Location {
 #[serde(tag = "type")]
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
-enum GoalKind {
-  Trait { _self: Location, _trait: Location },
+pub(super) enum GoalKind {
+  Trait {
+    _self: Location,
+    _trait: Location,
+  },
   TyChange,
-  FnToTrait { _trait: Location, arity: usize },
-  TyAsCallable { arity: usize },
-  DeleteFnParams { delta: usize },
-  AddFnParams { delta: usize },
+  /// The trait or self type comes from another version of its crate than
+  /// the one that would satisfy the goal.
+  DuplicateCrate {
+    mismatch: VersionMismatch,
+  },
+  /// The orphan rules forbid implementing the trait for the self type in
+  /// this crate, it has to be wrapped in a local type instead.
+  Newtype,
+  FnToTrait {
+    _trait: Location,
+    arity: usize,
+    /// How the closure's signature was inferred, compared with the
+    /// signature of the `Fn*` bound the trait's impl requires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "ClosureSignature | undefined"))]
+    signature: Option<ClosureSignature>,
+    /// Parameters blamed against the `Fn*` bound the trait's impl requires.
+    params: Vec<ParamBlame>,
+  },
+  TyAsCallable {
+    /// Number of arguments of the bound, unknown while they're inferred.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "number | undefined"))]
+    arity: Option<usize>,
+    /// How the signature of a closure in the self type was inferred,
+    /// compared with the signature of the bound.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "ClosureSignature | undefined"))]
+    signature: Option<ClosureSignature>,
+  },
+  DeleteFnParams {
+    delta: usize,
+  },
+  AddFnParams {
+    delta: usize,
+  },
   // Represents a function with the correct number of parameters,
   // but the parameters trait bounds or types are unsatisifed.
-  // TODO if it's worth the extra effort, we could figure out which
-  // parameters are incorrect and highlight them to the user.
-  IncorrectParams { arity: usize },
+  IncorrectParams {
+    arity: usize,
+    params: Vec<ParamBlame>,
+  },
   Misc,
 }
 
-impl GoalKind {
-  fn weight(&self) -> usize {
-    use GoalKind as GK;
-    use Location::{External as E, Local as L};
-    match self {
-      GK::Trait {
-        _self: L,
-        _trait: L,
-      } => 0,
-
-      GK::Trait {
-        _self: L,
-        _trait: E,
-      }
-      | GK::Trait {
-        _self: E,
-        _trait: L,
-      }
-      | GK::FnToTrait { _trait: L, .. } => 1,
-
-      GK::Trait {
-        _self: E,
-        _trait: E,
-      } => 2,
-
-      GK::TyChange => 4,
-      GK::IncorrectParams { arity: delta }
-      | GK::AddFnParams { delta }
-      | GK::DeleteFnParams { delta } => 5 * delta,
-      GK::FnToTrait { _trait: E, arity }
-      // You could implement the unstable Fn traits for a type,
-      // we could thens suggest this if there's nothing else better.
-      | GK::TyAsCallable { arity } => 4 + 5 * arity,
-      GK::Misc => 50,
-    }
+/// A function parameter blamed for an `IncorrectParams` or `FnToTrait`
+/// failure.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub(super) struct ParamBlame {
+  position: usize,
+
+  /// Range of the parameter in the function signature, if it's local.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "CharRange | undefined"))]
+  range: Option<CharRange>,
+
+  /// The failed goal bounding the parameter, if the tree contains one.
+  /// Parameters without a goal don't unify with the expected type.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "ProofNodeIdx | undefined"))]
+  goal: Option<I>,
+}
+
+impl Heuristic {
+  pub(super) fn weight(&self, weights: &Weights) -> usize {
+    weights.goal(&self.kind, self.on_unimplemented)
   }
 }
 
@@ -128,11 +187,11 @@ impl From<&Goal<'_, '_>> for I {
 }
 
 impl<'a, 'tcx> Goal<'a, 'tcx> {
+  /// Candidates of the goal, those of the original for shared goals.
   fn all_candidates(&self) -> impl Iterator<Item = Candidate<'a, 'tcx>> + '_ {
-    self
-      .tree
-      .topology
-      .children(self.idx)
+    let topology = self.tree.topology;
+    topology
+      .children(topology.resolve(self.idx))
       .filter_map(move |i| self.tree.candidate(i))
   }
 
@@ -142,10 +201,37 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
     self.all_candidates().filter(|c| c.retain)
   }
 
+  pub fn idx(&self) -> I {
+    self.idx
+  }
+
+  pub fn result(&self) -> EvaluationResult {
+    self.result
+  }
+
+  pub fn infcx(&self) -> &'a InferCtxt<'tcx> {
+    self.infcx
+  }
+
   pub fn predicate(&self) -> ty::Predicate<'tcx> {
     self.goal.predicate
   }
 
+  pub fn param_env(&self) -> ty::ParamEnv<'tcx> {
+    self.goal.param_env
+  }
+
+  fn heuristic(&self, kind: GoalKind) -> Heuristic {
+    let predicate = self.infcx.resolve_vars_if_possible(self.predicate());
+    Heuristic {
+      idx: self.idx,
+      kind,
+      hash: self.infcx.predicate_hash(&predicate).into(),
+      on_unimplemented: self.has_on_unimplemented(),
+      fixes: vec![],
+    }
+  }
+
   pub fn last_ancestor_pre_builtin(&self) -> Self {
     let mut i = self.idx;
     let tree = self.tree;
@@ -172,9 +258,8 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
     tree.goal(i).expect("invalid ancestor")
   }
 
+  #[allow(clippy::too_many_lines)]
   fn analyze(&self) -> Heuristic {
-    use std::cmp::Ordering;
-
     // We should only be analyzing failed predicates
     assert!(!self.result.is_yes());
 
@@ -182,6 +267,12 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
 
     let tcx = self.infcx.tcx;
 
+    // Items from two versions of a crate are unrelated to the solver, the
+    // goal only looks like an ordinary failure.
+    if let Some(mismatch) = self.version_mismatch() {
+      return self.heuristic(GoalKind::DuplicateCrate { mismatch });
+    }
+
     let kind = match self.predicate().kind().skip_binder() {
       ty::PredicateKind::Clause(ty::ClauseKind::Trait(t))
         if t.polarity == ty::PredicatePolarity::Positive
@@ -189,20 +280,29 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
           && tcx.function_arity(t.self_ty()).is_some() =>
       {
         let fn_arity = tcx.function_arity(t.self_ty()).unwrap();
-        let trait_arity = tcx.fn_trait_arity(t).unwrap_or(usize::MAX);
+        let trait_arity = tcx.fn_trait_arity(t);
 
         log::debug!("FnSigs\n{:?}\n{:?}", t.self_ty(), t.trait_ref);
         log::debug!("Fn Args {:?}", t.trait_ref.args.into_type_list(tcx));
-        log::debug!("{fn_arity} v {trait_arity}");
-
-        match fn_arity.cmp(&trait_arity) {
-          Ordering::Less => GoalKind::AddFnParams {
-            delta: trait_arity - fn_arity,
+        log::debug!("{fn_arity} v {trait_arity:?}");
+
+        // Arguments that aren't inferred yet can't be counted, only the
+        // parameters can be changed.
+        match trait_arity {
+          Some(trait_arity) if fn_arity < trait_arity => {
+            GoalKind::AddFnParams {
+              delta: trait_arity - fn_arity,
+            }
+          }
+          Some(trait_arity) if fn_arity > trait_arity => {
+            GoalKind::DeleteFnParams {
+              delta: fn_arity - trait_arity,
+            }
+          }
+          _ => GoalKind::IncorrectParams {
+            arity: fn_arity,
+            params: self.blame_params(t),
           },
-          Ordering::Greater => GoalKind::DeleteFnParams {
-            delta: fn_arity - trait_arity,
-          },
-          Ordering::Equal => GoalKind::IncorrectParams { arity: fn_arity },
         }
       }
 
@@ -211,8 +311,10 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
         if t.polarity == ty::PredicatePolarity::Positive
           && tcx.is_fn_trait(t.def_id()) =>
       {
-        let trait_arity = tcx.fn_trait_arity(t).unwrap_or(usize::MAX);
-        GoalKind::TyAsCallable { arity: trait_arity }
+        GoalKind::TyAsCallable {
+          arity: tcx.fn_trait_arity(t),
+          signature: self.callable_signature(t),
+        }
       }
 
       // Self type is a function type but the trait isn't
@@ -230,9 +332,21 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
         GoalKind::FnToTrait {
           _trait: location,
           arity: fn_arity,
+          signature: self.bound_signature(t),
+          params: self.blame_bound_params(t),
         }
       }
 
+      // The trait can't be implemented for the self type here. Type
+      // parameters are excluded, they're constrained with a where-clause.
+      ty::PredicateKind::Clause(ty::ClauseKind::Trait(t))
+        if t.polarity == ty::PredicatePolarity::Positive
+          && !matches!(t.self_ty().peel_refs().kind(), ty::Param(..))
+          && !impl_is_allowed(self.infcx, t) =>
+      {
+        GoalKind::Newtype
+      }
+
       ty::PredicateKind::Clause(ty::ClauseKind::Trait(t))
         if t.polarity == ty::PredicatePolarity::Positive =>
       {
@@ -241,26 +355,17 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
         let ty = t.self_ty();
         let def_id = t.def_id();
 
-        let def_id_local = def_id.is_local();
-        let ty_local = ty.is_local();
+        let location = |is_local| {
+          if is_local {
+            Location::Local
+          } else {
+            Location::External
+          }
+        };
 
-        match (ty_local, def_id_local) {
-          (true, true) => GoalKind::Trait {
-            _self: Location::Local,
-            _trait: Location::Local,
-          },
-          (true, false) => GoalKind::Trait {
-            _self: Location::Local,
-            _trait: Location::External,
-          },
-          (false, true) => GoalKind::Trait {
-            _self: Location::External,
-            _trait: Location::Local,
-          },
-          (false, false) => GoalKind::Trait {
-            _self: Location::External,
-            _trait: Location::External,
-          },
+        GoalKind::Trait {
+          _self: location(ty.is_local()),
+          _trait: location(def_id.is_local()),
         }
       }
 
@@ -283,10 +388,328 @@ impl<'a, 'tcx> Goal<'a, 'tcx> {
       | ty::PredicateKind::Ambiguous => GoalKind::Misc,
     };
 
-    Heuristic {
-      idx: self.idx,
-      kind,
+    self.heuristic(kind)
+  }
+
+  /// Parameters of the function self type of the `Fn*` predicate `t`, the
+  /// goal's, that don't unify with the expected inputs or whose bound in
+  /// the impl requiring the goal fails.
+  fn blame_params(&self, t: ty::TraitPredicate<'tcx>) -> Vec<ParamBlame> {
+    let infcx = self.infcx;
+    let tcx = infcx.tcx;
+
+    let self_ty = infcx.resolve_vars_if_possible(t.self_ty()).peel_refs();
+    let (Some(inputs), ty::Tuple(expected)) = (
+      function_inputs(tcx, self_ty),
+      infcx
+        .resolve_vars_if_possible(t.trait_ref.args.type_at(1))
+        .kind(),
+    ) else {
+      return vec![];
+    };
+    if inputs.len() != expected.len() {
+      return vec![];
     }
+
+    let failed_bounds = self.failed_bounds_by_position(t);
+    let ranges = function_param_spans(tcx, self_ty);
+    let source_map = tcx.sess.source_map();
+
+    inputs
+      .iter()
+      .zip(expected.iter())
+      .enumerate()
+      .filter_map(|(position, (&input, expected))| {
+        let goal = failed_bounds.get(position).copied().flatten();
+
+        // Regions don't affect whether a parameter is blamed.
+        let unifies = infcx.probe(|_| {
+          let [input, expected] = [input, expected].map(|ty| {
+            ty::fold::fold_regions(tcx, ty, |_, _| tcx.lifetimes.re_static)
+          });
+          infcx
+            .at(&ObligationCause::dummy(), self.goal.param_env)
+            .eq(DefineOpaqueTypes::No, input, expected)
+            .is_ok()
+        });
+
+        (goal.is_some() || !unifies).then(|| ParamBlame {
+          position,
+          range: ranges
+            .get(position)
+            .and_then(|&span| CharRange::from_span(span, source_map).ok()),
+          goal,
+        })
+      })
+      .collect()
+  }
+
+  /// For each input of the `Fn*` predicate `t`, the goal's, a failed
+  /// sibling goal from a where-clause bounding that input in the impl
+  /// that required the goal.
+  ///
+  /// Bounds are found by their position in the impl, e.g., `T2: FromRequest`
+  /// for the second input of `F: Fn(T1, T2)`, so parameters of the same type
+  /// aren't blamed for each other's bounds.
+  fn failed_bounds_by_position(
+    &self,
+    t: ty::TraitPredicate<'tcx>,
+  ) -> Vec<Option<I>> {
+    let infcx = self.infcx;
+    let tcx = infcx.tcx;
+    let topology = self.tree.topology;
+
+    let Some(candidate) = topology
+      .parent(self.idx)
+      .and_then(|i| self.tree.candidate(i))
+    else {
+      return vec![];
+    };
+    let ProbeKind::TraitCandidate {
+      source: CandidateSource::Impl(impl_def_id),
+      ..
+    } = *candidate.kind
+    else {
+      return vec![];
+    };
+    let Some(implemented) = topology
+      .parent(candidate.idx)
+      .and_then(|i| self.tree.goal(i))
+      .and_then(|goal| goal.predicate().as_trait_clause())
+      .and_then(ty::Binder::no_bound_vars)
+    else {
+      return vec![];
+    };
+
+    let fold = |t: ty::TraitPredicate<'tcx>| {
+      let t = infcx.resolve_vars_if_possible(t);
+      ty::fold::fold_regions(tcx, t, |_, _| tcx.lifetimes.re_static)
+    };
+    let failed = candidate
+      .all_subgoals()
+      .filter(|goal| goal.result.is_no())
+      .filter_map(|goal| {
+        let t = goal.predicate().as_trait_clause()?.no_bound_vars()?;
+        let t = goal.infcx.resolve_vars_if_possible(t);
+        Some((
+          ty::fold::fold_regions(tcx, t, |_, _| tcx.lifetimes.re_static),
+          goal.idx,
+        ))
+      })
+      .collect::<Vec<_>>();
+
+    let where_clauses = tcx
+      .predicates_of(impl_def_id)
+      .instantiate_identity(tcx)
+      .predicates
+      .into_iter()
+      .filter_map(|clause| clause.as_trait_clause()?.no_bound_vars())
+      .collect::<Vec<_>>();
+
+    infcx
+      .probe(|_| {
+        let args = infcx.fresh_args_for_item(DUMMY_SP, impl_def_id);
+        let instantiate = |clause: ty::TraitPredicate<'tcx>| {
+          fold(ty::EarlyBinder::bind(clause).instantiate(tcx, args))
+        };
+        let eq = |a: ty::TraitPredicate<'tcx>, b: ty::TraitPredicate<'tcx>| {
+          infcx
+            .at(&ObligationCause::dummy(), self.goal.param_env)
+            .eq(DefineOpaqueTypes::No, a.trait_ref, b.trait_ref)
+            .is_ok()
+        };
+
+        // The impl is instantiated as it was for the goal it implements,
+        // and its `Fn*` bound as the goal.
+        let impl_trait_ref =
+          tcx.impl_trait_ref(impl_def_id)?.instantiate(tcx, args);
+        let declared = ty::TraitPredicate {
+          trait_ref: impl_trait_ref,
+          polarity: implemented.polarity,
+        };
+        if !eq(fold(declared), fold(implemented)) {
+          return None;
+        }
+        let bound = where_clauses.iter().find(|clause| {
+          clause.def_id() == t.def_id()
+            && infcx.probe(|_| eq(instantiate(**clause), fold(t)))
+        })?;
+        if !eq(instantiate(*bound), fold(t)) {
+          return None;
+        }
+
+        let ty::Tuple(inputs) = bound.trait_ref.args.type_at(1).kind() else {
+          return None;
+        };
+        Some(
+          inputs
+            .iter()
+            .map(|input| {
+              where_clauses
+                .iter()
+                .filter(|clause| clause != &bound && clause.self_ty() == input)
+                .find_map(|clause| {
+                  let clause = instantiate(*clause);
+                  failed
+                    .iter()
+                    .find_map(|(goal, idx)| (*goal == clause).then_some(*idx))
+                })
+            })
+            .collect(),
+        )
+      })
+      .unwrap_or_default()
+  }
+
+  /// Parameters of the function self type blamed against the `Fn*` bound
+  /// below the goal, usually from the where-clauses of a blanket impl.
+  fn blame_bound_params(&self, t: ty::TraitPredicate<'tcx>) -> Vec<ParamBlame> {
+    let tcx = self.infcx.tcx;
+    let self_ty = self.infcx.resolve_vars_if_possible(t.self_ty()).peel_refs();
+    self
+      .tree
+      .goals_below(self.idx)
+      .into_iter()
+      .find_map(|goal| {
+        let bound = goal.predicate().as_trait_clause()?.no_bound_vars()?;
+        let bound_self = goal.infcx.resolve_vars_if_possible(bound.self_ty());
+        (tcx.is_fn_trait(bound.def_id())
+          && bound_self.peel_refs() == self_ty
+          && tcx.fn_trait_arity(bound) == tcx.function_arity(self_ty))
+        .then(|| goal.blame_params(bound))
+      })
+      .unwrap_or_default()
+  }
+
+  /// Signature of the closure in the self type, which isn't callable
+  /// itself, e.g., a closure behind a wrapper, against the goal's `Fn*`
+  /// trait.
+  fn callable_signature(
+    &self,
+    t: ty::TraitPredicate<'tcx>,
+  ) -> Option<ClosureSignature> {
+    let self_ty = self.infcx.resolve_vars_if_possible(t.self_ty());
+    let closure = self_ty
+      .walk()
+      .filter_map(ty::GenericArg::as_type)
+      .find(|ty| matches!(ty.kind(), ty::Closure(..)))?;
+
+    let expected = self.expected_sig(t, self.idx, self_ty);
+    closures::trace(self.infcx, self.param_env(), closure, expected.as_ref())
+  }
+
+  /// Signature of the closure self type against the `Fn*` bound below the
+  /// goal, usually from the where-clauses of a blanket impl.
+  fn bound_signature(
+    &self,
+    t: ty::TraitPredicate<'tcx>,
+  ) -> Option<ClosureSignature> {
+    let closure = self.infcx.resolve_vars_if_possible(t.self_ty()).peel_refs();
+    let ty::Closure(def_id, _) = closure.kind() else {
+      return None;
+    };
+
+    let tcx = self.infcx.tcx;
+    let expected =
+      self
+        .tree
+        .goals_below(self.idx)
+        .into_iter()
+        .find_map(|goal| {
+          let bound = goal.predicate().as_trait_clause()?.skip_binder();
+          let bound_self = goal.infcx.resolve_vars_if_possible(bound.self_ty());
+          let is_closure = matches!(
+            bound_self.peel_refs().kind(),
+            ty::Closure(other, _) if other == def_id
+          );
+          (tcx.is_fn_trait(bound.def_id()) && is_closure)
+            .then(|| goal.expected_sig(bound, self.idx, bound_self))
+            .flatten()
+        });
+
+    closures::trace(self.infcx, self.param_env(), closure, expected.as_ref())
+  }
+
+  /// The inputs expected by the `Fn*` trait predicate `t`, and its output if
+  /// a projection of `self_ty` below `root` constrains it.
+  fn expected_sig(
+    &self,
+    t: ty::TraitPredicate<'tcx>,
+    root: I,
+    self_ty: ty::Ty<'tcx>,
+  ) -> Option<ExpectedSig<'tcx>> {
+    let tcx = self.infcx.tcx;
+    let inputs = self
+      .infcx
+      .resolve_vars_if_possible(t.trait_ref.args.type_at(1));
+    let ty::Tuple(inputs) = inputs.kind() else {
+      return None;
+    };
+    if inputs.has_non_region_infer() {
+      return None;
+    }
+
+    let output = self.tree.goals_below(root).into_iter().find_map(|goal| {
+      let projection = goal.predicate().as_projection_clause()?.skip_binder();
+      let projection = goal.infcx.resolve_vars_if_possible(projection);
+      (tcx.is_lang_item(
+        projection.projection_term.def_id,
+        LangItem::FnOnceOutput,
+      ) && projection.self_ty() == self_ty)
+        .then(|| projection.term.as_type())
+        .flatten()
+        .filter(|output| !output.has_non_region_infer())
+    });
+
+    Some(ExpectedSig {
+      inputs: inputs.to_vec(),
+      output,
+    })
+  }
+
+  /// A duplicate crate version behind the failed trait goal, searched once
+  /// per goal as the goal is analyzed for every set it's in.
+  fn version_mismatch(&self) -> Option<VersionMismatch> {
+    if !self.result.is_no() || self.predicate().as_trait_clause().is_none() {
+      return None;
+    }
+
+    self
+      .tree
+      .version_mismatches
+      .borrow_mut()
+      .entry(self.idx)
+      .or_insert_with(|| {
+        versions::find_mismatch(self.infcx, self.param_env(), self.predicate())
+      })
+      .clone()
+  }
+
+  /// Whether the goal has the `on_unimplemented` note the proof tree
+  /// surfaces for it, memoized like the version mismatches.
+  fn has_on_unimplemented(&self) -> bool {
+    *self
+      .tree
+      .on_unimplemented
+      .borrow_mut()
+      .entry(self.idx)
+      .or_insert_with(|| {
+        proof_tree::on_unimplemented(self.infcx, self.goal, self.result)
+          .is_some()
+      })
+  }
+
+  fn fixes(&self, kind: &GoalKind) -> Vec<Fix> {
+    // Only the root goal's values are found at the obligation.
+    let obligation_span =
+      (self.idx == self.tree.root).then_some(self.tree.obligation_span);
+    fixes::suggest(
+      self.infcx,
+      self.tree.body_owner,
+      self.predicate(),
+      kind,
+      obligation_span,
+    )
   }
 }
 
@@ -339,8 +762,14 @@ pub struct T<'a, 'tcx: 'a> {
   pub ns: &'a IndexVec<I, N<'tcx>>,
   pub topology: &'a TreeTopology,
   pub maybe_ambiguous: bool,
+  body_owner: DefId,
+  obligation_span: Span,
   report_performance: bool,
-  dnf: RefCell<Option<Dnf<I>>>,
+  weights: Weights,
+  /// Memoized `versions::find_mismatch` of each analyzed goal.
+  version_mismatches: RefCell<HashMap<I, Option<VersionMismatch>>>,
+  /// Memoized presence of each analyzed goal's `on_unimplemented` note.
+  on_unimplemented: RefCell<HashMap<I, bool>>,
 }
 
 impl<'a, 'tcx: 'a> T<'a, 'tcx> {
@@ -349,6 +778,8 @@ impl<'a, 'tcx: 'a> T<'a, 'tcx> {
     ns: &'a IndexVec<I, N<'tcx>>,
     topology: &'a TreeTopology,
     maybe_ambiguous: bool,
+    body_owner: DefId,
+    obligation_span: Span,
     report_performance: bool,
   ) -> Self {
     Self {
@@ -356,15 +787,17 @@ impl<'a, 'tcx: 'a> T<'a, 'tcx> {
       ns,
       topology,
       maybe_ambiguous,
+      body_owner,
+      obligation_span,
       report_performance,
-      dnf: RefCell::new(None),
+      weights: Weights::configured(),
+      version_mismatches: RefCell::default(),
+      on_unimplemented: RefCell::default(),
     }
   }
 
-  pub fn for_correction_set(&self, mut f: impl FnMut(&And<I>)) {
-    for and in self.dnf().iter_conjuncts() {
-      f(and);
-    }
+  pub fn body_owner(&self) -> DefId {
+    self.body_owner
   }
 
   pub fn goal(&self, i: I) -> Option<Goal<'_, 'tcx>> {
@@ -401,52 +834,103 @@ impl<'a, 'tcx: 'a> T<'a, 'tcx> {
     }
   }
 
-  fn expect_dnf(&self) -> impl Deref<Target = Dnf<I>> + '_ {
-    use std::cell::Ref;
-    Ref::map(self.dnf.borrow(), |o| o.as_ref().expect("dnf"))
+  /// Goals in the subtree of `root`, in breadth-first order. Shared
+  /// subtrees are followed, but each goal is visited once.
+  fn goals_below(&self, root: I) -> Vec<Goal<'_, 'tcx>> {
+    let mut queue = std::collections::VecDeque::from([root]);
+    let mut visited = HashSet::new();
+    let mut goals = vec![];
+    while let Some(idx) = queue.pop_front() {
+      if !visited.insert(idx) {
+        continue;
+      }
+      queue.extend(self.topology.children(self.topology.resolve(idx)));
+      if let Some(goal) = self.goal(idx) {
+        if idx != root {
+          goals.push(goal);
+        }
+      }
+    }
+
+    goals
   }
 
-  pub fn dnf(&self) -> impl Deref<Target = Dnf<I>> + '_ {
-    fn goal_(this: &T, goal: &Goal) -> Option<Dnf<I>> {
+  /// Search for the `limit` cheapest minimal correction sets of the root,
+  /// along with the recorded search to rerun it with other weights.
+  pub fn correction_sets(&self, limit: usize) -> (Vec<Set<I>>, Replay) {
+    fn goal_(
+      this: &T,
+      problem: &mut Problem<Leaf>,
+      memo: &mut HashMap<I, Option<usize>>,
+      goal: &Goal,
+    ) -> Option<usize> {
       if !((this.maybe_ambiguous && goal.result.is_maybe())
         || goal.result.is_no())
       {
         return None;
       }
 
-      let candidates = goal.interesting_candidates();
-      let nested = candidates
-        .filter_map(|c| candidate_(this, &c))
+      // Shared subtrees are only searched once.
+      if let Some(&node) = memo.get(&goal.idx) {
+        return node;
+      }
+
+      let nested = goal
+        .interesting_candidates()
+        .filter_map(|c| candidate_(this, problem, memo, &c))
         .collect::<Vec<_>>();
 
-      if nested.is_empty() {
-        return Dnf::single(goal.idx).into();
-      }
+      let node = if nested.is_empty() {
+        let depth = this.topology.depth(goal.idx);
+        problem.leaf(Leaf::new(goal.idx, &goal.analyze(), depth))
+      } else {
+        problem.or(nested)
+      };
 
-      Dnf::or(nested.into_iter())
+      memo.insert(goal.idx, Some(node));
+      Some(node)
     }
 
-    fn candidate_(this: &T, candidate: &Candidate) -> Option<Dnf<I>> {
+    fn candidate_(
+      this: &T,
+      problem: &mut Problem<Leaf>,
+      memo: &mut HashMap<I, Option<usize>>,
+      candidate: &Candidate,
+    ) -> Option<usize> {
       if candidate.result.is_yes() {
         return None;
       }
 
-      let goals = candidate.source_subgoals();
-      Dnf::and(goals.filter_map(|g| goal_(this, &g)))
-    }
+      let goals = candidate
+        .source_subgoals()
+        .filter_map(|g| goal_(this, problem, memo, &g))
+        .collect::<Vec<_>>();
 
-    if self.dnf.borrow().is_some() {
-      return self.expect_dnf();
+      (!goals.is_empty()).then(|| problem.and(goals))
     }
 
-    let dnf_report_msg =
-      format!("Normalizing to DNF from {} nodes", self.ns.len());
-    let dnf_start = Instant::now();
+    let report_msg =
+      format!("Searching correction sets from {} nodes", self.ns.len());
+    let start = Instant::now();
 
+    let mut problem = Problem::default();
     let root = self.goal(self.root).expect("invalid root");
-    let dnf = goal_(self, &root).unwrap_or_else(Dnf::default);
+    goal_(self, &mut problem, &mut HashMap::default(), &root);
+    let replay = Replay::new(problem, limit);
+    let sets = replay
+      .sets(&self.weights)
+      .iter()
+      .map(|set| {
+        let mut goals = set
+          .iter()
+          .map(|&i| replay.leaves()[i].goal)
+          .collect::<Vec<_>>();
+        goals.sort_unstable();
+        goals.into()
+      })
+      .collect();
 
-    timer::elapsed(&dnf_report_msg, dnf_start);
+    timer::elapsed(&report_msg, start);
 
     // HACK to gather the performance report we write to stderr the CSV values `PERF<NODES><TIME>`
     // The testing harness will take the stderr output and place it in a file for analysis.
@@ -454,12 +938,11 @@ impl<'a, 'tcx: 'a> T<'a, 'tcx> {
       eprintln!(
         "PERF,{:?},{:.04}",
         self.ns.len(),
-        dnf_start.elapsed().as_secs_f64()
+        start.elapsed().as_secs_f64()
       );
     }
 
-    self.dnf.replace(Some(dnf));
-    self.expect_dnf()
+    (sets, replay)
   }
 
   /// Failed predicates are weighted as follows.
@@ -474,22 +957,38 @@ impl<'a, 'tcx: 'a> T<'a, 'tcx> {
   /// Non-intrusive changes:
   ///
   /// A local type failing to implement a trait (local/external).
-  /// NOTE that `T: C` where `T` is an external type is considered impossible
-  /// to change, if this is the only option a relaxed rule might suggest
-  /// creating a wrapper for the type.
+  /// NOTE that `T: C` where the orphan rules forbid implementing `C` for `T`
+  /// is impossible to change directly, a wrapper for the type is suggested
+  /// instead and weighed as an intrusive change.
   ///
   /// Intrusive changes
   ///
   /// Changing types. That could either be changing a type to match an
   /// alias-relate, deleting function parameters or tuple elements.
-  pub fn weight(&self, and: &And<I>) -> SetHeuristic {
-    let goals = and
+  pub fn weight(&self, set: &[I]) -> SetHeuristic {
+    let goals = set
       .iter()
-      .map(|&idx| self.goal(idx).expect("goal").analyze())
+      .map(|&idx| {
+        let goal = self.goal(idx).expect("goal");
+        let heuristic = goal.analyze();
+        Heuristic {
+          fixes: goal.fixes(&heuristic.kind),
+          ..heuristic
+        }
+      })
       .collect::<Vec<_>>();
 
-    let momentum = goals.iter().fold(0, |acc, g| acc + g.kind.weight());
-    let velocity = and
+    let features = goals
+      .iter()
+      .filter(|g| matches!(g.kind, GoalKind::Trait { .. } | GoalKind::Newtype))
+      .flat_map(|g| {
+        let goal = self.goal(g.idx).expect("goal");
+        features::suggest(goal.infcx.tcx, g.idx, goal.predicate())
+      })
+      .collect();
+
+    let momentum = goals.iter().map(|g| g.weight(&self.weights)).sum();
+    let velocity = set
       .iter()
       .map(|&idx| self.topology.depth(idx))
       .max()
@@ -498,7 +997,9 @@ impl<'a, 'tcx: 'a> T<'a, 'tcx> {
     SetHeuristic {
       momentum,
       velocity,
+      inertia: self.weights.inertia(momentum, velocity),
       goals,
+      features,
     }
   }
 }
@@ -520,3 +1021,90 @@ impl std::fmt::Debug for N<'_> {
     }
   }
 }
+
+/// Whether the orphan rules allow implementing the trait for the goal's
+/// types in this crate.
+///
+/// Generic parameters of the body would be parameters of the impl, they're
+/// replaced by inference variables which the orphan check treats as such.
+pub(super) fn impl_is_allowed<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  t: ty::TraitPredicate<'tcx>,
+) -> bool {
+  let tcx = infcx.tcx;
+  if t.def_id().is_local() {
+    return true;
+  }
+
+  infcx.probe(|_| {
+    let trait_ref = infcx.resolve_vars_if_possible(t.trait_ref);
+    let trait_ref = trait_ref.fold_with(&mut ty::fold::BottomUpFolder {
+      tcx,
+      ty_op: |ty| match ty.kind() {
+        ty::Param(..) => infcx.next_ty_var(DUMMY_SP),
+        _ => ty,
+      },
+      lt_op: |_| tcx.lifetimes.re_static,
+      ct_op: |ct| match ct.kind() {
+        ty::ConstKind::Param(..) => infcx.next_const_var(DUMMY_SP),
+        _ => ct,
+      },
+    });
+
+    matches!(
+      orphan_check_trait_ref(
+        infcx,
+        trait_ref,
+        InCrate::Local {
+          mode: OrphanCheckMode::Proper,
+        },
+        Ok::<_, ()>,
+      ),
+      Ok(Ok(()))
+    )
+  })
+}
+
+/// Input types of a callable type, with late-bound regions erased.
+fn function_inputs<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  ty: ty::Ty<'tcx>,
+) -> Option<Vec<ty::Ty<'tcx>>> {
+  let sig = match ty.kind() {
+    ty::FnDef(def_id, args) => tcx.fn_sig(def_id).instantiate(tcx, args),
+    ty::FnPtr(sig_tys, header) => sig_tys.with(*header),
+    ty::Closure(_, args) => args.as_closure().sig(),
+    _ => return None,
+  };
+
+  Some(
+    tcx
+      .instantiate_bound_regions_with_erased(sig)
+      .inputs()
+      .to_vec(),
+  )
+}
+
+/// Spans of the parameters in a local function or closure signature.
+pub(super) fn function_param_spans(
+  tcx: TyCtxt,
+  ty: ty::Ty,
+) -> Vec<rustc_span::Span> {
+  let (ty::FnDef(def_id, _) | ty::Closure(def_id, _)) = ty.kind() else {
+    return vec![];
+  };
+
+  let Some(local_id) = def_id.as_local() else {
+    return vec![];
+  };
+
+  if let Some(body) = tcx.hir_maybe_body_owned_by(local_id) {
+    return body.params.iter().map(|p| p.span).collect();
+  }
+
+  tcx
+    .hir_node_by_def_id(local_id)
+    .fn_decl()
+    .map(|decl| decl.inputs.iter().map(|ty| ty.span).collect())
+    .unwrap_or_default()
+}
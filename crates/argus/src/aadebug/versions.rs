@@ -0,0 +1,249 @@
+//! Duplicate crate versions behind unimplemented traits.
+//!
+//! When two versions of a crate are in the crate graph, a type can implement
+//! the trait from one version while the goal requires the other. To rustc
+//! these are unrelated items that only share a path.
+
+use rustc_hir::def_id::{CrateNum, DefId, LOCAL_CRATE};
+use rustc_infer::{
+  infer::InferCtxt,
+  traits::{Obligation, ObligationCause},
+};
+use rustc_middle::ty::{self, TyCtxt};
+use rustc_trait_selection::traits::query::evaluate_obligation::InferCtxtExt;
+use serde::Serialize;
+#[cfg(feature = "testing")]
+use ts_rs::TS;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct VersionMismatch {
+  pub item: DuplicatedItem,
+  /// Path of the duplicated item, e.g., `serde::Serialize`.
+  pub path: String,
+  /// The crate providing the item required by the goal.
+  pub required: CrateVersion,
+  /// The crate providing the same-named item that is implemented.
+  pub implemented: CrateVersion,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum DuplicatedItem {
+  /// The self type implements the trait from the other version.
+  Trait,
+  /// The type from the other version implements the trait.
+  SelfTy,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct CrateVersion {
+  pub name: String,
+
+  /// Version of the crate, known when its sources are in a directory
+  /// named after it, as they are for crates from a registry.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub version: Option<String>,
+
+  /// Path of the compiled crate.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub source: Option<String>,
+}
+
+/// Find a same-named trait or self type from another version of its crate
+/// that would satisfy the failed goal.
+pub(super) fn find_mismatch<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  predicate: ty::Predicate<'tcx>,
+) -> Option<VersionMismatch> {
+  let tcx = infcx.tcx;
+  let t = predicate.as_trait_clause()?;
+  let t = infcx.resolve_vars_if_possible(t);
+
+  duplicate_trait(infcx, param_env, t)
+    .map(|other| mismatch(tcx, DuplicatedItem::Trait, t.def_id(), other))
+    .or_else(|| {
+      let ty::Adt(def, _) = t.self_ty().skip_binder().kind() else {
+        return None;
+      };
+      let other = duplicate_self_ty(tcx, t.def_id(), def.did())?;
+      Some(mismatch(tcx, DuplicatedItem::SelfTy, def.did(), other))
+    })
+}
+
+fn mismatch(
+  tcx: TyCtxt,
+  item: DuplicatedItem,
+  required: DefId,
+  implemented: DefId,
+) -> VersionMismatch {
+  VersionMismatch {
+    item,
+    path: tcx.def_path_str(required),
+    required: crate_version(tcx, required.krate),
+    implemented: crate_version(tcx, implemented.krate),
+  }
+}
+
+/// The trait at the same path in another version of the goal trait's crate,
+/// if the self type implements it.
+fn duplicate_trait<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  t: ty::PolyTraitPredicate<'tcx>,
+) -> Option<DefId> {
+  let tcx = infcx.tcx;
+  let trait_def_id = t.def_id();
+  if !is_duplicated(tcx, trait_def_id.krate) {
+    return None;
+  }
+
+  tcx.all_traits().find(|&other| {
+    is_counterpart(tcx, trait_def_id, other)
+      && tcx.generics_of(other).count() == t.skip_binder().trait_ref.args.len()
+      && infcx.predicate_must_hold_modulo_regions(&Obligation::new(
+        tcx,
+        ObligationCause::dummy(),
+        param_env,
+        t.map_bound(|t| {
+          ty::TraitRef::new_from_args(tcx, other, t.trait_ref.args)
+        }),
+      ))
+  })
+}
+
+/// A type at the same path as `self_ty` in another version of its crate,
+/// if it has an impl of the trait.
+fn duplicate_self_ty(
+  tcx: TyCtxt,
+  trait_def_id: DefId,
+  self_ty: DefId,
+) -> Option<DefId> {
+  if !is_duplicated(tcx, self_ty.krate) {
+    return None;
+  }
+
+  tcx.all_impls(trait_def_id).find_map(|impl_def_id| {
+    let header = tcx.impl_trait_header(impl_def_id)?;
+    let ty::Adt(def, _) = header.trait_ref.skip_binder().self_ty().kind()
+    else {
+      return None;
+    };
+    (header.polarity == ty::ImplPolarity::Positive
+      && is_counterpart(tcx, self_ty, def.did()))
+    .then_some(def.did())
+  })
+}
+
+/// Whether another crate in the graph has the same name as `krate`.
+fn is_duplicated(tcx: TyCtxt, krate: CrateNum) -> bool {
+  let name = tcx.crate_name(krate);
+  krate != LOCAL_CRATE
+    && tcx
+      .crates(())
+      .iter()
+      .any(|&other| other != krate && tcx.crate_name(other) == name)
+}
+
+/// Whether `a` and `b` are the same item in different crates of the same name.
+fn is_counterpart(tcx: TyCtxt, a: DefId, b: DefId) -> bool {
+  a.krate != b.krate
+    && tcx.crate_name(a.krate) == tcx.crate_name(b.krate)
+    && tcx.def_path(a).data == tcx.def_path(b).data
+}
+
+fn crate_version(tcx: TyCtxt, krate: CrateNum) -> CrateVersion {
+  let name = tcx.crate_name(krate).to_string();
+  let root = tcx
+    .sess
+    .source_map()
+    .span_to_filename(tcx.def_span(krate.as_def_id()))
+    .prefer_local()
+    .to_string();
+  let version = version_from_path(&name, &root);
+
+  let source = tcx
+    .used_crate_source(krate)
+    .paths()
+    .next()
+    .map(|path| path.display().to_string());
+
+  CrateVersion {
+    name,
+    version,
+    source,
+  }
+}
+
+/// Version of the crate `name` whose root is at `root`.
+///
+/// Registry sources live in `<name>-<version>`, where the package name
+/// may use dashes in place of the crate name's underscores.
+fn version_from_path(name: &str, root: &str) -> Option<String> {
+  let package = format!("{}-", name.replace('_', "-"));
+  let crate_name = format!("{name}-");
+  root.split(['/', '\\']).find_map(|component| {
+    let version = component
+      .strip_prefix(&package)
+      .or_else(|| component.strip_prefix(&crate_name))?;
+    version
+      .starts_with(|c: char| c.is_ascii_digit())
+      .then(|| version.to_string())
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn versions_from_paths() {
+    let registry =
+      "/home/u/.cargo/registry/src/index.crates.io-6f17d22bba15001f";
+    let cases = [
+      (
+        "serde",
+        format!("{registry}/serde-1.0.219/src/lib.rs"),
+        Some("1.0.219"),
+      ),
+      // Package names use dashes, crate names underscores.
+      (
+        "serde_json",
+        format!("{registry}/serde-json-1.0.1/src/lib.rs"),
+        Some("1.0.1"),
+      ),
+      (
+        "serde_json",
+        format!("{registry}/serde_json-1.0.140/src/lib.rs"),
+        Some("1.0.140"),
+      ),
+      (
+        "rand_core",
+        r"C:\Users\u\rand_core-0.6.4-alpha.1\src\lib.rs".to_string(),
+        Some("0.6.4-alpha.1"),
+      ),
+      // Another crate with the name as a prefix, and local paths.
+      (
+        "serde",
+        format!("{registry}/serde-derive-1.0.0/src/lib.rs"),
+        None,
+      ),
+      ("serde", "/work/serde/src/lib.rs".to_string(), None),
+      ("serde", "src/lib.rs".to_string(), None),
+    ];
+
+    for (name, root, version) in cases {
+      assert_eq!(version_from_path(name, &root).as_deref(), version, "{root}");
+    }
+  }
+}
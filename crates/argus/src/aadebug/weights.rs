@@ -0,0 +1,511 @@
+//! Weights of the correction-set ranking, and their tuning.
+//!
+//! Weights are read from the JSON file named by `ARGUS_WEIGHTS`, missing
+//! fields keep their default. Tuning reruns the correction-set search of the
+//! labeled trees with each candidate, the searches are recorded while
+//! compiling so a dataset is compiled once and then searched offline.
+
+use std::{ops::RangeInclusive, path::Path, sync::LazyLock};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "testing")]
+use ts_rs::TS;
+
+use super::{
+  correction::{Problem, Set},
+  tree::{GoalKind, Heuristic, Location, I},
+};
+use crate::types::{BodyBundle, ObligationHash};
+
+static CONFIGURED: LazyLock<Weights> = LazyLock::new(|| {
+  let Ok(path) = std::env::var("ARGUS_WEIGHTS") else {
+    return Weights::default();
+  };
+  Weights::from_file(Path::new(&path)).unwrap_or_else(|e| {
+    log::error!("ignoring weights {path}: {e:?}");
+    Weights::default()
+  })
+});
+
+/// Number of coordinate descent rounds before the tuning search gives up.
+const MAX_ROUNDS: usize = 10;
+
+/// Maximum number of correction sets produced while searching, across
+/// all nodes of a tree.
+pub(super) const SEARCH_BUDGET: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct Weights {
+  /// Implementing a local trait for a local type.
+  pub local_impl: usize,
+  /// Implementing a trait where only one of the trait and type is local,
+  /// or a local trait for a function.
+  pub mixed_impl: usize,
+  /// Implementing an external trait for an external type.
+  pub external_impl: usize,
+  /// Aligning the versions of a duplicated dependency.
+  pub duplicate_crate: usize,
+  /// Wrapping a type the orphan rules forbid implementing the trait for.
+  pub newtype: usize,
+  /// Changing a type to match an alias-relate.
+  pub ty_change: usize,
+  /// Each function parameter added, deleted, or changed.
+  pub param: usize,
+  /// Making a type callable, on top of its parameters.
+  pub callable: usize,
+  /// Goals that fit no other kind.
+  pub misc: usize,
+  /// Goals of traits with `#[diagnostic::on_unimplemented]` have their
+  /// weight divided by this.
+  pub on_unimplemented_divisor: usize,
+  /// Coefficient of a set's momentum in its inertia.
+  pub momentum: usize,
+  /// Coefficient of a set's velocity in its inertia.
+  pub velocity: usize,
+}
+
+impl Default for Weights {
+  fn default() -> Self {
+    Weights {
+      local_impl: 0,
+      mixed_impl: 1,
+      external_impl: 2,
+      duplicate_crate: 1,
+      newtype: 6,
+      ty_change: 4,
+      param: 5,
+      callable: 4,
+      misc: 50,
+      on_unimplemented_divisor: 2,
+      momentum: 1,
+      velocity: 0,
+    }
+  }
+}
+
+impl Weights {
+  /// Ranges searched while tuning, in the order of `Weights::to_array`.
+  const RANGES: [RangeInclusive<usize>; 12] = [
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    0 ..= 60,
+    1 ..= 4,
+    0 ..= 4,
+    0 ..= 4,
+  ];
+
+  /// The weights configured by `ARGUS_WEIGHTS`.
+  pub fn configured() -> Self {
+    *CONFIGURED
+  }
+
+  pub fn from_file(path: &Path) -> Result<Self> {
+    let contents = std::fs::read_to_string(path)
+      .with_context(|| format!("reading {}", path.display()))?;
+    Ok(serde_json::from_str(&contents)?)
+  }
+
+  pub(super) fn goal(&self, kind: &GoalKind, on_unimplemented: bool) -> usize {
+    self.class(kind.class(), on_unimplemented)
+  }
+
+  fn class(&self, class: Class, on_unimplemented: bool) -> usize {
+    let weight = match class {
+      Class::LocalImpl => self.local_impl,
+      Class::MixedImpl => self.mixed_impl,
+      Class::ExternalImpl => self.external_impl,
+      Class::DuplicateCrate => self.duplicate_crate,
+      Class::TyChange => self.ty_change,
+      Class::Newtype => self.newtype,
+      Class::Params { count } => self.param * count,
+      Class::Callable { arity } => self.callable + self.param * arity,
+      Class::Misc => self.misc,
+    };
+
+    if on_unimplemented {
+      weight / self.on_unimplemented_divisor.max(1)
+    } else {
+      weight
+    }
+  }
+
+  pub(super) fn inertia(&self, momentum: usize, velocity: usize) -> usize {
+    self.momentum * momentum + self.velocity * velocity
+  }
+
+  fn to_array(self) -> [usize; 12] {
+    [
+      self.local_impl,
+      self.mixed_impl,
+      self.external_impl,
+      self.duplicate_crate,
+      self.newtype,
+      self.ty_change,
+      self.param,
+      self.callable,
+      self.misc,
+      self.on_unimplemented_divisor,
+      self.momentum,
+      self.velocity,
+    ]
+  }
+
+  fn from_array(a: [usize; 12]) -> Self {
+    Weights {
+      local_impl: a[0],
+      mixed_impl: a[1],
+      external_impl: a[2],
+      duplicate_crate: a[3],
+      newtype: a[4],
+      ty_change: a[5],
+      param: a[6],
+      callable: a[7],
+      misc: a[8],
+      on_unimplemented_divisor: a[9],
+      momentum: a[10],
+      velocity: a[11],
+    }
+  }
+
+  /// Rank of the labeled goal among the sets found by rerunning the search
+  /// of a tree, where sets of equal inertia all take the worst rank.
+  fn rank(&self, tree: &Replay, hash: ObligationHash) -> Option<usize> {
+    let sets = tree.sets(self);
+    let leaves = tree.problem.leaves();
+    let inertias = sets
+      .iter()
+      .map(|set| {
+        let leaves = set.iter().map(|&i| &leaves[i]);
+        let momentum = leaves
+          .clone()
+          .map(|leaf| self.class(leaf.class, leaf.on_unimplemented))
+          .sum();
+        let velocity = leaves.map(|leaf| leaf.depth).max().unwrap_or(0);
+        self.inertia(momentum, velocity)
+      })
+      .collect::<Vec<_>>();
+
+    let labeled = sets
+      .iter()
+      .zip(&inertias)
+      .filter(|(set, _)| set.iter().any(|&i| leaves[i].hash == hash))
+      .map(|(_, &inertia)| inertia)
+      .min()?;
+    Some(inertias.iter().filter(|&&i| i <= labeled).count())
+  }
+
+  #[allow(clippy::cast_precision_loss)]
+  pub fn evaluate(&self, samples: &[Sample]) -> RankMetrics {
+    let ranks = samples
+      .iter()
+      .filter_map(|sample| {
+        sample
+          .trees
+          .iter()
+          .filter_map(|tree| self.rank(tree, sample.hash))
+          .min()
+      })
+      .collect::<Vec<_>>();
+
+    let total = samples.len().max(1) as f64;
+    let within = |k| ranks.iter().filter(|&&r| r <= k).count() as f64 / total;
+    RankMetrics {
+      samples: samples.len(),
+      found: ranks.len(),
+      top1: within(1),
+      top3: within(3),
+      mrr: ranks.iter().map(|&r| 1.0 / r as f64).sum::<f64>() / total,
+    }
+  }
+
+  /// Search for weights that rank the labeled goals better, by coordinate
+  /// descent starting from `self`.
+  #[must_use]
+  pub fn tune(self, samples: &[Sample]) -> Self {
+    let mut best = self;
+    let mut best_metrics = best.evaluate(samples);
+    for _ in 0 .. MAX_ROUNDS {
+      let mut improved = false;
+      for (i, range) in Self::RANGES.iter().enumerate() {
+        for value in range.clone() {
+          let mut params = best.to_array();
+          params[i] = value;
+          let candidate = Self::from_array(params);
+          if candidate.momentum == 0 && candidate.velocity == 0 {
+            continue;
+          }
+
+          let metrics = candidate.evaluate(samples);
+          if metrics.is_better_than(&best_metrics) {
+            best = candidate;
+            best_metrics = metrics;
+            improved = true;
+          }
+        }
+      }
+
+      if !improved {
+        break;
+      }
+    }
+    best
+  }
+}
+
+/// The true root cause of the failures in a file.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Label {
+  /// Suffix of the file's path, e.g., `bad_sql_query.rs`.
+  pub file: String,
+  /// Hash of the root-cause goal, computed as for obligations.
+  pub hash: ObligationHash,
+}
+
+/// What the weight of a goal depends on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Class {
+  LocalImpl,
+  MixedImpl,
+  ExternalImpl,
+  DuplicateCrate,
+  Newtype,
+  TyChange,
+  /// Function parameters added, deleted, or changed.
+  Params {
+    count: usize,
+  },
+  Callable {
+    arity: usize,
+  },
+  Misc,
+}
+
+impl GoalKind {
+  fn class(&self) -> Class {
+    use GoalKind as GK;
+    use Location::{External as E, Local as L};
+    match self {
+      GK::Trait {
+        _self: L,
+        _trait: L,
+      } => Class::LocalImpl,
+
+      GK::Trait {
+        _self: L,
+        _trait: E,
+      }
+      | GK::Trait {
+        _self: E,
+        _trait: L,
+      }
+      | GK::FnToTrait { _trait: L, .. } => Class::MixedImpl,
+
+      GK::Trait {
+        _self: E,
+        _trait: E,
+      } => Class::ExternalImpl,
+
+      GK::DuplicateCrate { .. } => Class::DuplicateCrate,
+      GK::TyChange => Class::TyChange,
+      GK::Newtype => Class::Newtype,
+      GK::IncorrectParams { arity: count, .. }
+      | GK::AddFnParams { delta: count }
+      | GK::DeleteFnParams { delta: count } => Class::Params { count: *count },
+      GK::FnToTrait {
+        _trait: E, arity, ..
+      } => Class::Callable { arity: *arity },
+      GK::TyAsCallable { arity, .. } => Class::Callable {
+        arity: arity.unwrap_or_default(),
+      },
+      GK::Misc => Class::Misc,
+    }
+  }
+}
+
+/// A failed goal that the correction-set search fixes directly.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Leaf {
+  pub(super) goal: I,
+  class: Class,
+  on_unimplemented: bool,
+  hash: ObligationHash,
+  /// Depth of the goal in the tree, a set's velocity is its deepest goal.
+  depth: usize,
+}
+
+impl Leaf {
+  pub(super) fn new(goal: I, heuristic: &Heuristic, depth: usize) -> Self {
+    Leaf {
+      goal,
+      class: heuristic.kind.class(),
+      on_unimplemented: heuristic.on_unimplemented,
+      hash: heuristic.hash,
+      depth,
+    }
+  }
+}
+
+/// The correction-set search of a tree, recorded to be rerun with other
+/// weights.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Replay {
+  problem: Problem<Leaf>,
+  limit: usize,
+}
+
+impl Replay {
+  pub(super) fn new(problem: Problem<Leaf>, limit: usize) -> Self {
+    Replay { problem, limit }
+  }
+
+  pub(super) fn leaves(&self) -> &[Leaf] {
+    self.problem.leaves()
+  }
+
+  /// The cheapest minimal correction sets with `weights`, as indices into
+  /// the leaves.
+  pub(super) fn sets(&self, weights: &Weights) -> Vec<Set<usize>> {
+    self.problem.minimal_sets(
+      |leaf| weights.class(leaf.class, leaf.on_unimplemented),
+      SEARCH_BUDGET,
+      self.limit,
+    )
+  }
+}
+
+/// The recorded searches of every tree in a labeled file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sample {
+  trees: Vec<Replay>,
+  hash: ObligationHash,
+}
+
+impl Sample {
+  /// One sample per label, from the trees of the bundles in its file.
+  pub fn collect(bundles: &[BodyBundle], labels: &[Label]) -> Vec<Self> {
+    labels
+      .iter()
+      .map(|label| Sample {
+        trees: bundles
+          .iter()
+          .filter(|bundle| bundle.filename.ends_with(&label.file))
+          .flat_map(|bundle| bundle.trees.values())
+          .map(|tree| tree.analysis.replay.clone())
+          .collect(),
+        hash: label.hash,
+      })
+      .collect()
+  }
+
+  /// Combine the samples that each crate collected for the same labels.
+  pub fn merge(crates: impl IntoIterator<Item = Vec<Self>>) -> Vec<Self> {
+    crates
+      .into_iter()
+      .reduce(|mut merged, samples| {
+        for (sample, other) in merged.iter_mut().zip(samples) {
+          sample.trees.extend(other.trees);
+        }
+        merged
+      })
+      .unwrap_or_default()
+  }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct RankMetrics {
+  pub samples: usize,
+  /// Samples whose root cause is in some correction set.
+  pub found: usize,
+  pub top1: f64,
+  pub top3: f64,
+  /// Mean reciprocal rank, root causes that aren't found count as zero.
+  pub mrr: f64,
+}
+
+impl RankMetrics {
+  fn is_better_than(&self, other: &Self) -> bool {
+    (self.mrr, self.top1, self.top3) > (other.mrr, other.top1, other.top3)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn leaf(goal: usize, class: Class, hash: u64) -> Leaf {
+    Leaf {
+      goal: I::from_usize(goal),
+      class,
+      on_unimplemented: false,
+      hash: hash.into(),
+      depth: 1,
+    }
+  }
+
+  /// A goal fixed by either a local impl (hash 1) or a misc change (hash 2),
+  /// only the cheapest set is kept.
+  fn either() -> Replay {
+    let mut problem = Problem::default();
+    let local = problem.leaf(leaf(1, Class::LocalImpl, 1));
+    let misc = problem.leaf(leaf(2, Class::Misc, 2));
+    problem.or(vec![local, misc]);
+    Replay::new(problem, 1)
+  }
+
+  #[test]
+  fn missing_fields_keep_their_default() {
+    let path = std::env::temp_dir()
+      .join(format!("argus-weights-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{ "misc": 7, "onUnimplementedDivisor": 3 }"#)
+      .unwrap();
+    let weights = Weights::from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(weights.unwrap(), Weights {
+      misc: 7,
+      on_unimplemented_divisor: 3,
+      ..Weights::default()
+    });
+  }
+
+  #[test]
+  fn ranks_rerun_the_search() {
+    let tree = either();
+    let defaults = Weights::default();
+    assert_eq!(defaults.rank(&tree, 1.into()), Some(1));
+    assert_eq!(defaults.rank(&tree, 2.into()), None);
+
+    let misc_first = Weights {
+      local_impl: 60,
+      misc: 0,
+      ..defaults
+    };
+    assert_eq!(misc_first.rank(&tree, 2.into()), Some(1));
+  }
+
+  #[test]
+  fn tuning_ranks_the_labels_better() {
+    let samples = [Sample {
+      trees: vec![either()],
+      hash: 2.into(),
+    }];
+    let defaults = Weights::default();
+    assert_eq!(defaults.evaluate(&samples).found, 0);
+
+    let metrics = defaults.tune(&samples).evaluate(&samples);
+    assert_eq!((metrics.found, metrics.top1), (1, 1.0));
+  }
+}
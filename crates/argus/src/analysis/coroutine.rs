@@ -0,0 +1,215 @@
+//! Locals held across await points that make a future fail an auto trait.
+//!
+//! An auto trait holds for a coroutine when it holds for every type stored in
+//! its witness, the locals that are live across a suspension point. The proof
+//! of `{coroutine}: Send` therefore fails at some `CoroutineWitness` goal, and
+//! its failing nested goals are the types of the culprit locals. These are
+//! mapped back to the locals, and their suspension points, with the coroutine
+//! layout computed by MIR.
+
+use argus_ser as ser;
+use rustc_hir::def_id::DefId;
+use rustc_infer::{infer::InferCtxt, traits::PredicateObligation};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::{Span, Symbol, DUMMY_SP};
+use rustc_trait_selection::{
+  solve::inspect::{InspectGoal, ProofTreeInferCtxtExt, ProofTreeVisitor},
+  traits::solve::Goal,
+};
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+
+use crate::{tls, types::HeldAcrossAwait};
+
+pub(super) struct HeldLocal<'tcx> {
+  pub name: Option<Symbol>,
+  pub ty: Ty<'tcx>,
+  pub auto_trait: DefId,
+  pub binding: Span,
+  pub await_point: Span,
+}
+
+impl<'tcx> HeldLocal<'tcx> {
+  pub fn to_output(&self, infcx: &InferCtxt<'tcx>) -> Option<HeldAcrossAwait> {
+    #[derive(Serialize)]
+    struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+    let source_map = infcx.tcx.sess.source_map();
+    let binding = CharRange::from_span(self.binding, source_map).ok()?;
+    let await_point =
+      CharRange::from_span(self.await_point, source_map).ok()?;
+    let (ty, auto_trait) = tls::unsafe_access_interner(|ty_interner| {
+      (
+        ser::to_value_expect(infcx, ty_interner, &TyWrapper(self.ty)),
+        ser::to_value_expect(
+          infcx,
+          ty_interner,
+          &ser::PathDefNoArgs(self.auto_trait),
+        ),
+      )
+    });
+
+    Some(HeldAcrossAwait {
+      name: self.name.map(|name| name.to_string()),
+      ty,
+      auto_trait,
+      binding,
+      await_point,
+    })
+  }
+}
+
+/// Find the locals held across await points that cause `obligation` to fail.
+pub(super) fn held_across_await<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  obligation: &PredicateObligation<'tcx>,
+) -> Vec<HeldLocal<'tcx>> {
+  // Only auto traits are implemented structurally by coroutines, other
+  // proof trees can't contain a witness goal.
+  let is_auto = obligation
+    .predicate
+    .as_trait_clause()
+    .is_some_and(|t| infcx.tcx.trait_is_auto(t.def_id()));
+  if !is_auto {
+    return vec![];
+  }
+
+  infcx.probe(|_| {
+    let goal = Goal {
+      predicate: obligation.predicate,
+      param_env: obligation.param_env,
+    };
+    let mut finder = WitnessFinder { held: vec![] };
+    infcx.visit_proof_tree(goal, &mut finder);
+    finder.held
+  })
+}
+
+struct WitnessFinder<'tcx> {
+  held: Vec<HeldLocal<'tcx>>,
+}
+
+impl<'tcx> WitnessFinder<'tcx> {
+  /// The failing auto trait goal on a coroutine witness, if `goal` is one.
+  fn as_witness_goal(goal: &InspectGoal<'_, 'tcx>) -> Option<(DefId, DefId)> {
+    let infcx = goal.infcx();
+    let trait_pred = goal.goal().predicate.as_trait_clause()?;
+    if !infcx.tcx.trait_is_auto(trait_pred.def_id()) {
+      return None;
+    }
+
+    let self_ty =
+      infcx.resolve_vars_if_possible(trait_pred.self_ty().skip_binder());
+    match *self_ty.kind() {
+      ty::CoroutineWitness(def_id, _) => Some((def_id, trait_pred.def_id())),
+      _ => None,
+    }
+  }
+
+  fn record_witness(
+    &mut self,
+    goal: &InspectGoal<'_, 'tcx>,
+    (coroutine, auto_trait): (DefId, DefId),
+  ) {
+    let infcx = goal.infcx();
+    let tcx = infcx.tcx;
+    if !coroutine.is_local() {
+      return;
+    }
+    let Some(layout) = tcx.mir_coroutine_witnesses(coroutine) else {
+      return;
+    };
+
+    // The auto trait goals nested in the witness are its fields that matter
+    // for auto traits, in order. Fields with aliases are preceded by goals
+    // normalizing them.
+    let fields = layout
+      .field_tys
+      .iter_enumerated()
+      .filter(|(_, decl)| !decl.ignore_for_traits)
+      .map(|(local, _)| local)
+      .collect::<Vec<_>>();
+
+    let failing = goal
+      .candidates()
+      .iter()
+      .flat_map(|candidate| {
+        infcx.probe(|_| {
+          let nested = candidate
+            .instantiate_nested_goals(DUMMY_SP)
+            .into_iter()
+            .filter_map(|nested| {
+              let pred = nested.goal().predicate.as_trait_clause()?;
+              (pred.def_id() == auto_trait).then_some((nested, pred))
+            })
+            .collect::<Vec<_>>();
+          if nested.len() != fields.len() {
+            return vec![];
+          }
+
+          nested
+            .into_iter()
+            .zip(&fields)
+            .filter(|((nested, _), _)| nested.result().is_err())
+            .map(|((_, pred), &local)| {
+              let ty = infcx.resolve_vars_if_possible(pred.self_ty());
+              (local, erase(tcx, ty))
+            })
+            .collect::<Vec<_>>()
+        })
+      })
+      .collect::<Vec<_>>();
+
+    for (local, ty) in failing {
+      // Find the first suspension point where the local is live.
+      let await_point = layout
+        .variant_fields
+        .iter()
+        .zip(&layout.variant_source_info)
+        .find_map(|(variant, source_info)| {
+          variant
+            .iter()
+            .any(|&l| l == local)
+            .then_some(source_info.span)
+        });
+
+      if let Some(await_point) = await_point {
+        self.held.push(HeldLocal {
+          name: layout.field_names[local],
+          ty,
+          auto_trait,
+          binding: layout.field_tys[local].source_info.span,
+          await_point,
+        });
+      }
+    }
+  }
+}
+
+/// Types of the coroutine interior have their regions erased.
+fn erase<'tcx>(tcx: TyCtxt<'tcx>, ty: ty::Binder<'tcx, Ty<'tcx>>) -> Ty<'tcx> {
+  tcx.erase_regions(tcx.instantiate_bound_regions_with_erased(ty))
+}
+
+impl<'tcx> ProofTreeVisitor<'tcx> for WitnessFinder<'tcx> {
+  type Result = ();
+
+  fn span(&self) -> Span {
+    DUMMY_SP
+  }
+
+  fn visit_goal(&mut self, goal: &InspectGoal<'_, 'tcx>) {
+    if goal.result().is_ok() {
+      return;
+    }
+
+    if let Some(witness) = Self::as_witness_goal(goal) {
+      self.record_witness(goal, witness);
+      return;
+    }
+
+    for candidate in goal.candidates() {
+      candidate.visit_nested_in_probe(self);
+    }
+  }
+}
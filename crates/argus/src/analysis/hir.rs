@@ -1,14 +1,26 @@
 use argus_ext::ty::TyCtxtExt;
+use argus_ser as ser;
 use rustc_data_structures::fx::FxHashMap as HashMap;
 use rustc_hir::{
-  self as hir, intravisit::Visitor as HirVisitor, BodyId, HirId,
+  self as hir, def_id::DefId, intravisit::Visitor as HirVisitor, BodyId, HirId,
+  LangItem,
 };
-use rustc_middle::{hir::nested_filter, ty::TyCtxt};
-use rustc_span::Span;
+use rustc_infer::infer::InferCtxt;
+use rustc_middle::{
+  hir::nested_filter,
+  ty::{Ty, TyCtxt},
+};
+use rustc_span::{DesugaringKind, Span};
+use serde::Serialize;
 
-use crate::types::intermediate::ErrorAssemblyCtx;
+use crate::{
+  tls,
+  types::{intermediate::ErrorAssemblyCtx, OperatorTrait},
+};
 
-pub fn associate_obligations_nodes(ctx: &ErrorAssemblyCtx) -> Vec<Bin> {
+pub fn associate_obligations_nodes<'tcx>(
+  ctx: &ErrorAssemblyCtx<'_, 'tcx>,
+) -> Vec<Bin<'tcx>> {
   let mut grouped: HashMap<_, Vec<_>> = HashMap::default();
   for (i, prov) in ctx.obligations.iter().enumerate() {
     grouped.entry(prov.hir_id).or_default().push(i);
@@ -29,18 +41,28 @@ pub fn associate_obligations_nodes(ctx: &ErrorAssemblyCtx) -> Vec<Bin> {
 //
 // * for method calls `obj . frobnicate(arg1, arg2, ...)`
 //
-// -- obligations corresponding to `obj`
+// -- obligations corresponding to the receiver `obj`
 // -- obligations corresponding call args `arg1, arg2, ...`, respectively.
 // -- obligations corresponding to `obj . frobnicate(arg1, arg2, ...)`
 //
-// TODO: for now, we will leave everything else *untouched*. We may want
-// to expand this in the future, for example, when creating a system of
-// traits it is important to debug why an impl block is invalid. (Either
-// ill-formed, violates orphan rule, overlapping, etc...)
-fn bin_expressions(
-  ctx: &ErrorAssemblyCtx,
+// * for desugared and overloaded expressions, the obligations introduced
+//   by the desugaring point at the syntax responsible for them:
+//
+// -- `expr?` at the `?` token
+// -- `expr.await` at the `await` keyword
+// -- `for pat in iter` at `iter`
+// -- `a + b`, `a += b`, `-a` at the operator
+// -- `a[i]` at the brackets
+//
+// * field accesses, closures and struct literals are binned at the field,
+//   the closure head `|args|`, and the struct path.
+//
+// Obligations of impl blocks and signatures are outside of bodies, those
+// are analyzed by `analysis::items`.
+fn bin_expressions<'tcx>(
+  ctx: &ErrorAssemblyCtx<'_, 'tcx>,
   mut map: HashMap<HirId, Vec<usize>>,
-) -> Vec<Bin> {
+) -> Vec<Bin<'tcx>> {
   let mut binner = BinCreator {
     ctx,
     map: &mut map,
@@ -56,6 +78,7 @@ fn bin_expressions(
       hir_id,
       obligations,
       kind: BinKind::Misc,
+      span: None,
     });
   }
 
@@ -63,30 +86,102 @@ fn bin_expressions(
 }
 
 #[derive(Debug)]
-pub enum BinKind {
+pub enum BinKind<'tcx> {
   CallableExpr,
   CallArg,
   Call,
+  MethodReceiver,
+  FieldAccess,
+  Try,
+  Await,
+  Closure,
+  ForLoopIterator,
+  /// The trait of the operator, `None` for the lazy `&&` and `||`.
+  Operator(Option<OverloadedOp<'tcx>>),
+  Index,
+  StructLiteral,
   Misc,
 }
 
-pub struct Bin {
+/// The trait an operator is overloaded with, `Lhs: Trait<Rhs>`.
+#[derive(Debug)]
+pub struct OverloadedOp<'tcx> {
+  pub trait_: DefId,
+  pub lhs: Ty<'tcx>,
+  /// The right-hand side of binary operators.
+  pub rhs: Option<Ty<'tcx>>,
+}
+
+impl<'tcx> OverloadedOp<'tcx> {
+  pub fn to_output(&self, infcx: &InferCtxt<'tcx>) -> OperatorTrait {
+    #[derive(Serialize)]
+    struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+    tls::unsafe_access_interner(|ty_interner| {
+      let ty = |ty| ser::to_value_expect(infcx, ty_interner, &TyWrapper(ty));
+      OperatorTrait {
+        trait_: ser::to_value_expect(
+          infcx,
+          ty_interner,
+          &ser::PathDefNoArgs(self.trait_),
+        ),
+        lhs: ty(self.lhs),
+        rhs: self.rhs.map(ty),
+      }
+    })
+  }
+}
+
+/// The lang item trait of a binary or compound assignment operator.
+fn binary_op_trait(op: hir::BinOpKind, is_assign: bool) -> Option<LangItem> {
+  use hir::BinOpKind as B;
+  let (op, assign) = match op {
+    B::Add => (LangItem::Add, LangItem::AddAssign),
+    B::Sub => (LangItem::Sub, LangItem::SubAssign),
+    B::Mul => (LangItem::Mul, LangItem::MulAssign),
+    B::Div => (LangItem::Div, LangItem::DivAssign),
+    B::Rem => (LangItem::Rem, LangItem::RemAssign),
+    B::BitXor => (LangItem::BitXor, LangItem::BitXorAssign),
+    B::BitAnd => (LangItem::BitAnd, LangItem::BitAndAssign),
+    B::BitOr => (LangItem::BitOr, LangItem::BitOrAssign),
+    B::Shl => (LangItem::Shl, LangItem::ShlAssign),
+    B::Shr => (LangItem::Shr, LangItem::ShrAssign),
+    B::Eq | B::Ne => return Some(LangItem::PartialEq),
+    B::Lt | B::Le | B::Gt | B::Ge => return Some(LangItem::PartialOrd),
+    B::And | B::Or => return None,
+  };
+  Some(if is_assign { assign } else { op })
+}
+
+pub struct Bin<'tcx> {
   pub hir_id: HirId,
   // TODO: use IndexVec for obligations instead of the--
   //
   // usize indexes into the obligation vec
   pub obligations: Vec<usize>,
-  pub kind: BinKind,
+  pub kind: BinKind<'tcx>,
+  /// Part of the node the obligations are about, e.g., the operator of a
+  /// binary expression. The whole node if `None`.
+  pub span: Option<Span>,
 }
 
 struct BinCreator<'a, 'tcx: 'a> {
   ctx: &'a ErrorAssemblyCtx<'a, 'tcx>,
   map: &'a mut HashMap<HirId, Vec<usize>>,
-  bins: Vec<Bin>,
+  bins: Vec<Bin<'tcx>>,
 }
 
-impl BinCreator<'_, '_> {
-  fn drain_nested(&mut self, target: HirId, kind: BinKind) {
+impl<'tcx> BinCreator<'_, 'tcx> {
+  fn drain_nested(&mut self, target: HirId, kind: BinKind<'tcx>) {
+    self.drain_nested_at(target, kind, None);
+  }
+
+  fn drain_nested_at(
+    &mut self,
+    target: HirId,
+    kind: BinKind<'tcx>,
+    span: Option<Span>,
+  ) {
     let is_nested = |id: HirId| self.ctx.tcx.is_parent_of(target, id);
 
     let mut to_remove = Vec::default();
@@ -113,9 +208,30 @@ impl BinCreator<'_, '_> {
         hir_id: target,
         obligations,
         kind,
+        span,
       });
     }
   }
+
+  fn operator(
+    &self,
+    trait_: Option<LangItem>,
+    lhs: &hir::Expr,
+    rhs: Option<&hir::Expr>,
+  ) -> BinKind<'tcx> {
+    let typeck_results = self.ctx.typeck_results;
+    let op = trait_.and_then(|item| {
+      Some(OverloadedOp {
+        trait_: self.ctx.tcx.lang_items().get(item)?,
+        lhs: typeck_results.expr_ty_opt(lhs)?,
+        rhs: match rhs {
+          Some(rhs) => Some(typeck_results.expr_ty_opt(rhs)?),
+          None => None,
+        },
+      })
+    });
+    BinKind::Operator(op)
+  }
 }
 
 impl<'a, 'tcx: 'a> HirVisitor<'tcx> for BinCreator<'a, 'tcx> {
@@ -129,6 +245,30 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for BinCreator<'a, 'tcx> {
   // Obligations associated with parameters are now being assigned to the overall call,
   // this makes more things use a method call table than necessary.
   fn visit_expr(&mut self, ex: &'tcx hir::Expr) {
+    // Only the user-written operand of `?` and `.await` is visited, the
+    // rest of the desugaring belongs to the operator.
+    if let hir::ExprKind::Match(
+      hir::Expr {
+        kind: hir::ExprKind::Call(_, [operand]),
+        span: scrutinee_span,
+        ..
+      },
+      _,
+      source @ (hir::MatchSource::TryDesugar(_)
+      | hir::MatchSource::AwaitDesugar),
+    ) = ex.kind
+    {
+      self.visit_expr(operand);
+      let (kind, span) = if matches!(source, hir::MatchSource::AwaitDesugar) {
+        (BinKind::Await, *scrutinee_span)
+      } else {
+        let source_map = self.ctx.tcx.sess.source_map();
+        (BinKind::Try, source_map.end_point(ex.span))
+      };
+      self.drain_nested_at(ex.hir_id, kind, Some(span));
+      return;
+    }
+
     // Drain nested obligations first to match the most specific node possible.
     hir::intravisit::walk_expr(self, ex);
 
@@ -138,6 +278,13 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for BinCreator<'a, 'tcx> {
     );
 
     match ex.kind {
+      // `IntoIterator::into_iter(iter)` and `Iterator::next(&mut iter)`,
+      // both spanning the iterator expression.
+      hir::ExprKind::Call(..)
+        if ex.span.is_desugaring(DesugaringKind::ForLoop) =>
+      {
+        self.drain_nested(ex.hir_id, BinKind::ForLoopIterator);
+      }
       hir::ExprKind::Call(callable, args) => {
         for arg in args {
           self.drain_nested(arg.hir_id, BinKind::CallArg);
@@ -150,11 +297,56 @@ impl<'a, 'tcx: 'a> HirVisitor<'tcx> for BinCreator<'a, 'tcx> {
           self.drain_nested(arg.hir_id, BinKind::CallArg);
         }
         self.drain_nested(segment.hir_id, BinKind::Call);
-        self.drain_nested(func.hir_id, BinKind::CallArg);
+        self.drain_nested(func.hir_id, BinKind::MethodReceiver);
         // [ ] TODO (see above `FIXME`):
         // self.drain_nested(ex.hir_id, BinKind::MethodCall);
         self.drain_nested(ex.hir_id, BinKind::Misc);
       }
+      hir::ExprKind::Field(base, _) => {
+        let field = ex.span.trim_start(base.span);
+        self.drain_nested_at(ex.hir_id, BinKind::FieldAccess, field);
+      }
+      // Coroutines are the desugared bodies of `async` blocks and functions.
+      hir::ExprKind::Closure(
+        closure @ hir::Closure {
+          kind:
+            hir::ClosureKind::Closure | hir::ClosureKind::CoroutineClosure(_),
+          ..
+        },
+      ) => {
+        self.drain_nested_at(
+          ex.hir_id,
+          BinKind::Closure,
+          Some(closure.fn_decl_span),
+        );
+      }
+      hir::ExprKind::Binary(op, lhs, rhs)
+      | hir::ExprKind::AssignOp(op, lhs, rhs) => {
+        let is_assign = matches!(ex.kind, hir::ExprKind::AssignOp(..));
+        let trait_ = binary_op_trait(op.node, is_assign);
+        let kind = self.operator(trait_, lhs, Some(rhs));
+        self.drain_nested_at(ex.hir_id, kind, Some(op.span));
+      }
+      hir::ExprKind::Unary(op, operand) => {
+        let trait_ = match op {
+          hir::UnOp::Deref => LangItem::Deref,
+          hir::UnOp::Not => LangItem::Not,
+          hir::UnOp::Neg => LangItem::Neg,
+        };
+        let kind = self.operator(Some(trait_), operand, None);
+        let op = ex.span.until(operand.span);
+        self.drain_nested_at(ex.hir_id, kind, Some(op));
+      }
+      hir::ExprKind::Index(_, _, brackets) => {
+        self.drain_nested_at(ex.hir_id, BinKind::Index, Some(brackets));
+      }
+      hir::ExprKind::Struct(path, ..) => {
+        self.drain_nested_at(
+          ex.hir_id,
+          BinKind::StructLiteral,
+          Some(path.span()),
+        );
+      }
       _ => {}
     }
   }
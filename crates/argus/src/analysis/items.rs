@@ -0,0 +1,429 @@
+//! Obligations of items, outside of any body.
+//!
+//! Rustc checks these while checking an item's well-formedness, not while
+//! type-checking a body, so the obligation inspector never sees them. They
+//! are collected here the same way `wfcheck` collects them: impl headers
+//! against the trait's where-clauses, associated types against the bounds
+//! declared in the trait, signature and field types, and where-clauses.
+
+use anyhow::{anyhow, Result};
+use argus_ext::{ty::EvaluationResultExt, utils::SpanExt};
+use argus_ser as ser;
+use index_vec::IndexVec;
+use indexmap::IndexSet;
+use rustc_data_structures::fx::FxIndexMap;
+use rustc_hir::{self as hir, def::DefKind, def_id::LocalDefId, LangItem};
+use rustc_infer::{
+  infer::{InferCtxt, TyCtxtInferExt},
+  traits::{
+    query::NoSolution, solve::Certainty, Obligation, ObligationCause,
+    PredicateObligation,
+  },
+};
+use rustc_middle::ty::{
+  self, fold::BottomUpFolder, TyCtxt, TypeFoldable, Upcast,
+};
+use rustc_span::Span;
+use rustc_trait_selection::traits::{
+  solve::Goal, wf, ObligationCtxt, ScrubbedTraitError,
+};
+use rustc_utils::source_map::range::CharRange;
+
+use super::{EvaluationResult, INCLUDE_SUCCESSES, OBLIGATION_TARGET};
+use crate::{
+  dyn_compat,
+  ext::InferCtxtExt,
+  proof_tree::{serialize::try_serialize, SerializedTree},
+  tls,
+  types::{
+    Expr, ExprKind, ObligationHash, ObligationIdx, ObligationNecessity,
+    ObligationsInBody, TraitError,
+  },
+};
+
+/// Whether items of this kind have obligations outside of their bodies.
+pub(crate) fn has_item_obligations(kind: DefKind) -> bool {
+  matches!(
+    kind,
+    DefKind::Struct
+      | DefKind::Enum
+      | DefKind::Union
+      | DefKind::Trait
+      | DefKind::Fn
+      | DefKind::AssocFn
+      | DefKind::AssocTy
+      | DefKind::Impl { .. }
+  )
+}
+
+pub(crate) struct ItemObligations<'tcx> {
+  pub infcx: InferCtxt<'tcx>,
+  pub item: LocalDefId,
+  pub obligations: Vec<(PredicateObligation<'tcx>, EvaluationResult)>,
+}
+
+impl<'tcx> ItemObligations<'tcx> {
+  /// Collect and evaluate the obligations of `item`. Successful obligations
+  /// are dropped unless `INCLUDE_SUCCESSES` is set.
+  pub fn new(tcx: TyCtxt<'tcx>, item: LocalDefId) -> Self {
+    let infcx = tcx.infer_ctxt().build(ty::TypingMode::non_body_analysis());
+    let include_successes = INCLUDE_SUCCESSES.copied().unwrap_or(false);
+    let obligations = collect(&infcx, item)
+      .into_iter()
+      .map(|obligation| {
+        let obligation = infcx.resolve_vars_if_possible(obligation);
+        let result = evaluate(&infcx, &obligation);
+        (obligation, result)
+      })
+      .filter(|(_, result)| include_successes || !result.is_yes())
+      .collect();
+
+    ItemObligations {
+      infcx,
+      item,
+      obligations,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.obligations.is_empty()
+  }
+
+  /// The obligations grouped by the signature type or bound they're
+  /// anchored to, failures are reported as trait errors and ambiguities as
+  /// ambiguity errors.
+  pub fn to_output(&self) -> ObligationsInBody {
+    let infcx = &self.infcx;
+    let tcx = infcx.tcx;
+    let source_map = tcx.sess.source_map();
+
+    let mut obligations = IndexVec::<ObligationIdx, _>::default();
+    let mut anchors = FxIndexMap::<Span, Vec<ObligationIdx>>::default();
+    for (obligation, result) in &self.obligations {
+      let span = anchor(obligation);
+      let Ok(range) = CharRange::from_span(span, source_map) else {
+        log::error!("failed to get range for item obligation {obligation:?}");
+        continue;
+      };
+      let fdata = infcx.bless_fulfilled(obligation, *result);
+      let idx = obligations.push(infcx.erase_non_local_data_at(range, fdata));
+      anchors.entry(span).or_default().push(idx);
+    }
+
+    let mut exprs = IndexVec::default();
+    let mut trait_errors = vec![];
+    let mut ambiguity_errors = IndexSet::default();
+    for (span, idxs) in anchors {
+      let range = CharRange::from_span(span, source_map)
+        .expect("anchor range computed above");
+      let hashes = idxs
+        .iter()
+        .filter(|&&i| obligations[i].result.is_no())
+        .map(|&i| obligations[i].hash)
+        .collect::<Vec<_>>();
+      let is_ambiguous = idxs.iter().any(|&i| obligations[i].result.is_maybe());
+
+      let idx = exprs.push(Expr {
+        range,
+        snippet: span.sanitized_snippet(source_map),
+        obligations: idxs,
+        kind: ExprKind::Signature,
+        is_body: false,
+      });
+
+      if !hashes.is_empty() {
+        let failing = self
+          .obligations
+          .iter()
+          .filter(|(obligation, result)| {
+            result.is_no() && anchor(obligation) == span
+          })
+          .map(|(obligation, _)| obligation.predicate);
+        trait_errors.push(TraitError {
+          idx,
+          range,
+          hashes,
+          held_across_await: vec![],
+          dyn_compatibility: dyn_compat::for_predicates(infcx, failing),
+        });
+      } else if is_ambiguous {
+        ambiguity_errors.insert(crate::types::AmbiguityError { idx, range });
+      }
+    }
+
+    let name = tls::unsafe_access_interner(|ty_interner| {
+      ser::to_value_expect(
+        infcx,
+        ty_interner,
+        &ser::PathDefNoArgs(self.item.to_def_id()),
+      )
+    });
+    let item_span = tcx.hir().span(tcx.local_def_id_to_hir_id(self.item));
+    let range = CharRange::from_span(item_span, source_map)
+      .expect("Couldn't get item range");
+
+    ObligationsInBody::new(
+      Some(name),
+      !trait_errors.is_empty(),
+      range,
+      ambiguity_errors,
+      trait_errors,
+      obligations,
+      exprs,
+    )
+  }
+
+  /// Serialize the proof tree of the obligation with `hash`.
+  pub fn tree(&self, hash: ObligationHash) -> Result<SerializedTree> {
+    let infcx = &self.infcx;
+    let (obligation, result) = self
+      .obligations
+      .iter()
+      .find(|(obligation, result)| {
+        infcx.bless_fulfilled(obligation, *result).hash == hash
+      })
+      .ok_or_else(|| anyhow!("item has no obligation {hash:?}"))?;
+
+    log::info!("Generating tree for item obligation {obligation:?}");
+    try_serialize(
+      Goal {
+        predicate: obligation.predicate,
+        param_env: obligation.param_env,
+      },
+      *result,
+      obligation.cause.span,
+      infcx,
+      self.item.to_def_id(),
+    )
+  }
+
+  /// Obligations whose trees are bundled, as for bodies.
+  pub fn necessary(&self) -> Vec<ObligationHash> {
+    self
+      .obligations
+      .iter()
+      .filter_map(|(obligation, result)| {
+        let necessity = self.infcx.obligation_necessity(obligation);
+        (necessity == ObligationNecessity::Yes
+          || (necessity == ObligationNecessity::OnError && result.is_no()))
+        .then(|| self.infcx.bless_fulfilled(obligation, *result).hash)
+      })
+      .collect()
+  }
+
+  /// The tree targeted by `OBLIGATION_TARGET`, if it's an obligation of
+  /// this item.
+  pub fn target_tree(&self) -> Option<Result<SerializedTree>> {
+    OBLIGATION_TARGET.get(|target| {
+      let hash = target?.hash;
+      self
+        .obligations
+        .iter()
+        .any(|(obligation, result)| {
+          self.infcx.bless_fulfilled(obligation, *result).hash == hash
+        })
+        .then(|| self.tree(hash))
+    })
+  }
+}
+
+/// Where an item obligation is reported, the callsite for obligations of
+/// macro-generated items.
+fn anchor(obligation: &PredicateObligation) -> Span {
+  obligation.cause.span.source_callsite()
+}
+
+fn evaluate<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  obligation: &PredicateObligation<'tcx>,
+) -> EvaluationResult {
+  infcx.probe(|_| {
+    let ocx = ObligationCtxt::new(infcx);
+    ocx.register_obligation(obligation.clone());
+    let errors = ocx.select_all_or_error();
+    if errors.is_empty() {
+      Ok(Certainty::Yes)
+    } else if errors.iter().any(ScrubbedTraitError::is_true_error) {
+      Err(NoSolution)
+    } else {
+      Ok(Certainty::AMBIGUOUS)
+    }
+  })
+}
+
+fn collect<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  def_id: LocalDefId,
+) -> Vec<PredicateObligation<'tcx>> {
+  let tcx = infcx.tcx;
+  let param_env = tcx.param_env(def_id);
+  let well_formed = |ty: ty::Ty<'tcx>, span: Span| {
+    wf::obligations(infcx, param_env, def_id, 0, ty.into(), span)
+      .into_iter()
+      .flatten()
+  };
+  let obligation = |span: Span, predicate: ty::Predicate<'tcx>| {
+    Obligation::new(
+      tcx,
+      ObligationCause::misc(span, def_id),
+      param_env,
+      predicate,
+    )
+  };
+
+  let mut out = vec![];
+  for &(clause, span) in tcx.explicit_predicates_of(def_id).predicates {
+    out.extend(wf::clause_obligations(
+      infcx, param_env, def_id, clause, span,
+    ));
+  }
+
+  let node = tcx.hir_node_by_def_id(def_id);
+  if let Some(sig) = node.fn_sig() {
+    let fn_sig = tcx.liberate_late_bound_regions(
+      def_id.to_def_id(),
+      tcx.fn_sig(def_id).instantiate_identity(),
+    );
+    for (&ty, hir_ty) in fn_sig.inputs().iter().zip(sig.decl.inputs) {
+      out.extend(well_formed(ty, hir_ty.span));
+    }
+    if let hir::FnRetTy::Return(hir_ty) = sig.decl.output {
+      out.extend(well_formed(fn_sig.output(), hir_ty.span));
+    }
+  }
+
+  // All fields are sized, except for the last field of a struct.
+  let mut fields = |fields: &[hir::FieldDef], all_sized: bool| {
+    for (i, field) in fields.iter().enumerate() {
+      let ty = tcx.type_of(field.def_id).instantiate_identity();
+      out.extend(well_formed(ty, field.ty.span));
+      if all_sized || i + 1 < fields.len() {
+        let sized = tcx.require_lang_item(LangItem::Sized, Some(field.span));
+        let trait_ref = ty::TraitRef::new(tcx, sized, [ty]);
+        out.push(obligation(field.ty.span, trait_ref.upcast(tcx)));
+      }
+    }
+  };
+
+  match node {
+    hir::Node::Item(item) => match &item.kind {
+      hir::ItemKind::Struct(data, _) => fields(data.fields(), false),
+      hir::ItemKind::Union(data, _) => fields(data.fields(), true),
+      hir::ItemKind::Enum(def, _) => {
+        for variant in def.variants {
+          fields(variant.data.fields(), true);
+        }
+      }
+      hir::ItemKind::Impl(imp) => {
+        if let Some(hir_trait_ref) = &imp.of_trait {
+          if tcx.impl_polarity(def_id) == ty::ImplPolarity::Positive {
+            out.extend(impl_header(infcx, item, imp, hir_trait_ref));
+          }
+        } else {
+          let self_ty = tcx.type_of(def_id).instantiate_identity();
+          out.extend(well_formed(self_ty, imp.self_ty.span));
+        }
+      }
+      _ => {}
+    },
+
+    hir::Node::ImplItem(hir::ImplItem {
+      kind: hir::ImplItemKind::Type(hir_ty),
+      ..
+    }) => {
+      let ty = tcx.type_of(def_id).instantiate_identity();
+      out.extend(well_formed(ty, hir_ty.span));
+      out.extend(
+        assoc_ty_bounds(tcx, def_id, ty)
+          .into_iter()
+          .map(|bound| obligation(hir_ty.span, bound)),
+      );
+    }
+
+    _ => {}
+  }
+
+  out
+}
+
+/// The trait's where-clauses and supertraits, instantiated for the impl.
+/// Bounds on the self type are reported at the self type, rather than at
+/// the trait path.
+fn impl_header<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  item: &'tcx hir::Item<'tcx>,
+  imp: &hir::Impl,
+  hir_trait_ref: &hir::TraitRef,
+) -> Vec<PredicateObligation<'tcx>> {
+  let tcx = infcx.tcx;
+  let def_id = item.owner_id.def_id;
+  let Some(trait_ref) = tcx.impl_trait_ref(def_id) else {
+    return vec![];
+  };
+  let trait_ref = trait_ref.instantiate_identity();
+  let trait_span = hir_trait_ref.path.span;
+
+  let mut obligations = wf::trait_obligations(
+    infcx,
+    tcx.param_env(def_id),
+    def_id,
+    ty::TraitPredicate {
+      trait_ref,
+      polarity: ty::PredicatePolarity::Positive,
+    },
+    trait_span,
+    item,
+  )
+  .into_iter()
+  .collect::<Vec<_>>();
+
+  for obligation in &mut obligations {
+    let on_self_ty = obligation
+      .predicate
+      .as_trait_clause()
+      .is_some_and(|t| t.skip_binder().self_ty() == trait_ref.self_ty());
+    if obligation.cause.span == trait_span && on_self_ty {
+      obligation.cause.span = imp.self_ty.span;
+    }
+  }
+
+  obligations
+}
+
+/// Bounds the trait declares on an associated type, for its value `ty` in
+/// an impl.
+fn assoc_ty_bounds<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  def_id: LocalDefId,
+  ty: ty::Ty<'tcx>,
+) -> Vec<ty::Predicate<'tcx>> {
+  let Some(trait_item) = tcx.associated_item(def_id).trait_item_def_id else {
+    return vec![];
+  };
+  let impl_def_id = tcx.local_parent(def_id);
+  let Some(trait_ref) = tcx.impl_trait_ref(impl_def_id) else {
+    return vec![];
+  };
+  let args = ty::GenericArgs::identity_for_item(tcx, def_id).rebase_onto(
+    tcx,
+    impl_def_id.to_def_id(),
+    trait_ref.instantiate_identity().args,
+  );
+
+  // Show the bounds on the type itself rather than on the projection.
+  let mut folder = BottomUpFolder {
+    tcx,
+    ty_op: |t: ty::Ty<'tcx>| match t.kind() {
+      ty::Alias(ty::Projection, alias) if alias.def_id == trait_item => ty,
+      _ => t,
+    },
+    lt_op: |r| r,
+    ct_op: |c| c,
+  };
+
+  tcx
+    .explicit_item_bounds(trait_item)
+    .iter_instantiated_copied(tcx, args)
+    .map(|(bound, _)| bound.as_predicate().fold_with(&mut folder))
+    .collect()
+}
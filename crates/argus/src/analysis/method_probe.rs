@@ -0,0 +1,553 @@
+//! Method probes of the method calls in a body.
+//!
+//! rustc resolves `receiver.method()` by autoderefing the receiver type, and
+//! at each step trying the type by value and then autoref'd, first against
+//! the inherent methods named `method` and then against the methods of the
+//! traits in scope. The first adjusted type where a method applies picks
+//! it. The probe records none of this, so it's replayed here after
+//! type-checking. The compiler's pick and adjustments are taken from the
+//! typeck results, the replay explains why the other candidates weren't
+//! picked, or why nothing was.
+
+use argus_ser as ser;
+use rustc_hir::{
+  self as hir, def_id::DefId, intravisit::Visitor as HirVisitor, BodyId, HirId,
+};
+use rustc_hir_analysis::autoderef::{Autoderef, AutoderefKind};
+use rustc_infer::{
+  infer::{BoundRegionConversionTime, DefineOpaqueTypes, InferCtxt},
+  traits::{Obligation, ObligationCause},
+};
+use rustc_middle::{
+  hir::nested_filter,
+  ty::{
+    self,
+    adjustment::{Adjust, AutoBorrow, AutoBorrowMutability, PointerCoercion},
+    fast_reject::{simplify_type, TreatParams},
+    Ty, TyCtxt, TypeVisitableExt, TypeckResults, Upcast,
+  },
+};
+use rustc_span::{symbol::Ident, Span};
+use rustc_trait_selection::traits::query::evaluate_obligation::InferCtxtExt;
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+
+use crate::{
+  tls,
+  types::{
+    CandidateRejection, ExprIdx, MethodCandidate, MethodCandidateKind,
+    MethodProbe, PickedMethod, ProbeStep, ReceiverAdjustment, StepCandidate,
+    StepOutcome,
+  },
+};
+
+/// A method call expression, `receiver.name(args)`.
+pub(super) struct MethodCall<'tcx> {
+  pub hir_id: HirId,
+  pub name: Ident,
+  pub receiver: &'tcx hir::Expr<'tcx>,
+  /// Span of the method name and arguments.
+  pub span: Span,
+}
+
+/// The method calls of `body_id`, including those in closures.
+pub(super) fn method_calls(
+  tcx: TyCtxt<'_>,
+  body_id: BodyId,
+) -> Vec<MethodCall<'_>> {
+  let mut finder = MethodCallFinder {
+    tcx,
+    owner: tcx.hir_body_owner(body_id).owner,
+    calls: vec![],
+  };
+  finder.visit_body(tcx.hir_body(body_id));
+  finder.calls
+}
+
+struct MethodCallFinder<'tcx> {
+  tcx: TyCtxt<'tcx>,
+  owner: hir::OwnerId,
+  calls: Vec<MethodCall<'tcx>>,
+}
+
+impl<'tcx> HirVisitor<'tcx> for MethodCallFinder<'tcx> {
+  type NestedFilter = nested_filter::OnlyBodies;
+
+  fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
+    self.tcx
+  }
+
+  fn visit_expr(&mut self, ex: &'tcx hir::Expr) {
+    // Nested items are type-checked on their own.
+    if ex.hir_id.owner != self.owner {
+      return;
+    }
+
+    if let hir::ExprKind::MethodCall(segment, receiver, _, span) = ex.kind {
+      if !ex.span.from_expansion() {
+        self.calls.push(MethodCall {
+          hir_id: ex.hir_id,
+          name: segment.ident,
+          receiver,
+          span,
+        });
+      }
+    }
+
+    hir::intravisit::walk_expr(self, ex);
+  }
+}
+
+/// Limit on the traits out of scope that are suggested as candidates,
+/// every trait of every crate is searched for them.
+const MAX_OUT_OF_SCOPE_TRAITS: usize = 8;
+
+/// A method named like the call, from an inherent impl or a trait.
+struct Candidate {
+  def_id: DefId,
+  /// The trait of a trait method.
+  trait_def_id: Option<DefId>,
+  rejection: Option<CandidateRejection>,
+}
+
+/// Replay the probe of `call`, the expression with index `expr` if any.
+pub(super) fn probe<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  typeck_results: &TypeckResults<'tcx>,
+  call: &MethodCall<'tcx>,
+  range: CharRange,
+  expr: Option<ExprIdx>,
+) -> Option<MethodProbe> {
+  let tcx = infcx.tcx;
+  let receiver_ty = typeck_results.expr_ty_opt(call.receiver)?;
+  if receiver_ty.references_error() || receiver_ty.has_non_region_infer() {
+    return None;
+  }
+
+  // Regions don't affect the probe, they're replaced so the types can be
+  // related without introducing constraints.
+  let receiver_ty =
+    ty::fold::fold_regions(tcx, receiver_ty, |_, _| tcx.lifetimes.re_static);
+  let steps = autoderef_steps(infcx, param_env, call, receiver_ty);
+
+  let picked = typeck_results
+    .type_dependent_def(call.hir_id)
+    .map(|(_, def_id)| def_id);
+  let candidates = candidates(infcx, param_env, call, &steps, picked.is_none());
+
+  let mut probe_steps = vec![];
+  let autorefs = [None, Some(ty::Mutability::Not), Some(ty::Mutability::Mut)];
+  'steps: for (step_ty, adjustments) in &steps {
+    for autoref in autorefs {
+      let adjusted = autoref.map_or(*step_ty, |mutbl| {
+        Ty::new_ref(tcx, tcx.lifetimes.re_static, *step_ty, mutbl)
+      });
+      let mut adjustments = adjustments.clone();
+      adjustments.extend(autoref.map(|mutbl| ReceiverAdjustment::Borrow {
+        mutable: mutbl.is_mut(),
+      }));
+
+      // Inherent methods shadow trait methods at the same step.
+      let mut applies = false;
+      let mut step_candidates = vec![];
+      for inherent in [true, false] {
+        if applies {
+          break;
+        }
+
+        for (i, candidate) in candidates.iter().enumerate() {
+          if candidate.rejection.is_some()
+            || candidate.trait_def_id.is_none() != inherent
+          {
+            continue;
+          }
+
+          let outcome =
+            consider(infcx, param_env, call.span, candidate, adjusted);
+          let outcome = match outcome {
+            StepOutcome::Applicable if picked == Some(candidate.def_id) => {
+              StepOutcome::Picked
+            }
+            outcome => outcome,
+          };
+          applies |=
+            matches!(outcome, StepOutcome::Applicable | StepOutcome::Picked);
+          step_candidates.push(StepCandidate {
+            candidate: i,
+            outcome,
+          });
+        }
+      }
+
+      probe_steps.push(ProbeStep {
+        ty: to_value(infcx, &TyWrapper(adjusted)),
+        adjustments,
+        candidates: step_candidates,
+      });
+
+      if applies {
+        break 'steps;
+      }
+    }
+  }
+
+  let picked = picked.map(|def_id| PickedMethod {
+    method: to_value(infcx, &ser::PathDefNoArgs(def_id)),
+    adjustments: typeck_results
+      .expr_adjustments(call.receiver)
+      .iter()
+      .filter_map(|adjustment| receiver_adjustment(&adjustment.kind))
+      .collect(),
+  });
+
+  let candidates = candidates
+    .into_iter()
+    .map(|candidate| {
+      let item = tcx.associated_item(candidate.def_id);
+      MethodCandidate {
+        method: to_value(infcx, &ser::PathDefNoArgs(candidate.def_id)),
+        kind: match candidate.trait_def_id {
+          Some(trait_def_id) => MethodCandidateKind::Trait {
+            trait_: to_value(infcx, &ser::PathDefNoArgs(trait_def_id)),
+          },
+          None => MethodCandidateKind::Inherent,
+        },
+        location: ser::DefLocation::from_def_id_tcx(candidate.def_id, tcx),
+        receiver: item.fn_has_self_parameter.then(|| {
+          let sig = tcx.fn_sig(candidate.def_id).instantiate_identity();
+          to_value(infcx, &TyWrapper(sig.input(0).skip_binder()))
+        }),
+        rejection: candidate.rejection,
+      }
+    })
+    .collect();
+
+  Some(MethodProbe {
+    range,
+    expr,
+    name: call.name.to_string(),
+    receiver: to_value(infcx, &TyWrapper(receiver_ty)),
+    picked,
+    candidates,
+    steps: probe_steps,
+  })
+}
+
+#[derive(Serialize)]
+struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+#[derive(Serialize)]
+struct PredicateWrapper<'tcx>(
+  #[serde(with = "ser::ty::PredicateDef")] ty::Predicate<'tcx>,
+);
+
+fn to_value(infcx: &InferCtxt, value: &impl Serialize) -> serde_json::Value {
+  tls::unsafe_access_interner(|ty_interner| {
+    ser::to_value_expect(infcx, ty_interner, value)
+  })
+}
+
+/// The autoderef steps of the receiver, ending with an unsized array.
+fn autoderef_steps<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  call: &MethodCall<'tcx>,
+  receiver_ty: Ty<'tcx>,
+) -> Vec<(Ty<'tcx>, Vec<ReceiverAdjustment>)> {
+  let tcx = infcx.tcx;
+  let body_def_id = call.hir_id.owner.def_id;
+  let mut autoderef =
+    Autoderef::new(infcx, param_env, body_def_id, call.span, receiver_ty)
+      .silence_errors();
+  let tys = autoderef.by_ref().map(|(ty, _)| ty).collect::<Vec<_>>();
+
+  let mut adjustments = vec![];
+  let mut steps = vec![];
+  for (i, ty) in tys.into_iter().enumerate() {
+    if i > 0 {
+      let overloaded =
+        matches!(autoderef.steps()[i - 1].1, AutoderefKind::Overloaded);
+      adjustments.push(ReceiverAdjustment::Deref { overloaded });
+    }
+    steps.push((ty, adjustments.clone()));
+  }
+
+  if let Some((last, adjustments)) = steps.last() {
+    if let ty::Array(elem, _) = last.kind() {
+      let mut adjustments = adjustments.clone();
+      adjustments.push(ReceiverAdjustment::Unsize);
+      steps.push((Ty::new_slice(tcx, *elem), adjustments));
+    }
+  }
+
+  steps
+}
+
+/// Methods named like the call, of the inherent impls of the step types
+/// and of the traits in scope or bounding the step types.
+///
+/// Traits that aren't in scope are only searched when the compiler found
+/// no method, they're candidates if they're accessible from the body and
+/// implemented by one of the step types.
+fn candidates<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  call: &MethodCall<'tcx>,
+  steps: &[(Ty<'tcx>, Vec<ReceiverAdjustment>)],
+  search_all_traits: bool,
+) -> Vec<Candidate> {
+  let tcx = infcx.tcx;
+  let body_module = tcx.parent_module(call.hir_id).to_def_id();
+  let methods = |container: DefId| {
+    tcx
+      .associated_items(container)
+      .filter_by_name_unhygienic(call.name.name)
+      .filter(|item| item.kind == ty::AssocKind::Fn)
+      .copied()
+      .collect::<Vec<_>>()
+  };
+
+  let mut candidates = Vec::<Candidate>::new();
+  let mut push = |item: ty::AssocItem,
+                  trait_def_id: Option<DefId>,
+                  rejection: Option<CandidateRejection>| {
+    if candidates.iter().any(|c| c.def_id == item.def_id) {
+      return;
+    }
+    let rejection = if item.fn_has_self_parameter {
+      rejection.or_else(|| {
+        (!tcx
+          .visibility(item.def_id)
+          .is_accessible_from(body_module, tcx))
+        .then_some(CandidateRejection::Private)
+      })
+    } else {
+      Some(CandidateRejection::NotAMethod)
+    };
+    candidates.push(Candidate {
+      def_id: item.def_id,
+      trait_def_id,
+      rejection,
+    });
+  };
+
+  for (ty, _) in steps {
+    for impl_def_id in inherent_impls(tcx, *ty) {
+      for item in methods(impl_def_id) {
+        push(item, None, None);
+      }
+    }
+  }
+
+  let mut traits = tcx
+    .in_scope_traits(call.hir_id)
+    .unwrap_or_default()
+    .iter()
+    .map(|candidate| candidate.def_id)
+    .collect::<Vec<_>>();
+  traits.extend(bounding_traits(tcx, param_env, steps));
+  for &trait_def_id in &traits {
+    for item in methods(trait_def_id) {
+      push(item, Some(trait_def_id), None);
+    }
+  }
+
+  if search_all_traits {
+    let out_of_scope = tcx
+      .all_traits()
+      .filter(|&trait_def_id| {
+        !traits.contains(&trait_def_id)
+          && tcx
+            .visibility(trait_def_id)
+            .is_accessible_from(body_module, tcx)
+      })
+      .map(|trait_def_id| (trait_def_id, methods(trait_def_id)))
+      .filter(|(trait_def_id, items)| {
+        !items.is_empty()
+          && implemented_by_step(infcx, param_env, *trait_def_id, steps)
+      })
+      .take(MAX_OUT_OF_SCOPE_TRAITS)
+      .collect::<Vec<_>>();
+    for (trait_def_id, items) in out_of_scope {
+      for item in items {
+        push(
+          item,
+          Some(trait_def_id),
+          Some(CandidateRejection::NotInScope),
+        );
+      }
+    }
+  }
+
+  candidates
+}
+
+fn inherent_impls<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> Vec<DefId> {
+  let mut impls = match ty.kind() {
+    ty::Adt(def, _) => tcx.inherent_impls(def.did()).to_vec(),
+    ty::Foreign(def_id) => tcx.inherent_impls(*def_id).to_vec(),
+    ty::Dynamic(predicates, ..) => predicates
+      .principal_def_id()
+      .map(|def_id| tcx.inherent_impls(def_id).to_vec())
+      .unwrap_or_default(),
+    _ => vec![],
+  };
+
+  // Primitives, and some library types, have impls in other crates.
+  if let Some(simplified) = simplify_type(tcx, ty, TreatParams::AsRigid) {
+    for &impl_def_id in tcx.incoherent_impls(simplified) {
+      if !impls.contains(&impl_def_id) {
+        impls.push(impl_def_id);
+      }
+    }
+  }
+
+  impls
+}
+
+/// Traits bounding a step type, by a where-clause of a type parameter or
+/// as a trait object, which are usable without being in scope.
+fn bounding_traits<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  steps: &[(Ty<'tcx>, Vec<ReceiverAdjustment>)],
+) -> Vec<DefId> {
+  let mut traits = vec![];
+  for (ty, _) in steps {
+    match ty.kind() {
+      ty::Param(..) => traits.extend(
+        param_env
+          .caller_bounds()
+          .iter()
+          .filter_map(ty::Clause::as_trait_clause)
+          .filter(|t| t.self_ty().skip_binder() == *ty)
+          .map(ty::PolyTraitPredicate::def_id),
+      ),
+      ty::Dynamic(predicates, ..) => {
+        traits.extend(predicates.principal_def_id());
+      }
+      _ => {}
+    }
+  }
+
+  let mut elaborated = vec![];
+  for def_id in traits {
+    for supertrait in ty::elaborate::supertrait_def_ids(tcx, def_id) {
+      if !elaborated.contains(&supertrait) {
+        elaborated.push(supertrait);
+      }
+    }
+  }
+  elaborated
+}
+
+fn implemented_by_step<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  trait_def_id: DefId,
+  steps: &[(Ty<'tcx>, Vec<ReceiverAdjustment>)],
+) -> bool {
+  let tcx = infcx.tcx;
+  steps.iter().any(|(ty, _)| {
+    infcx.probe(|_| {
+      let args = infcx.fresh_args_for_item(rustc_span::DUMMY_SP, trait_def_id);
+      let args = tcx.mk_args_from_iter(
+        std::iter::once((*ty).into()).chain(args.iter().skip(1)),
+      );
+      let trait_ref = ty::TraitRef::new_from_args(tcx, trait_def_id, args);
+      let obligation =
+        Obligation::new(tcx, ObligationCause::dummy(), param_env, trait_ref);
+      infcx.predicate_may_hold(&obligation)
+    })
+  })
+}
+
+/// Whether `candidate` applies to the `adjusted` receiver type.
+fn consider<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  span: Span,
+  candidate: &Candidate,
+  adjusted: Ty<'tcx>,
+) -> StepOutcome {
+  let tcx = infcx.tcx;
+  infcx.probe(|_| {
+    let args = infcx.fresh_args_for_item(span, candidate.def_id);
+    let sig = tcx.fn_sig(candidate.def_id).instantiate(tcx, args);
+    let sig = infcx.instantiate_binder_with_fresh_vars(
+      span,
+      BoundRegionConversionTime::FnCall,
+      sig,
+    );
+    let Some(&receiver) = sig.inputs().first() else {
+      return StepOutcome::ReceiverMismatch;
+    };
+
+    if infcx
+      .at(&ObligationCause::dummy(), param_env)
+      .eq(DefineOpaqueTypes::No, receiver, adjusted)
+      .is_err()
+    {
+      return StepOutcome::ReceiverMismatch;
+    }
+
+    // The bounds checked while probing, those of the method itself are
+    // only checked once it's picked.
+    let container = tcx.parent(candidate.def_id);
+    let container_args = args.truncate_to(tcx, tcx.generics_of(container));
+    let predicates: Vec<ty::Predicate<'tcx>> = match candidate.trait_def_id {
+      Some(trait_def_id) => {
+        vec![
+          ty::TraitRef::new_from_args(tcx, trait_def_id, container_args)
+            .upcast(tcx),
+        ]
+      }
+      None => tcx
+        .predicates_of(container)
+        .instantiate(tcx, container_args)
+        .predicates
+        .into_iter()
+        .map(ty::Clause::as_predicate)
+        .collect(),
+    };
+
+    let unsatisfied = predicates.into_iter().find(|&predicate| {
+      let obligation =
+        Obligation::new(tcx, ObligationCause::dummy(), param_env, predicate);
+      !infcx.predicate_may_hold(&obligation)
+    });
+
+    match unsatisfied {
+      Some(predicate) => StepOutcome::Unsatisfied {
+        predicate: to_value(
+          infcx,
+          &PredicateWrapper(infcx.resolve_vars_if_possible(predicate)),
+        ),
+      },
+      None => StepOutcome::Applicable,
+    }
+  })
+}
+
+fn receiver_adjustment(adjust: &Adjust) -> Option<ReceiverAdjustment> {
+  match adjust {
+    Adjust::Deref(overloaded) => Some(ReceiverAdjustment::Deref {
+      overloaded: overloaded.is_some(),
+    }),
+    Adjust::Borrow(AutoBorrow::Ref(mutbl)) => {
+      Some(ReceiverAdjustment::Borrow {
+        mutable: matches!(mutbl, AutoBorrowMutability::Mut { .. }),
+      })
+    }
+    Adjust::Borrow(AutoBorrow::RawPtr(mutbl)) => {
+      Some(ReceiverAdjustment::RawBorrow {
+        mutable: mutbl.is_mut(),
+      })
+    }
+    Adjust::Pointer(PointerCoercion::Unsize) => {
+      Some(ReceiverAdjustment::Unsize)
+    }
+    Adjust::NeverToAny | Adjust::Pointer(..) | Adjust::ReborrowPin(..) => None,
+  }
+}
@@ -1,5 +1,8 @@
+mod coroutine;
 pub(crate) mod entry;
 mod hir;
+mod items;
+mod method_probe;
 mod transform;
 
 use std::collections::HashMap;
@@ -7,9 +10,10 @@ use std::collections::HashMap;
 use anyhow::Result;
 use argus_ext::ty::TyCtxtExt;
 use fluid_let::fluid_let;
-use rustc_hir::BodyId;
+use rustc_hir::{def_id::LocalDefId, BodyId};
 use rustc_middle::ty::TyCtxt;
 
+pub(crate) use self::items::has_item_obligations;
 pub(crate) use crate::types::intermediate::{
   EvaluationResult, FulfillmentData,
 };
@@ -91,6 +95,59 @@ pub fn bundle(tcx: TyCtxt, body_id: BodyId) -> Result<BodyBundle> {
   })
 }
 
+/// Obligations of an item outside of its body, e.g., an impl header or
+/// field types. Items whose obligations all hold are skipped.
+pub fn item_obligations(
+  tcx: TyCtxt,
+  item: LocalDefId,
+) -> Result<Option<ObligationsInBody>> {
+  log::trace!("item_obligations {item:?}");
+  let items = items::ItemObligations::new(tcx, item);
+  Ok((!items.is_empty()).then(|| items.to_output()))
+}
+
+/// Generate the proof tree for a target obligation of an item, see
+/// `OBLIGATION_TARGET` for target data.
+pub fn item_tree(
+  tcx: TyCtxt,
+  item: LocalDefId,
+) -> Result<Option<SerializedTree>> {
+  log::trace!("item_tree {item:?}");
+  items::ItemObligations::new(tcx, item)
+    .target_tree()
+    .transpose()
+}
+
+/// Self-contained output for an item, as `bundle` is for bodies.
+pub fn item_bundle(
+  tcx: TyCtxt,
+  item: LocalDefId,
+) -> Result<Option<BodyBundle>> {
+  log::trace!("item_bundle {item:?}");
+  let items = items::ItemObligations::new(tcx, item);
+  if items.is_empty() {
+    return Ok(None);
+  }
+
+  let trees = items
+    .necessary()
+    .into_iter()
+    .filter_map(|hash| Some((hash, items.tree(hash).ok()?)))
+    .collect();
+  let filename = tcx
+    .sess
+    .source_map()
+    .span_to_filename(tcx.def_span(item))
+    .prefer_local()
+    .to_string();
+
+  Ok(Some(BodyBundle {
+    filename,
+    body: items.to_output(),
+    trees,
+  }))
+}
+
 pub(crate) fn body_data(
   tcx: TyCtxt,
   body_id: BodyId,
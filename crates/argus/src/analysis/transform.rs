@@ -7,19 +7,26 @@ use argus_ext::{
 };
 use index_vec::IndexVec;
 use indexmap::IndexSet;
-use rustc_data_structures::fx::{FxHashMap as HashMap, FxIndexMap};
+use rustc_data_structures::fx::{
+  FxHashMap as HashMap, FxHashSet as HashSet, FxIndexMap,
+};
 use rustc_hir::{self as hir, BodyId, HirId};
-use rustc_infer::{infer::InferCtxt, traits::PredicateObligation};
-use rustc_middle::ty::{TyCtxt, TypeckResults};
+use rustc_infer::{
+  infer::{InferCtxt, TyCtxtInferExt},
+  traits::{self, PredicateObligation},
+};
+use rustc_middle::ty::{TyCtxt, TypeckResults, TypingMode};
 use rustc_span::Span;
 use rustc_utils::source_map::{range::CharRange, span::SpanExt};
 use serde_json as json;
 
 use super::{
+  coroutine,
   hir::{self as hier_hir, Bin, BinKind},
-  EvaluationResult,
+  method_probe, EvaluationResult,
 };
 use crate::{
+  dyn_compat,
   ext::InferCtxtExt as LocalInferCtxtExt,
   tls::UODIdx,
   types::{intermediate::*, *},
@@ -87,7 +94,7 @@ pub fn transform<'a, 'tcx: 'a>(
   obligations: Vec<Provenance<Obligation>>,
   obligation_data: &FullData<'tcx>,
   reported_trait_errors: &FxIndexMap<Span, Vec<ObligationHash>>,
-  bins: Vec<Bin>,
+  bins: Vec<Bin<'tcx>>,
 ) -> ObligationsInBody {
   let mut obligations_idx = IndexVec::<ObligationIdx, _>::default();
 
@@ -119,6 +126,7 @@ pub fn transform<'a, 'tcx: 'a>(
     exprs_to_hir_id: HashMap::default(),
     ambiguity_errors: IndexSet::default(),
     trait_errors: Vec::default(),
+    method_probes: Vec::default(),
     exprs: IndexVec::default(),
   };
 
@@ -139,7 +147,11 @@ pub fn transform<'a, 'tcx: 'a>(
     property_is_ok!(builder.is_valid(), "builder is invalid");
   }
 
-  ObligationsInBody::new(
+  builder.relate_held_across_await();
+  builder.relate_dyn_compatibility();
+  builder.relate_method_probes();
+
+  let mut body = ObligationsInBody::new(
     body_name,
     typeck_results.tainted_by_errors.is_some(),
     body_range,
@@ -147,7 +159,9 @@ pub fn transform<'a, 'tcx: 'a>(
     builder.trait_errors,
     builder.raw_obligations,
     builder.exprs,
-  )
+  );
+  body.method_probes = builder.method_probes;
+  body
 }
 
 struct ObligationsBuilder<'a, 'tcx: 'a> {
@@ -165,6 +179,7 @@ struct ObligationsBuilder<'a, 'tcx: 'a> {
   exprs_to_hir_id: HashMap<ExprIdx, HirId>,
   ambiguity_errors: IndexSet<AmbiguityError>,
   trait_errors: Vec<TraitError>,
+  method_probes: Vec<MethodProbe>,
   exprs: IndexVec<ExprIdx, Expr>,
 }
 
@@ -189,9 +204,15 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
     }
   }
 
-  fn sort_bins(&mut self, bins: Vec<Bin>) {
+  fn sort_bins(&mut self, bins: Vec<Bin<'tcx>>) {
     use ExprKind as EK;
 
+    let def_id = self.tcx.hir_body_owner_def_id(self.body_id);
+    let infcx = self
+      .tcx
+      .infer_ctxt()
+      .build(TypingMode::analysis_in_body(self.tcx, def_id));
+
     let hir = self.tcx.hir();
     let source_map = self.tcx.sess.source_map();
     for bin in bins {
@@ -199,10 +220,12 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
         hir_id,
         mut obligations,
         kind,
+        span: sub_span,
       } = bin;
       let span = self.hir_id_to_span(hir_id);
       let snippet = self.local_snip(span);
-      let Ok(range) = CharRange::from_span(span, source_map) else {
+      let range_span = sub_span.map_or(span, |s| self.to_local(s));
+      let Ok(range) = CharRange::from_span(range_span, source_map) else {
         log::error!(
           "failed to get range for HIR: {}",
           hir.node_to_string(hir_id)
@@ -220,6 +243,17 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
         BinKind::CallableExpr => EK::CallableExpr,
         BinKind::CallArg => EK::CallArg,
         BinKind::Call => EK::Call,
+        BinKind::MethodReceiver => EK::MethodReceiver,
+        BinKind::FieldAccess => EK::FieldAccess,
+        BinKind::Try => EK::Try,
+        BinKind::Await => EK::Await,
+        BinKind::Closure => EK::Closure,
+        BinKind::ForLoopIterator => EK::ForLoopIterator,
+        BinKind::Operator(op) => EK::Operator {
+          data: op.map(|op| op.to_output(&infcx)),
+        },
+        BinKind::Index => EK::Index,
+        BinKind::StructLiteral => EK::StructLiteral,
       };
 
       // We can only filter obligations that have known provenance data, so just split the
@@ -335,6 +369,8 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
           idx: expr_id,
           range: self.exprs[expr_id].range,
           hashes,
+          held_across_await: vec![],
+          dyn_compatibility: vec![],
         });
         continue;
       }
@@ -345,44 +381,55 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
 
       // A predicate did not match exactly, now we're scrambling
       // to find an expression by span, and pick an obligation.
-      let Some(err_hir_id) =
-        hier_hir::find_most_enclosing_node(self.tcx, self.body_id, *error_span)
-      else {
-        log::error!("reported error doesn't have an associated span ...");
-        continue;
-      };
-
-      let parent_ids_of_error = self
-        .exprs_to_hir_id
-        .iter()
-        .filter(|(_, expr_hir_id)| {
-          self.tcx.is_parent_of(**expr_hir_id, err_hir_id)
-        })
-        .collect::<Vec<_>>();
-
-      let Some((expr_id, _hir_id)) =
-        parent_ids_of_error.iter().copied().find(|(_, this_id)| {
-          // Find child-most expression that contains the error.
-          parent_ids_of_error
-            .iter()
-            .all(|(_, that_id)| self.tcx.is_parent_of(**that_id, **this_id))
-        })
-      else {
-        log::error!(
-          "failed to find most enclosing hir id for {parent_ids_of_error:?}"
-        );
+      let Some(expr_id) = self.innermost_expr_at(*error_span) else {
         continue;
       };
 
       // Mark the found Expr as containing an error.
       self.trait_errors.push(TraitError {
-        idx: *expr_id,
-        range: self.exprs[*expr_id].range,
+        idx: expr_id,
+        range: self.exprs[expr_id].range,
         hashes: vec![],
+        held_across_await: vec![],
+        dyn_compatibility: vec![],
       });
     }
   }
 
+  /// Find the child-most expression containing `span`.
+  fn innermost_expr_at(&self, span: Span) -> Option<ExprIdx> {
+    let Some(err_hir_id) =
+      hier_hir::find_most_enclosing_node(self.tcx, self.body_id, span)
+    else {
+      log::error!("reported error doesn't have an associated span ...");
+      return None;
+    };
+
+    let parent_ids_of_error = self
+      .exprs_to_hir_id
+      .iter()
+      .filter(|(_, expr_hir_id)| {
+        self.tcx.is_parent_of(**expr_hir_id, err_hir_id)
+      })
+      .collect::<Vec<_>>();
+
+    let Some((expr_id, _hir_id)) =
+      parent_ids_of_error.iter().copied().find(|(_, this_id)| {
+        // Find child-most expression that contains the error.
+        parent_ids_of_error
+          .iter()
+          .all(|(_, that_id)| self.tcx.is_parent_of(**that_id, **this_id))
+      })
+    else {
+      log::error!(
+        "failed to find most enclosing hir id for {parent_ids_of_error:?}"
+      );
+      return None;
+    };
+
+    Some(*expr_id)
+  }
+
   /// Find error nodes in the HIR and search for failed obligation failures in the node.
   fn relate_unreported_errors(&mut self) {
     // for all error nodes in the HIR, find a binned failure in that same node.
@@ -394,9 +441,7 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
       };
 
       let expr = &self.exprs[*eid];
-      let span = self.tcx.hir().span(hir_id);
-      let range = CharRange::from_span(span, self.tcx.sess.source_map())
-        .expect("failed to get range for reported trait error");
+      let range = expr.range;
 
       let hashes = expr
         .obligations
@@ -414,10 +459,199 @@ impl<'a, 'tcx: 'a> ObligationsBuilder<'a, 'tcx> {
         idx: *eid,
         range,
         hashes,
+        held_across_await: vec![],
+        dyn_compatibility: vec![],
       });
     }
   }
 
+  /// Explain failing auto traits of futures by the locals they hold across
+  /// await points.
+  ///
+  /// rustc reports these failures after type-checking the body, so the
+  /// expression is marked as a trait error if it isn't one already.
+  fn relate_held_across_await(&mut self) {
+    let mut seen = HashSet::default();
+    let mut related = self
+      .exprs
+      .indices()
+      .map(|expr_id| {
+        let (hashes, held) = self.held_across_await(expr_id, &mut seen);
+        (expr_id, hashes, held)
+      })
+      .collect::<Vec<_>>();
+    related.extend(self.stalled_held_across_await(&mut seen));
+
+    for (expr_id, hashes, held) in related {
+      if held.is_empty() {
+        continue;
+      }
+
+      match self.trait_errors.iter_mut().find(|e| e.idx == expr_id) {
+        Some(error) => error.held_across_await.extend(held),
+        None => self.trait_errors.push(TraitError {
+          idx: expr_id,
+          range: self.exprs[expr_id].range,
+          hashes,
+          held_across_await: held,
+          dyn_compatibility: vec![],
+        }),
+      }
+    }
+  }
+
+  fn held_across_await(
+    &self,
+    expr_id: ExprIdx,
+    seen: &mut HashSet<(Span, Span)>,
+  ) -> (Vec<ObligationHash>, Vec<HeldAcrossAwait>) {
+    let mut hashes = vec![];
+    let mut held = vec![];
+    for prov in self.obligations {
+      let Some(uoidx) = prov.full_data else {
+        continue;
+      };
+      let fdata = self.full_data.get(uoidx);
+      if fdata.result.is_ok() || !self.exprs[expr_id].obligations.contains(prov)
+      {
+        continue;
+      }
+
+      let locals =
+        coroutine::held_across_await(&fdata.infcx, &fdata.obligation);
+      for local in locals {
+        if seen.insert((local.binding, local.await_point)) {
+          held.extend(local.to_output(&fdata.infcx));
+          hashes.push(fdata.hash);
+        }
+      }
+    }
+    hashes.dedup();
+    (hashes, held)
+  }
+
+  /// Goals on the coroutines defined in this body are stalled during
+  /// type-checking, and solved once their witnesses are known.
+  fn stalled_held_across_await(
+    &self,
+    seen: &mut HashSet<(Span, Span)>,
+  ) -> Vec<(ExprIdx, Vec<ObligationHash>, Vec<HeldAcrossAwait>)> {
+    let tcx = self.tcx;
+    let stalled = &self.typeck_results.coroutine_stalled_predicates;
+    if stalled.is_empty() {
+      return vec![];
+    }
+
+    let def_id = tcx.hir_body_owner_def_id(self.body_id);
+    let infcx = tcx
+      .infer_ctxt()
+      .ignoring_regions()
+      .build(TypingMode::post_borrowck_analysis(tcx, def_id));
+    let param_env = tcx.param_env(def_id);
+
+    stalled
+      .iter()
+      .filter_map(|(predicate, cause)| {
+        let obligation =
+          traits::Obligation::new(tcx, cause.clone(), param_env, *predicate);
+        let held = coroutine::held_across_await(&infcx, &obligation)
+          .into_iter()
+          .filter(|local| seen.insert((local.binding, local.await_point)))
+          .filter_map(|local| local.to_output(&infcx))
+          .collect::<Vec<_>>();
+        if held.is_empty() {
+          return None;
+        }
+
+        let expr_id = self.innermost_expr_at(cause.span)?;
+        let hash = infcx.predicate_hash(predicate).into();
+        Some((expr_id, vec![hash], held))
+      })
+      .collect()
+  }
+
+  /// Explain trait errors whose obligations require a trait that isn't dyn
+  /// compatible, e.g., the well-formedness of `Box<dyn Trait>`.
+  fn relate_dyn_compatibility(&mut self) {
+    for error in &mut self.trait_errors {
+      let mut traits = vec![];
+      for prov in self.obligations {
+        let Some(uoidx) = prov.full_data else {
+          continue;
+        };
+        let fdata = self.full_data.get(uoidx);
+        if !error.hashes.contains(&fdata.hash) {
+          continue;
+        }
+
+        let predicate = fdata.obligation.predicate;
+        for def_id in dyn_compat::incompatible_traits(self.tcx, predicate) {
+          if !traits.contains(&def_id) {
+            traits.push(def_id);
+            error
+              .dyn_compatibility
+              .extend(dyn_compat::dyn_compatibility(&fdata.infcx, def_id));
+          }
+        }
+      }
+    }
+  }
+
+  /// Explain how the method calls of a body with errors were resolved, a
+  /// method that isn't found or the wrong method being picked is otherwise
+  /// only visible through the errors that follow.
+  fn relate_method_probes(&mut self) {
+    if self.typeck_results.tainted_by_errors.is_none() {
+      return;
+    }
+
+    let tcx = self.tcx;
+    let source_map = tcx.sess.source_map();
+    let def_id = tcx.hir_body_owner_def_id(self.body_id);
+    let infcx = tcx
+      .infer_ctxt()
+      .ignoring_regions()
+      .build(TypingMode::analysis_in_body(tcx, def_id));
+    let param_env = tcx.param_env(def_id);
+
+    for call in method_probe::method_calls(tcx, self.body_id) {
+      let expr = self.exprs_to_hir_id.iter().find_map(|(expr_id, hir_id)| {
+        (*hir_id == call.hir_id).then_some(*expr_id)
+      });
+
+      // Only calls that went wrong are explained, those without a method or
+      // with a failed obligation.
+      let has_failure = expr.is_some_and(|expr| {
+        self.exprs[expr]
+          .obligations
+          .iter()
+          .any(|&idx| self.raw_obligations[idx].result.is_err())
+      });
+      if !has_failure
+        && self
+          .typeck_results
+          .type_dependent_def(call.hir_id)
+          .is_some()
+      {
+        continue;
+      }
+
+      let Ok(range) =
+        CharRange::from_span(self.to_local(call.span), source_map)
+      else {
+        continue;
+      };
+      self.method_probes.extend(method_probe::probe(
+        &infcx,
+        param_env,
+        self.typeck_results,
+        &call,
+        range,
+        expr,
+      ));
+    }
+  }
+
   #[cfg(any(feature = "testing", debug_assertions))]
   fn is_valid(&self) -> anyhow::Result<()> {
     for obl in &self.raw_obligations {
@@ -0,0 +1,119 @@
+//! Attribution of obligations introduced by derive macros.
+//!
+//! Obligations from a derive expansion are located at the derive's path in
+//! the attribute list, e.g., `Serialize` in `#[derive(Debug, Serialize)]`.
+//! Derives usually reuse the spans of the fields they generate code for,
+//! which finds the field whose type the obligation is about.
+
+use argus_ser as ser;
+use rustc_hir::def::DefKind;
+use rustc_infer::{infer::InferCtxt, traits::PredicateObligation};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::{
+  def_id::DefId,
+  hygiene::{ExpnKind, MacroKind},
+  Span,
+};
+use rustc_utils::source_map::range::CharRange;
+use serde::Serialize;
+
+use crate::{
+  tls,
+  types::{DeriveField, DeriveOrigin},
+};
+
+/// The derive whose expansion introduced `obligation`, if any.
+pub(crate) fn derive_origin<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  obligation: &PredicateObligation<'tcx>,
+) -> Option<DeriveOrigin> {
+  let tcx = infcx.tcx;
+  let span = obligation.cause.span;
+  let (derive, call_site) =
+    span.macro_backtrace().find_map(|expn| match expn.kind {
+      ExpnKind::Macro(MacroKind::Derive, name) => Some((name, expn.call_site)),
+      _ => None,
+    })?;
+
+  let source_map = tcx.sess.source_map();
+  let range = CharRange::from_span(call_site, source_map).ok()?;
+
+  let self_ty = obligation
+    .predicate
+    .as_clause()
+    .and_then(ty::Clause::as_trait_clause)
+    .map(|trait_pred| {
+      let self_ty = trait_pred.skip_binder().self_ty();
+      tcx.erase_regions(infcx.resolve_vars_if_possible(self_ty))
+    });
+  let field = derived_adt(tcx, obligation.cause.body_id.to_def_id())
+    .and_then(|adt| derived_field(tcx, adt, span, self_ty))
+    .and_then(|(variant, field)| {
+      let field_ty = tcx.type_of(field.did).instantiate_identity();
+      Some(DeriveField {
+        name: field.name.to_string(),
+        variant: variant.map(|variant| variant.name.to_string()),
+        ty: tls::unsafe_access_interner(|ty_interner| {
+          ser::to_value_expect(infcx, ty_interner, &TyWrapper(field_ty))
+        }),
+        range: CharRange::from_span(tcx.def_span(field.did), source_map)
+          .ok()?,
+      })
+    });
+
+  Some(DeriveOrigin {
+    derive: derive.to_string(),
+    range,
+    field,
+  })
+}
+
+#[derive(Serialize)]
+struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+/// The type a derive was applied to, the self type of the impl containing
+/// `def_id`.
+fn derived_adt(tcx: TyCtxt, def_id: DefId) -> Option<ty::AdtDef> {
+  let mut current = def_id;
+  while !matches!(tcx.def_kind(current), DefKind::Impl { .. }) {
+    current = tcx.opt_parent(current)?;
+  }
+  match tcx.type_of(current).instantiate_identity().kind() {
+    ty::Adt(adt, _) => Some(*adt),
+    _ => None,
+  }
+}
+
+/// The field of `adt` whose definition contains `span`, or failing that,
+/// the only field whose type is `self_ty`, with its variant for enums.
+fn derived_field<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  adt: ty::AdtDef<'tcx>,
+  span: Span,
+  self_ty: Option<Ty<'tcx>>,
+) -> Option<(Option<&'tcx ty::VariantDef>, &'tcx ty::FieldDef)> {
+  let fields = || {
+    adt.variants().iter().flat_map(move |def| {
+      let variant = adt.is_enum().then_some(def);
+      def.fields.iter().map(move |field| (variant, field))
+    })
+  };
+
+  // Spans of the expansion keep the positions of the field they came from.
+  let span = span.data();
+  let containing = fields().find(|(_, field)| {
+    let field_span = tcx.def_span(field.did).data();
+    field_span.lo <= span.lo && span.hi <= field_span.hi
+  });
+  if containing.is_some() {
+    return containing;
+  }
+
+  let self_ty = self_ty?.peel_refs();
+  let mut matching = fields().filter(|(_, field)| {
+    let field_ty = tcx.type_of(field.did).instantiate_identity();
+    tcx.erase_regions(field_ty).peel_refs() == self_ty
+  });
+  let field = matching.next()?;
+  matching.next().is_none().then_some(field)
+}
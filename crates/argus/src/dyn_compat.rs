@@ -0,0 +1,237 @@
+//! Dyn-compatibility violations of traits used as trait objects.
+//!
+//! A `dyn Trait` type is only well-formed if `Trait` is dyn compatible, the
+//! solver checks this with `DynCompatible` goals, which say nothing about
+//! *why* a trait isn't. The violations come from the compiler's own query,
+//! with the fixes it would suggest.
+
+use argus_ser as ser;
+use rustc_hir::{self as hir, def_id::DefId};
+use rustc_infer::infer::InferCtxt;
+use rustc_middle::{
+  traits::{
+    DynCompatibilityViolation as Violation,
+    DynCompatibilityViolationSolution as Solution, MethodViolationCode,
+  },
+  ty::{self, TyCtxt, TypeSuperVisitable, TypeVisitable, TypeVisitor},
+};
+use rustc_span::{Span, Symbol};
+
+use crate::{
+  tls,
+  types::{
+    DynCompatibility, DynCompatibilityFix, DynCompatibilityViolation,
+    DynCompatibilityViolationKind as Kind,
+  },
+};
+
+/// Traits that aren't dyn compatible but are required to be by `predicate`,
+/// either directly or by a `dyn Trait` type it mentions.
+pub(crate) fn incompatible_traits<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  predicate: ty::Predicate<'tcx>,
+) -> Vec<DefId> {
+  if let ty::PredicateKind::DynCompatible(def_id) =
+    predicate.kind().skip_binder()
+  {
+    return if tcx.is_dyn_compatible(def_id) {
+      vec![]
+    } else {
+      vec![def_id]
+    };
+  }
+
+  let mut finder = DynFinder { tcx, found: vec![] };
+  predicate.visit_with(&mut finder);
+  finder.found
+}
+
+struct DynFinder<'tcx> {
+  tcx: TyCtxt<'tcx>,
+  found: Vec<DefId>,
+}
+
+impl<'tcx> TypeVisitor<TyCtxt<'tcx>> for DynFinder<'tcx> {
+  fn visit_ty(&mut self, ty: ty::Ty<'tcx>) {
+    if let ty::Dynamic(predicates, ..) = ty.kind() {
+      if let Some(def_id) = predicates.principal_def_id() {
+        if !self.tcx.is_dyn_compatible(def_id) && !self.found.contains(&def_id)
+        {
+          self.found.push(def_id);
+        }
+      }
+    }
+    ty.super_visit_with(self);
+  }
+}
+
+/// The violations of each trait that isn't dyn compatible but is required
+/// to be by one of `predicates`.
+pub(crate) fn for_predicates<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  predicates: impl IntoIterator<Item = ty::Predicate<'tcx>>,
+) -> Vec<DynCompatibility> {
+  let mut traits = vec![];
+  for predicate in predicates {
+    for def_id in incompatible_traits(infcx.tcx, predicate) {
+      if !traits.contains(&def_id) {
+        traits.push(def_id);
+      }
+    }
+  }
+
+  traits
+    .into_iter()
+    .filter_map(|def_id| dyn_compatibility(infcx, def_id))
+    .collect()
+}
+
+/// The dyn-compatibility violations of `trait_def_id`, if it has any.
+pub(crate) fn dyn_compatibility(
+  infcx: &InferCtxt,
+  trait_def_id: DefId,
+) -> Option<DynCompatibility> {
+  let tcx = infcx.tcx;
+  let violations = tcx.dyn_compatibility_violations(trait_def_id);
+  if violations.is_empty() {
+    return None;
+  }
+
+  let trait_ = tls::unsafe_access_interner(|ty_interner| {
+    ser::to_value_expect(infcx, ty_interner, &ser::PathDefNoArgs(trait_def_id))
+  });
+  let violations = violations
+    .iter()
+    .map(|violation| DynCompatibilityViolation {
+      kind: kind(violation),
+      name: name(violation).map(|name| name.to_string()),
+      message: violation.error_msg().into_owned(),
+      locations: violation
+        .spans()
+        .into_iter()
+        .filter_map(|span| ser::DefLocation::from_span(span, tcx))
+        .collect(),
+      fixes: fixes(tcx, trait_def_id, violation),
+    })
+    .collect();
+
+  Some(DynCompatibility { trait_, violations })
+}
+
+fn kind(violation: &Violation) -> Kind {
+  use MethodViolationCode as MVC;
+  match violation {
+    Violation::SizedSelf(..) => Kind::SizedSelf,
+    Violation::SupertraitSelf(..) => Kind::SupertraitSelf,
+    Violation::SupertraitNonLifetimeBinder(..) => {
+      Kind::SupertraitNonLifetimeBinder
+    }
+    Violation::Method(_, code, _) => match code {
+      MVC::StaticMethod(..) => Kind::StaticMethod,
+      MVC::ReferencesSelfInput(..) => Kind::ReferencesSelfInput,
+      MVC::ReferencesSelfOutput => Kind::ReferencesSelfOutput,
+      MVC::ReferencesImplTraitInTrait(..) => Kind::ReferencesImplTraitInTrait,
+      MVC::AsyncFn => Kind::AsyncFn,
+      MVC::WhereClauseReferencesSelf => Kind::WhereClauseReferencesSelf,
+      MVC::Generic => Kind::GenericMethod,
+      MVC::UndispatchableReceiver(..) => Kind::UndispatchableReceiver,
+    },
+    Violation::AssocConst(..) => Kind::AssocConst,
+    Violation::GAT(..) => Kind::GenericAssocType,
+  }
+}
+
+fn name(violation: &Violation) -> Option<Symbol> {
+  match violation {
+    Violation::Method(name, ..)
+    | Violation::AssocConst(name, _)
+    | Violation::GAT(name, _) => Some(*name),
+    _ => None,
+  }
+}
+
+/// The compiler's suggestions, and for methods, a `where Self: Sized`
+/// bound which exempts them from dyn compatibility.
+fn fixes(
+  tcx: TyCtxt,
+  trait_def_id: DefId,
+  violation: &Violation,
+) -> Vec<DynCompatibilityFix> {
+  let location = |span: Span| ser::DefLocation::from_span(span, tcx);
+
+  let mut fixes = vec![];
+  match violation.solution() {
+    // Moving the item to another trait is suggested below for all items.
+    Solution::None | Solution::MoveToAnotherTrait(_) => {}
+    Solution::AddSelfOrMakeSized {
+      add_self_sugg: (suggestion, span),
+      make_sized_sugg,
+      ..
+    } => {
+      fixes.extend(location(span).map(|location| {
+        DynCompatibilityFix::AddSelfParameter {
+          suggestion,
+          location,
+        }
+      }));
+      fixes.extend(require_sized(tcx, make_sized_sugg));
+    }
+    Solution::ChangeToRefSelf(_, span) => {
+      fixes.extend(
+        location(span)
+          .map(|location| DynCompatibilityFix::ChangeToRefSelf { location }),
+      );
+    }
+  }
+
+  if let Violation::Method(name, code, _) = violation {
+    if !matches!(code, MethodViolationCode::StaticMethod(..)) {
+      fixes.extend(
+        method_generics(tcx, trait_def_id, *name)
+          .map(|generics| {
+            (
+              format!("{} Self: Sized", generics.add_where_or_trailing_comma()),
+              generics.tail_span_for_predicate_suggestion(),
+            )
+          })
+          .and_then(|sugg| require_sized(tcx, sugg)),
+      );
+    }
+  }
+
+  if let Some(name) = name(violation) {
+    fixes.push(DynCompatibilityFix::MoveToAnotherTrait {
+      name: name.to_string(),
+    });
+  }
+
+  fixes
+}
+
+fn require_sized(
+  tcx: TyCtxt,
+  (suggestion, span): (String, Span),
+) -> Option<DynCompatibilityFix> {
+  let location = ser::DefLocation::from_span(span, tcx)?;
+  Some(DynCompatibilityFix::RequireSized {
+    suggestion,
+    location,
+  })
+}
+
+/// Generics of the local trait method `name`.
+fn method_generics(
+  tcx: TyCtxt<'_>,
+  trait_def_id: DefId,
+  name: Symbol,
+) -> Option<&hir::Generics<'_>> {
+  let method = tcx
+    .associated_items(trait_def_id)
+    .filter_by_name_unhygienic(name)
+    .find(|item| item.kind == ty::AssocKind::Fn)?;
+  let local = method.def_id.as_local()?;
+  match tcx.hir_node_by_def_id(local) {
+    hir::Node::TraitItem(item) => Some(item.generics),
+    _ => None,
+  }
+}
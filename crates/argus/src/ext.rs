@@ -1,15 +1,17 @@
 use argus_ext::{
   infer::InferCtxtExt as ArgusInferCtxtExt,
-  ty::{PredicateExt, PredicateObligationExt},
+  ty::{EvaluationResultExt, PredicateExt, PredicateObligationExt},
 };
 use argus_ser as ser;
 use rustc_hir::BodyId;
 use rustc_infer::{infer::InferCtxt, traits::PredicateObligation};
-use rustc_middle::ty::{self, Predicate};
+use rustc_middle::ty::{self, Predicate, Upcast};
+use rustc_utils::source_map::range::CharRange;
 use serde::Serialize;
 
 use crate::{
   analysis::{EvaluationResult, FulfillmentData},
+  derives, dyn_compat, outlives, projection,
   types::{Obligation, ObligationNecessity},
 };
 
@@ -26,6 +28,13 @@ pub trait InferCtxtExt<'tcx> {
     fdata: FulfillmentData<'_, 'tcx>,
   ) -> Obligation;
 
+  /// Like `erase_non_local_data`, for obligations located outside of bodies.
+  fn erase_non_local_data_at(
+    &self,
+    range: CharRange,
+    fdata: FulfillmentData<'_, 'tcx>,
+  ) -> Obligation;
+
   fn guess_predicate_necessity(
     &self,
     p: &Predicate<'tcx>,
@@ -65,8 +74,17 @@ impl<'tcx> InferCtxtExt<'tcx> for InferCtxt<'tcx> {
       )
     };
 
+    // Other predicates can fail because a `dyn Trait` isn't dyn compatible,
+    // e.g., the well-formedness of `Box<dyn Trait>`, this is worth showing.
+    let requires_incompatible_dyn =
+      || !dyn_compat::incompatible_traits(self.tcx, *p).is_empty();
+
     if !is_writeable() || p.is_lhs_unit() {
-      ON::No
+      if requires_incompatible_dyn() {
+        ON::OnError
+      } else {
+        ON::No
+      }
     } else if (p.is_trait_predicate() && is_rhs_lang_item())
       || !p.is_trait_predicate()
     {
@@ -119,6 +137,15 @@ impl<'tcx> InferCtxtExt<'tcx> for InferCtxt<'tcx> {
     &self,
     body_id: BodyId,
     fdata: FulfillmentData<'_, 'tcx>,
+  ) -> Obligation {
+    let range = fdata.obligation.range(&self.tcx, body_id);
+    self.erase_non_local_data_at(range, fdata)
+  }
+
+  fn erase_non_local_data_at(
+    &self,
+    range: CharRange,
+    fdata: FulfillmentData<'_, 'tcx>,
   ) -> Obligation {
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
@@ -128,7 +155,6 @@ impl<'tcx> InferCtxtExt<'tcx> for InferCtxt<'tcx> {
     );
 
     let obl = &fdata.obligation;
-    let range = obl.range(&self.tcx, body_id);
     let necessity = self.obligation_necessity(obl);
     let obligation = crate::tls::unsafe_access_interner(|ty_intern| {
       ser::to_value_expect(self, ty_intern, &Wrapper(obl))
@@ -141,6 +167,24 @@ impl<'tcx> InferCtxtExt<'tcx> for InferCtxt<'tcx> {
       kind: fdata.kind(),
       necessity,
       result: fdata.result,
+      projection_mismatch: fdata
+        .result
+        .is_no()
+        .then(|| projection::projection_mismatch(self, obl))
+        .flatten(),
+      outlives: outlives::explain(
+        self,
+        obl.predicate,
+        Some(obl.cause.body_id),
+        obl
+          .cause
+          .code()
+          .peel_derives_with_predicate()
+          .1
+          .map(|parent| parent.upcast(self.tcx)),
+        outlives::required_by(obl.cause.code()),
+      ),
+      derive: derives::derive_origin(self, obl),
     }
   }
 }
@@ -1,7 +1,7 @@
 //! This is a copy of the `BodyFinder` from `rustc_utils` but it
 //! does *not* skip const/static items. Funny enough, these items
 //! often have important trait constraints evaluated (think derive macros).
-use rustc_hir::{intravisit::Visitor, BodyId};
+use rustc_hir::{def_id::LocalDefId, intravisit::Visitor, BodyId};
 use rustc_middle::{hir::nested_filter::OnlyBodies, ty::TyCtxt};
 use rustc_span::Span;
 use rustc_utils::{block_timer, SpanExt};
@@ -66,3 +66,31 @@ pub fn find_enclosing_bodies(
   bodies.sort_by_key(|(span, _)| span.size());
   bodies.into_iter().map(|(_, id)| id)
 }
+
+/// Finds all items with obligations outside of their bodies, e.g., impl
+/// headers and signatures.
+pub fn find_items(tcx: TyCtxt) -> Vec<(Span, LocalDefId)> {
+  block_timer!("find_items");
+  tcx
+    .hir_crate_items(())
+    .definitions()
+    .filter(|&def_id| {
+      crate::analysis::has_item_obligations(tcx.def_kind(def_id))
+    })
+    .map(|def_id| {
+      let span = tcx.hir().span(tcx.local_def_id_to_hir_id(def_id));
+      (span, def_id)
+    })
+    .collect()
+}
+
+/// Finds all the items that enclose the given span, from innermost to outermost
+pub fn find_enclosing_items(
+  tcx: TyCtxt,
+  sp: Span,
+) -> impl Iterator<Item = LocalDefId> {
+  let mut items = find_items(tcx);
+  items.retain(|(other, _)| other.contains(sp));
+  items.sort_by_key(|(span, _)| span.size());
+  items.into_iter().map(|(_, id)| id)
+}
@@ -6,34 +6,43 @@
   clippy::must_use_candidate,
   clippy::module_name_repetitions
 )]
+extern crate rustc_ast;
 extern crate rustc_ast_ir;
 extern crate rustc_data_structures;
 #[cfg(feature = "testing")]
 extern crate rustc_driver;
 extern crate rustc_hashes;
 extern crate rustc_hir;
+extern crate rustc_hir_analysis;
 extern crate rustc_hir_typeck;
 extern crate rustc_infer;
 
-#[cfg(feature = "testing")]
 extern crate rustc_errors;
 
 #[cfg(feature = "testing")]
 extern crate rustc_interface;
 extern crate rustc_middle;
+extern crate rustc_parse;
+extern crate rustc_session;
 extern crate rustc_span;
 extern crate rustc_trait_selection;
 
 mod aadebug;
 pub mod analysis;
+mod derives;
+mod dyn_compat;
 pub mod ext;
 pub mod find_bodies; // TODO: remove when upstreamed to rustc-plugin
+mod outlives;
+mod projection;
 mod proof_tree;
 #[cfg(feature = "testing")]
 pub mod test_utils;
 mod tls;
 pub mod types;
 
+pub use aadebug::weights;
+
 #[cfg(feature = "testing")]
 mod tests {
   #[test]
@@ -0,0 +1,413 @@
+//! Explanations of outlives obligations.
+//!
+//! Regions are erased or printed without provenance, so an outlives
+//! obligation on its own says little about why it's required. It's
+//! explained by the bound that required it, the path from its type to the
+//! component that must outlive the region, and where the region comes from.
+
+use argus_ser as ser;
+use rustc_hir::{
+  def::DefKind,
+  def_id::{DefId, LocalDefId},
+};
+use rustc_infer::{infer::InferCtxt, traits::ObligationCauseCode};
+use rustc_middle::ty::{self, GenericArgKind, GenericParamDefKind, Ty, TyCtxt};
+use rustc_span::Span;
+use serde::Serialize;
+
+use crate::{
+  tls,
+  types::{
+    OutlivesComponent, OutlivesExplanation, OutlivesRequirement, OutlivesStep,
+    RegionOrigin,
+  },
+};
+
+/// Limit on the length of a path through the components of a type.
+const MAX_DEPTH: usize = 32;
+
+/// Explain `predicate`, if it's an outlives predicate.
+///
+/// `parent` is the predicate that required it, and `required_by` the item
+/// and bound span of the where-clause that did. Types that are still
+/// inference variables, e.g., a closure passed to a bounded parameter before
+/// it's checked, end the path immediately.
+pub(crate) fn explain<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  predicate: ty::Predicate<'tcx>,
+  owner: Option<LocalDefId>,
+  parent: Option<ty::Predicate<'tcx>>,
+  required_by: Option<(DefId, Span)>,
+) -> Option<OutlivesExplanation> {
+  let tcx = infcx.tcx;
+  let predicate = infcx.resolve_vars_if_possible(predicate);
+  let (sub, region): (ty::GenericArg<'tcx>, _) =
+    match predicate.kind().skip_binder() {
+      ty::PredicateKind::Clause(ty::ClauseKind::TypeOutlives(
+        ty::OutlivesPredicate(ty, region),
+      )) => (ty.into(), region),
+      ty::PredicateKind::Clause(ty::ClauseKind::RegionOutlives(
+        ty::OutlivesPredicate(sub, region),
+      )) => (sub.into(), region),
+      _ => return None,
+    };
+
+  let mut steps = vec![];
+  if let Some(ty) = sub.as_type() {
+    if !walk(tcx, ty, region, &mut steps) {
+      steps = vec![(ty, None)];
+    }
+  }
+  let steps = steps
+    .into_iter()
+    .map(|(ty, component)| OutlivesStep {
+      ty: tls::unsafe_access_interner(|ty_interner| {
+        ser::to_value_expect(infcx, ty_interner, &TyWrapper(ty))
+      }),
+      component,
+    })
+    .collect();
+
+  let region_value = tls::unsafe_access_interner(|ty_interner| {
+    ser::to_value_expect(infcx, ty_interner, &RegionWrapper(region))
+  });
+
+  Some(OutlivesExplanation {
+    region: region_value,
+    origin: origin(tcx, region, owner, parent),
+    required_by: required_by.map(|(def_id, span)| OutlivesRequirement {
+      item: tls::unsafe_access_interner(|ty_interner| {
+        ser::to_value_expect(infcx, ty_interner, &ser::PathDefNoArgs(def_id))
+      }),
+      location: ser::DefLocation::from_span(span, tcx),
+    }),
+    steps,
+    implied_by: owner.and_then(|owner| implied_by(tcx, owner, sub, region)),
+  })
+}
+
+/// The item and bound span of the where-clause behind `code`.
+pub(crate) fn required_by(code: &ObligationCauseCode) -> Option<(DefId, Span)> {
+  match code.peel_derives() {
+    ObligationCauseCode::WhereClause(def_id, span)
+    | ObligationCauseCode::WhereClauseInExpr(def_id, span, ..) => {
+      Some((*def_id, *span))
+    }
+    _ => None,
+  }
+}
+
+#[derive(Serialize)]
+struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+#[derive(Serialize)]
+struct RegionWrapper<'tcx>(
+  #[serde(with = "ser::ty::RegionDef")] ty::Region<'tcx>,
+);
+
+fn origin<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  region: ty::Region<'tcx>,
+  owner: Option<LocalDefId>,
+  parent: Option<ty::Predicate<'tcx>>,
+) -> RegionOrigin {
+  match region.kind() {
+    ty::ReStatic if parent.is_some_and(has_static_trait_object) => {
+      RegionOrigin::TraitObject
+    }
+    ty::ReStatic => RegionOrigin::Static,
+    ty::ReEarlyParam(param) => RegionOrigin::Named {
+      name: param.name.to_string(),
+      location: owner.and_then(|owner| {
+        let generics = tcx.generics_of(owner);
+        let index = param.index as usize;
+        if index >= generics.count() {
+          return None;
+        }
+        let def = generics.param_at(index, tcx);
+        (matches!(def.kind, GenericParamDefKind::Lifetime)
+          && def.name == param.name)
+          .then(|| ser::DefLocation::from_def_id_tcx(def.def_id, tcx))
+          .flatten()
+      }),
+    },
+    ty::ReLateParam(ty::LateParamRegion {
+      kind: ty::LateParamRegionKind::Named(def_id, name),
+      ..
+    }) => RegionOrigin::Named {
+      name: name.to_string(),
+      location: ser::DefLocation::from_def_id_tcx(def_id, tcx),
+    },
+    ty::ReVar(_) => RegionOrigin::Local,
+    _ => RegionOrigin::Anonymous,
+  }
+}
+
+/// Does the trait predicate `predicate` mention a `dyn Trait + 'static`?
+fn has_static_trait_object(predicate: ty::Predicate<'_>) -> bool {
+  let Some(trait_pred) = predicate.as_trait_clause() else {
+    return false;
+  };
+  trait_pred
+    .skip_binder()
+    .trait_ref
+    .args
+    .iter()
+    .flat_map(ty::GenericArg::walk)
+    .any(|arg| {
+      matches!(
+        arg.as_type().map(Ty::kind),
+        Some(ty::Dynamic(_, region, _)) if region.is_static()
+      )
+    })
+}
+
+/// Find the path from `ty` to the first component that doesn't trivially
+/// outlive `region`, a type parameter, alias, or type with another region.
+fn walk<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  ty: Ty<'tcx>,
+  region: ty::Region<'tcx>,
+  steps: &mut Vec<(Ty<'tcx>, Option<OutlivesComponent>)>,
+) -> bool {
+  let is_other = |r: ty::Region<'tcx>| r != region && !r.is_static();
+  let is_leaf = match ty.kind() {
+    ty::Param(..) | ty::Placeholder(..) | ty::Alias(..) | ty::Infer(..) => true,
+    ty::Ref(r, ..) | ty::Dynamic(_, r, _) => is_other(*r),
+    ty::Adt(_, args) => args.regions().any(is_other),
+    _ => false,
+  };
+
+  if is_leaf {
+    steps.push((ty, None));
+    return true;
+  }
+
+  if steps.len() >= MAX_DEPTH {
+    return false;
+  }
+
+  for (component, child) in components(tcx, ty) {
+    steps.push((ty, Some(component)));
+    if walk(tcx, child, region, steps) {
+      return true;
+    }
+    steps.pop();
+  }
+
+  false
+}
+
+fn components<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  ty: Ty<'tcx>,
+) -> Vec<(OutlivesComponent, Ty<'tcx>)> {
+  match *ty.kind() {
+    ty::Closure(def_id, args) => {
+      captures(tcx, def_id, args.as_closure().tupled_upvars_ty())
+    }
+    ty::CoroutineClosure(def_id, args) => {
+      captures(tcx, def_id, args.as_coroutine_closure().tupled_upvars_ty())
+    }
+    ty::Coroutine(def_id, args) => {
+      captures(tcx, def_id, args.as_coroutine().tupled_upvars_ty())
+    }
+    ty::Adt(_, args) => args
+      .iter()
+      .enumerate()
+      .filter_map(|(index, arg)| {
+        arg
+          .as_type()
+          .map(|ty| (OutlivesComponent::GenericArg { index }, ty))
+      })
+      .collect(),
+    ty::Ref(_, ty, _) | ty::RawPtr(ty, _) => {
+      vec![(OutlivesComponent::Pointee, ty)]
+    }
+    ty::Array(ty, _) | ty::Slice(ty) => vec![(OutlivesComponent::Element, ty)],
+    ty::Tuple(tys) => tys
+      .iter()
+      .enumerate()
+      .map(|(index, ty)| (OutlivesComponent::TupleField { index }, ty))
+      .collect(),
+    _ => vec![],
+  }
+}
+
+/// The types of the captures of a closure or coroutine.
+///
+/// Outlives obligations are solved while the inspected body is type-checked,
+/// so the captured places come from the regular type-check results of the
+/// body instead. Captures are only named if those match the upvars.
+fn captures<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  def_id: DefId,
+  tupled_upvars: Ty<'tcx>,
+) -> Vec<(OutlivesComponent, Ty<'tcx>)> {
+  let ty::Tuple(tys) = tupled_upvars.kind() else {
+    return vec![];
+  };
+
+  let places = def_id
+    .as_local()
+    .map(|def_id| {
+      let root = tcx.typeck_root_def_id(def_id.to_def_id()).expect_local();
+      tcx
+        .typeck(root)
+        .closure_min_captures_flattened(def_id)
+        .collect::<Vec<_>>()
+    })
+    .filter(|places| places.len() == tys.len());
+  tys
+    .iter()
+    .enumerate()
+    .map(|(i, ty)| {
+      let place = places.as_ref().map(|places| places[i]);
+      let component = OutlivesComponent::Capture {
+        name: place.map(|place| place.to_string(tcx)),
+        location: place.and_then(|place| {
+          ser::DefLocation::from_span(place.get_path_span(tcx), tcx)
+        }),
+      };
+      (component, ty)
+    })
+    .collect()
+}
+
+/// The signature type of `owner` whose well-formedness implies that `sub`
+/// outlives `region`, only references are considered, `&'a T` implies
+/// `T: 'a`.
+fn implied_by<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  owner: LocalDefId,
+  sub: ty::GenericArg<'tcx>,
+  region: ty::Region<'tcx>,
+) -> Option<ser::DefLocation> {
+  let owner = tcx.typeck_root_def_id(owner.to_def_id()).as_local()?;
+  if !matches!(
+    tcx.def_kind(owner),
+    DefKind::Fn | DefKind::AssocFn | DefKind::Impl { .. }
+  ) {
+    return None;
+  }
+
+  // Only parameters and regions are implied to outlive the reference.
+  let is_implied = match sub.unpack() {
+    GenericArgKind::Type(ty) => matches!(ty.kind(), ty::Param(..)),
+    GenericArgKind::Lifetime(..) => true,
+    GenericArgKind::Const(..) => false,
+  };
+  if !is_implied {
+    return None;
+  }
+
+  tcx
+    .assumed_wf_types(owner)
+    .iter()
+    .find(|(wf_ty, _)| {
+      wf_ty.walk().any(|arg| match arg.as_type().map(Ty::kind) {
+        Some(ty::Ref(r, inner, _)) if *r == region => {
+          inner.walk().any(|arg| arg == sub)
+        }
+        _ => false,
+      })
+    })
+    .and_then(|(_, span)| ser::DefLocation::from_span(*span, tcx))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use rustc_hir::def::DefKind;
+
+  use super::*;
+  use crate::test_utils as tu;
+
+  const ITEMS: &str = r"
+fn param<T>(_: Box<Vec<(u8, T)>>) {}
+fn region<'a>(_: Option<&'a u8>) {}
+fn outlives(_: (u8, [&'static str; 2])) {}
+fn capture<'a>(x: &'a u8) -> impl Fn() + 'a {
+  let pair = (1, x);
+  move || {
+    let _borrowed = pair.1;
+  }
+}
+";
+
+  /// A type on the path and the component leading out of it.
+  type Step = (String, serde_json::Value);
+
+  /// The path from the first input of each function, or the type of each
+  /// closure, to the component that doesn't outlive `'static`.
+  fn paths() -> HashMap<String, Option<Vec<Step>>> {
+    let mut paths = HashMap::new();
+    tu::compile_normal(ITEMS, |tcx| {
+      for def_id in tcx.hir_body_owners() {
+        let ty = match tcx.def_kind(def_id) {
+          DefKind::Fn => {
+            let sig = tcx.liberate_late_bound_regions(
+              def_id.to_def_id(),
+              tcx.fn_sig(def_id).instantiate_identity(),
+            );
+            sig.inputs()[0]
+          }
+          DefKind::Closure => tcx.type_of(def_id).instantiate_identity(),
+          _ => continue,
+        };
+
+        let mut steps = vec![];
+        let path =
+          walk(tcx, ty, tcx.lifetimes.re_static, &mut steps).then(|| {
+            steps
+              .into_iter()
+              .map(|(ty, component)| {
+                (ty.to_string(), serde_json::to_value(component).unwrap())
+              })
+              .collect()
+          });
+        let name = match tcx.def_kind(def_id) {
+          DefKind::Closure => "closure".to_owned(),
+          _ => tcx.item_name(def_id.to_def_id()).to_string(),
+        };
+        paths.insert(name, path);
+      }
+    });
+    paths
+  }
+
+  #[test]
+  fn walk_to_the_component() {
+    let paths = paths();
+
+    let steps = paths["param"].as_ref().unwrap();
+    let components = steps
+      .iter()
+      .map(|(_, component)| component["type"].as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(components, [
+      Some("GenericArg"),
+      Some("GenericArg"),
+      Some("TupleField"),
+      None
+    ]);
+    assert_eq!(steps[2].1["index"], 1);
+    assert_eq!(steps[3].0, "T");
+
+    let steps = paths["region"].as_ref().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1].0, "&'a u8");
+
+    assert!(paths["outlives"].is_none());
+  }
+
+  #[test]
+  fn captures_are_named_by_place() {
+    let paths = paths();
+    let steps = paths["closure"].as_ref().unwrap();
+    assert_eq!(steps[0].1["type"], "Capture");
+    assert_eq!(steps[0].1["name"], "pair.1");
+    assert_eq!(steps.len(), 2);
+  }
+}
@@ -0,0 +1,231 @@
+//! Expected and found types of failed projection obligations.
+//!
+//! A projection obligation `<I as Iterator>::Item == &str` fails when the
+//! associated type normalizes to something else. The normalized type is
+//! recovered in a probe, and compared structurally with the expected one to
+//! find the first component where they differ.
+
+use argus_ser as ser;
+use rustc_infer::{
+  infer::{BoundRegionConversionTime, InferCtxt},
+  traits::{ObligationCauseCode, PredicateObligation},
+};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_trait_selection::traits::{ObligationCtxt, ScrubbedTraitError};
+use serde::Serialize;
+
+use crate::{
+  tls,
+  types::{ProjectionMismatch, TyDiff},
+};
+
+/// The expected and found types of `obligation`, if it's a projection.
+pub(crate) fn projection_mismatch<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  obligation: &PredicateObligation<'tcx>,
+) -> Option<ProjectionMismatch> {
+  let tcx = infcx.tcx;
+  let clause = obligation.predicate.as_clause()?.as_projection_clause()?;
+
+  // Serialize inside the probe, the types may mention inference variables
+  // created by it.
+  infcx.probe(|_| {
+    let projection = infcx.instantiate_binder_with_fresh_vars(
+      obligation.cause.span,
+      BoundRegionConversionTime::HigherRankedType,
+      clause,
+    );
+    let expected = projection.term.as_type()?;
+    let alias = projection.projection_term.to_term(tcx).as_type()?;
+
+    let ocx = ObligationCtxt::<ScrubbedTraitError>::new(infcx);
+    let found = ocx
+      .deeply_normalize(&obligation.cause, obligation.param_env, alias)
+      .ok()?;
+    let found = infcx.resolve_vars_if_possible(found);
+    let expected = infcx.resolve_vars_if_possible(expected);
+
+    let diff = first_difference(tcx, expected, found, &mut vec![]).map(
+      |(path, expected, found)| TyDiff {
+        path,
+        expected: to_value(infcx, expected),
+        found: to_value(infcx, found),
+      },
+    );
+
+    Some(ProjectionMismatch {
+      expected: to_value(infcx, expected),
+      found: to_value(infcx, found),
+      diff,
+      location: bound_location(tcx, obligation.cause.code()),
+    })
+  })
+}
+
+fn to_value<'tcx>(infcx: &InferCtxt<'tcx>, ty: Ty<'tcx>) -> serde_json::Value {
+  #[derive(Serialize)]
+  struct TyWrapper<'tcx>(#[serde(with = "ser::ty::TyDef")] Ty<'tcx>);
+
+  tls::unsafe_access_interner(|ty_interner| {
+    ser::to_value_expect(infcx, ty_interner, &TyWrapper(ty))
+  })
+}
+
+/// Location of the where-clause that required the projection.
+fn bound_location(
+  tcx: TyCtxt,
+  code: &ObligationCauseCode,
+) -> Option<ser::DefLocation> {
+  match code.peel_derives() {
+    ObligationCauseCode::WhereClause(_, span)
+    | ObligationCauseCode::WhereClauseInExpr(_, span, ..)
+      if !span.is_dummy() =>
+    {
+      ser::DefLocation::from_span(*span, tcx)
+    }
+    _ => None,
+  }
+}
+
+/// The first pair of components where `expected` and `found` differ, with
+/// the path of component indices leading to it.
+///
+/// Regions are ignored, and inference variables match any type.
+fn first_difference<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  expected: Ty<'tcx>,
+  found: Ty<'tcx>,
+  path: &mut Vec<usize>,
+) -> Option<(Vec<usize>, Ty<'tcx>, Ty<'tcx>)> {
+  if expected.is_ty_var()
+    || found.is_ty_var()
+    || tcx.erase_regions(expected) == tcx.erase_regions(found)
+  {
+    return None;
+  }
+
+  let (Some(expected_components), Some(found_components)) =
+    (components(expected), components(found))
+  else {
+    return Some((path.clone(), expected, found));
+  };
+
+  if !same_constructor(expected, found)
+    || expected_components.len() != found_components.len()
+  {
+    return Some((path.clone(), expected, found));
+  }
+
+  for (i, (e, f)) in expected_components
+    .into_iter()
+    .zip(found_components)
+    .enumerate()
+  {
+    path.push(i);
+    if let Some(difference) = first_difference(tcx, e, f, path) {
+      return Some(difference);
+    }
+    path.pop();
+  }
+
+  // Components only differ in their consts, e.g., array lengths.
+  Some((path.clone(), expected, found))
+}
+
+/// The type components of a type constructor, `None` for leaf types.
+fn components(ty: Ty<'_>) -> Option<Vec<Ty<'_>>> {
+  match *ty.kind() {
+    ty::Adt(_, args) => Some(args.types().collect()),
+    ty::Ref(_, ty, _)
+    | ty::RawPtr(ty, _)
+    | ty::Array(ty, _)
+    | ty::Slice(ty) => Some(vec![ty]),
+    ty::Tuple(tys) => Some(tys.to_vec()),
+    ty::FnPtr(sig_tys, _) => {
+      Some(sig_tys.skip_binder().inputs_and_output.to_vec())
+    }
+    _ => None,
+  }
+}
+
+fn same_constructor<'tcx>(a: Ty<'tcx>, b: Ty<'tcx>) -> bool {
+  match (a.kind(), b.kind()) {
+    (ty::Adt(a, _), ty::Adt(b, _)) => a.did() == b.did(),
+    (ty::Ref(_, _, a), ty::Ref(_, _, b))
+    | (ty::RawPtr(_, a), ty::RawPtr(_, b)) => a == b,
+    (ty::Array(..), ty::Array(..))
+    | (ty::Slice(..), ty::Slice(..))
+    | (ty::Tuple(..), ty::Tuple(..)) => true,
+    (ty::FnPtr(_, a), ty::FnPtr(_, b)) => a == b,
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use rustc_hir::def::DefKind;
+
+  use super::*;
+  use crate::test_utils as tu;
+
+  const SIGNATURES: &str = r"
+fn equal(_: Vec<&'static str>, _: Vec<&str>) {}
+fn nested(_: Option<Vec<u8>>, _: Option<Vec<u16>>) {}
+fn constructor(_: Option<u8>, _: Result<u8, ()>) {}
+fn tuple(_: (u8, &str), _: (u8, String)) {}
+fn length(_: [u8; 2], _: [u8; 3]) {}
+fn mutability(_: &u8, _: &mut u8) {}
+fn inputs(_: fn(u8) -> bool, _: fn(u8, u8) -> bool) {}
+";
+
+  #[test]
+  fn first_differences() {
+    let mut differences = vec![];
+    tu::compile_normal(SIGNATURES, |tcx| {
+      for def_id in tcx.hir_body_owners() {
+        if tcx.def_kind(def_id) != DefKind::Fn {
+          continue;
+        }
+        let sig = tcx.instantiate_bound_regions_with_erased(
+          tcx.fn_sig(def_id).instantiate_identity(),
+        );
+        let [expected, found] = sig.inputs() else {
+          unreachable!()
+        };
+        let difference = first_difference(tcx, *expected, *found, &mut vec![])
+          .map(|(path, e, f)| (path, e.to_string(), f.to_string()));
+        differences
+          .push((tcx.item_name(def_id.to_def_id()).to_string(), difference));
+      }
+    });
+
+    let difference = |path: &[usize], expected: &str, found: &str| {
+      Some((path.to_vec(), expected.to_owned(), found.to_owned()))
+    };
+    let expected = [
+      ("equal", None),
+      ("nested", difference(&[0, 0], "u8", "u16")),
+      (
+        "constructor",
+        difference(
+          &[],
+          "std::option::Option<u8>",
+          "std::result::Result<u8, ()>",
+        ),
+      ),
+      ("tuple", difference(&[1], "&str", "std::string::String")),
+      ("length", difference(&[], "[u8; 2]", "[u8; 3]")),
+      ("mutability", difference(&[], "&u8", "&mut u8")),
+      (
+        "inputs",
+        difference(&[], "fn(u8) -> bool", "fn(u8, u8) -> bool"),
+      ),
+    ];
+    for (name, difference) in expected {
+      assert!(
+        differences.contains(&(name.to_owned(), difference.clone())),
+        "{name}: expected {difference:?} in {differences:#?}"
+      );
+    }
+  }
+}
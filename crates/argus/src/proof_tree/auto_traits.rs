@@ -0,0 +1,267 @@
+//! Structural explanations of failed auto traits.
+//!
+//! The solver proves `Ty: Send` by requiring it of each component of `Ty`,
+//! its fields for an ADT or a generic argument for an impl where-clause. A
+//! failure is explained by following the failing auto trait goal at each
+//! level, down to the type that doesn't implement it, or to the where-clause
+//! of a user impl of the auto trait that doesn't hold.
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_span::DUMMY_SP;
+use rustc_trait_selection::{
+  solve::inspect::{InspectCandidate, InspectGoal, ProbeKind},
+  traits::solve::CandidateSource,
+};
+
+/// Limit on the length of a path, the solver bounds the depth of its
+/// proof trees but a path this long isn't an explanation anymore.
+const MAX_DEPTH: usize = 32;
+
+pub(super) struct RawAutoTraitPath<'tcx> {
+  pub steps: Vec<RawAutoTraitStep<'tcx>>,
+  pub cause: RawAutoTraitCause<'tcx>,
+}
+
+pub(super) struct RawAutoTraitStep<'tcx> {
+  pub ty: Ty<'tcx>,
+  pub auto_trait: DefId,
+  pub component: Option<RawComponent>,
+}
+
+pub(super) enum RawComponent {
+  Field { name: String, def_id: DefId },
+  TupleField { index: usize },
+  GenericArg { index: usize },
+  Element,
+  Pointee,
+  Other,
+}
+
+pub(super) enum RawAutoTraitCause<'tcx> {
+  NegativeImpl(DefId),
+  /// A where-clause of a user impl of the auto trait, as declared on the
+  /// impl.
+  WhereClause {
+    impl_def_id: DefId,
+    clause: ty::Clause<'tcx>,
+  },
+  MissingBound,
+  Unimplemented,
+}
+
+/// The auto trait and self type of `goal`, if it's a failed auto trait goal.
+pub(super) fn failed_auto_trait<'tcx>(
+  goal: &InspectGoal<'_, 'tcx>,
+) -> Option<(DefId, Ty<'tcx>)> {
+  if goal.result().is_ok() {
+    return None;
+  }
+
+  let infcx = goal.infcx();
+  let pred = goal.goal().predicate.as_trait_clause()?;
+  if !infcx.tcx.trait_is_auto(pred.def_id())
+    || pred.polarity() != ty::PredicatePolarity::Positive
+  {
+    return None;
+  }
+
+  let self_ty = infcx.resolve_vars_if_possible(pred.self_ty().skip_binder());
+  Some((pred.def_id(), self_ty))
+}
+
+/// Explain why the failed auto trait goal `goal` doesn't hold.
+pub(super) fn explain<'tcx>(
+  goal: &InspectGoal<'_, 'tcx>,
+) -> Option<RawAutoTraitPath<'tcx>> {
+  let mut steps = vec![];
+  let cause = walk(goal, &mut steps)?;
+  Some(RawAutoTraitPath { steps, cause })
+}
+
+fn walk<'tcx>(
+  goal: &InspectGoal<'_, 'tcx>,
+  steps: &mut Vec<RawAutoTraitStep<'tcx>>,
+) -> Option<RawAutoTraitCause<'tcx>> {
+  let (auto_trait, ty) = failed_auto_trait(goal)?;
+  let infcx = goal.infcx();
+  let tcx = infcx.tcx;
+
+  if steps.len() < MAX_DEPTH {
+    for candidate in goal.candidates() {
+      let cause = infcx.probe(|_| {
+        let nested = candidate.instantiate_nested_goals(DUMMY_SP);
+        // Components with aliases are preceded by goals normalizing them,
+        // the auto trait goals are the components in order.
+        let (position, next, next_ty) = nested
+          .iter()
+          .filter(|nested| {
+            nested
+              .goal()
+              .predicate
+              .as_trait_clause()
+              .is_some_and(|t| tcx.trait_is_auto(t.def_id()))
+          })
+          .enumerate()
+          .find_map(|(i, nested)| {
+            failed_auto_trait(nested).map(|(_, ty)| (i, nested, ty))
+          })?;
+
+        let position = is_structural(&candidate).then_some(position);
+        steps.push(RawAutoTraitStep {
+          ty,
+          auto_trait,
+          component: Some(component(tcx, ty, position, next_ty)),
+        });
+        let cause = walk(next, steps);
+        if cause.is_none() {
+          steps.pop();
+        }
+        cause
+      });
+
+      if cause.is_some() {
+        return cause;
+      }
+    }
+  }
+
+  steps.push(RawAutoTraitStep {
+    ty,
+    auto_trait,
+    component: None,
+  });
+  let cause = goal
+    .candidates()
+    .iter()
+    .find_map(failed_where_clause)
+    .unwrap_or_else(|| leaf_cause(tcx, auto_trait, ty));
+  Some(cause)
+}
+
+/// Is `candidate` the builtin impl of an auto trait, which requires the
+/// trait of each component of the type.
+fn is_structural(candidate: &InspectCandidate) -> bool {
+  matches!(candidate.kind(), ProbeKind::TraitCandidate {
+    source: CandidateSource::BuiltinImpl(..),
+    ..
+  })
+}
+
+/// The first where-clause of a user impl candidate that doesn't hold.
+fn failed_where_clause<'tcx>(
+  candidate: &InspectCandidate<'_, 'tcx>,
+) -> Option<RawAutoTraitCause<'tcx>> {
+  let ProbeKind::TraitCandidate {
+    source: CandidateSource::Impl(impl_def_id),
+    ..
+  } = candidate.kind()
+  else {
+    return None;
+  };
+
+  let infcx = candidate.goal().infcx();
+  let tcx = infcx.tcx;
+  let idx = infcx.probe(|_| {
+    let (nested, impl_args) =
+      candidate.instantiate_nested_goals_and_opt_impl_args(DUMMY_SP);
+    let instantiated = tcx
+      .predicates_of(impl_def_id)
+      .instantiate(tcx, impl_args?)
+      .predicates
+      .into_iter()
+      .map(|clause| infcx.resolve_vars_if_possible(clause.as_predicate()))
+      .collect::<Vec<_>>();
+
+    nested
+      .iter()
+      .filter(|nested| nested.result().is_err())
+      .find_map(|nested| {
+        let predicate = infcx.resolve_vars_if_possible(nested.goal().predicate);
+        instantiated.iter().position(|wc| *wc == predicate)
+      })
+  })?;
+
+  let clause = tcx
+    .predicates_of(impl_def_id)
+    .instantiate_identity(tcx)
+    .predicates[idx];
+  Some(RawAutoTraitCause::WhereClause {
+    impl_def_id,
+    clause,
+  })
+}
+
+/// How `child` is reached from `parent` in the proof of an auto trait.
+///
+/// The builtin impl of an auto trait requires it of each component of the
+/// type in order, `position` is the index of `child` among them. Other impls
+/// require it of generic arguments through their where-clauses.
+fn component<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  parent: Ty<'tcx>,
+  position: Option<usize>,
+  child: Ty<'tcx>,
+) -> RawComponent {
+  let child = tcx.erase_regions(child);
+  let is_child = |ty: Ty<'tcx>| tcx.erase_regions(ty) == child;
+  match *parent.kind() {
+    ty::Adt(def, args) => {
+      let field = position.and_then(|position| {
+        let (variant, field) = def
+          .variants()
+          .iter()
+          .flat_map(|variant| variant.fields.iter().map(move |f| (variant, f)))
+          .nth(position)?;
+        let name = if def.is_enum() {
+          format!("{}::{}", variant.name, field.name)
+        } else {
+          field.name.to_string()
+        };
+        Some(RawComponent::Field {
+          name,
+          def_id: field.did,
+        })
+      });
+
+      field
+        .or_else(|| {
+          args
+            .iter()
+            .position(|arg| arg.as_type().is_some_and(is_child))
+            .map(|index| RawComponent::GenericArg { index })
+        })
+        .unwrap_or(RawComponent::Other)
+    }
+    ty::Tuple(..) => position.map_or(RawComponent::Other, |index| {
+      RawComponent::TupleField { index }
+    }),
+    ty::Array(..) | ty::Slice(..) => RawComponent::Element,
+    ty::Ref(..) | ty::RawPtr(..) => RawComponent::Pointee,
+    _ => RawComponent::Other,
+  }
+}
+
+fn leaf_cause<'tcx>(
+  tcx: TyCtxt<'tcx>,
+  auto_trait: DefId,
+  ty: Ty<'tcx>,
+) -> RawAutoTraitCause<'tcx> {
+  if matches!(ty.kind(), ty::Param(..) | ty::Alias(..)) {
+    return RawAutoTraitCause::MissingBound;
+  }
+
+  let mut negative = None;
+  tcx.for_each_relevant_impl(auto_trait, ty, |impl_def_id| {
+    if negative.is_none()
+      && tcx.impl_polarity(impl_def_id) == ty::ImplPolarity::Negative
+    {
+      negative = Some(impl_def_id);
+    }
+  });
+
+  negative.map_or(
+    RawAutoTraitCause::Unimplemented,
+    RawAutoTraitCause::NegativeImpl,
+  )
+}
@@ -1,17 +1,24 @@
 use std::{
   cmp::{Eq, PartialEq},
+  collections::HashSet,
   hash::Hash,
 };
 
 use argus_ext::{
-  infer::InferCtxtExt, ty::VarCounterExt, utils::SpanExt as ArgusSpanExt,
+  infer::InferCtxtExt,
+  ty::{EvaluationResultExt, VarCounterExt},
+  utils::SpanExt as ArgusSpanExt,
 };
 use argus_ser as ser;
 use argus_ser::interner::Interner;
 use index_vec::IndexVec;
 use rustc_hashes::Hash64;
-use rustc_hir::def_id::DefId;
-use rustc_infer::infer::InferCtxt;
+use rustc_hir::{def::DefKind, def_id::DefId, LangItem};
+use rustc_infer::{
+  infer::InferCtxt,
+  traits::{Obligation, ObligationCause},
+};
+use rustc_span::Span;
 use rustc_trait_selection::{
   solve::inspect::{InspectCandidate, InspectGoal},
   traits::{
@@ -22,7 +29,7 @@ use rustc_trait_selection::{
 
 use super::*;
 use crate::{
-  ext::InferCtxtExt as InferCtxtExt_, tls,
+  dyn_compat, ext::InferCtxtExt as InferCtxtExt_, tls,
   types::intermediate::EvaluationResult,
 };
 
@@ -35,7 +42,7 @@ pub struct Interners {
 #[derive(PartialEq, Eq, Hash)]
 enum CanKey {
   Impl(DefId),
-  ParamEnv(usize),
+  ParamEnv(usize, Hash64),
   Str(&'static str),
 }
 
@@ -62,8 +69,6 @@ impl Interners {
     )
   }
 
-  // NOTE: used in `test_utils`.
-  #[allow(dead_code)]
   pub fn goal(&self, g: GoalIdx) -> &GoalData {
     self.goals.get_data(&g).expect("missing goal idx")
   }
@@ -80,13 +85,17 @@ impl Interners {
 
   pub fn mk_goal_node(&mut self, goal: &InspectGoal) -> Node {
     let infcx = goal.infcx();
-    let result_idx = self.intern_result(goal.result());
+    let goal_result = goal.result();
     let goal = goal.goal();
-    let goal_idx = self.intern_goal(infcx, &goal, result_idx);
+    let goal_idx = self.intern_goal(infcx, &goal, goal_result);
     Node::Goal(goal_idx)
   }
 
-  pub fn mk_candidate_node(&mut self, candidate: &InspectCandidate) -> Node {
+  pub fn mk_candidate_node(
+    &mut self,
+    candidate: &InspectCandidate,
+    body_owner: DefId,
+  ) -> Node {
     let can_idx = match candidate.kind() {
       ProbeKind::Root { .. } => self.intern_can_string("root"),
       ProbeKind::NormalizedSelfTyAssembly => {
@@ -105,7 +114,9 @@ impl Interners {
         }
         CandidateSource::AliasBound => self.intern_can_string("alias-bound"),
         // The only two we really care about.
-        CandidateSource::ParamEnv(idx) => self.intern_can_param_env(idx),
+        CandidateSource::ParamEnv(idx) => {
+          self.intern_can_param_env(candidate.goal(), idx, body_owner)
+        }
 
         CandidateSource::Impl(def_id) => {
           self.intern_impl(candidate.goal().infcx(), def_id)
@@ -135,8 +146,9 @@ impl Interners {
     &mut self,
     infcx: &InferCtxt<'tcx>,
     goal: &solve::Goal<'tcx, ty::Predicate<'tcx>>,
-    result_idx: ResultIdx,
+    result: EvaluationResult,
   ) -> GoalIdx {
+    let result_idx = self.intern_result(result);
     let goal = infcx.resolve_vars_if_possible(*goal);
     let hash = infcx.predicate_hash(&goal.predicate);
     let hash = (hash, result_idx);
@@ -147,6 +159,9 @@ impl Interners {
     let necessity = infcx.guess_predicate_necessity(&goal.predicate);
     let num_vars = goal.predicate.count_vars(infcx.tcx);
     let is_main_tv = goal.predicate.is_main_ty_var();
+    // Reporting the failure is costly, it's only done for new goals.
+    let on_unimplemented = on_unimplemented(infcx, &goal, result);
+    let dyn_compatibility = dyn_compatibility(infcx, &goal, result);
     let goal_value = tls::unsafe_access_interner(|ty_interner| {
       ser::to_value_expect(infcx, ty_interner, &ser::GoalPredicateDef(goal))
     });
@@ -157,6 +172,8 @@ impl Interners {
       num_vars,
       is_main_tv,
       result: result_idx,
+      on_unimplemented,
+      dyn_compatibility,
 
       #[cfg(debug_assertions)]
       debug_comparison: format!("{:?}", goal.predicate.kind().skip_binder()),
@@ -171,14 +188,33 @@ impl Interners {
     self.candidates.insert(CanKey::Str(s), s.into())
   }
 
-  fn intern_can_param_env(&mut self, idx: usize) -> CandidateIdx {
-    if let Some(i) = self.candidates.get_idx(&CanKey::ParamEnv(idx)) {
+  fn intern_can_param_env(
+    &mut self,
+    goal: &InspectGoal,
+    idx: usize,
+    body_owner: DefId,
+  ) -> CandidateIdx {
+    let infcx = goal.infcx();
+    let Some(clause) = goal.goal().param_env.caller_bounds().get(idx).copied()
+    else {
+      log::warn!("param-env candidate {idx} out of bounds");
+      return self.intern_can_string("param-env");
+    };
+
+    let key =
+      CanKey::ParamEnv(idx, infcx.predicate_hash(&clause.as_predicate()));
+    if let Some(i) = self.candidates.get_idx(&key) {
       return i;
     }
 
-    self
-      .candidates
-      .insert(CanKey::ParamEnv(idx), CandidateData::ParamEnv(idx))
+    let (origin, span) =
+      param_env_origin(infcx.tcx, body_owner, goal.goal().param_env, clause);
+    let location =
+      span.and_then(|span| ser::DefLocation::from_span(span, infcx.tcx));
+    self.candidates.insert(
+      key,
+      CandidateData::new_param_env(infcx, idx, clause, origin, location),
+    )
   }
 
   pub(super) fn intern_impl(
@@ -211,3 +247,169 @@ impl Interners {
     self.candidates.insert_no_key(CandidateData::from(string))
   }
 }
+
+/// Find how `clause`, a member of `param_env`, got there and the span of
+/// the bound that introduced it.
+fn param_env_origin<'tcx>(
+  tcx: ty::TyCtxt<'tcx>,
+  body_owner: DefId,
+  param_env: ty::ParamEnv<'tcx>,
+  clause: ty::Clause<'tcx>,
+) -> (ParamEnvOrigin, Option<Span>) {
+  let mut item = Some(param_env_owner(tcx, body_owner, param_env));
+
+  // Bounds are declared on the item or any of its parents, e.g., a method
+  // inherits the bounds of its impl block.
+  while let Some(def_id) = item {
+    let explicit = tcx.explicit_predicates_of(def_id).predicates;
+    if let Some(&(_, span)) = explicit.iter().find(|(c, _)| *c == clause) {
+      let origin = if is_default_sized_bound(tcx, def_id, clause, span) {
+        ParamEnvOrigin::Implied
+      } else {
+        ParamEnvOrigin::Declared
+      };
+      return (origin, Some(span));
+    }
+
+    let predicates = tcx.predicates_of(def_id).predicates;
+    if let Some(&(_, span)) = predicates.iter().find(|(c, _)| *c == clause) {
+      return (ParamEnvOrigin::Implied, Some(span));
+    }
+
+    item = tcx.generics_of(def_id).parent;
+  }
+
+  // The param-env is elaborated, any of its bounds may imply the clause.
+  param_env
+    .caller_bounds()
+    .iter()
+    .filter(|&c| c != clause)
+    .filter_map(ty::Clause::as_trait_clause)
+    .find_map(|p| {
+      find_supertrait_bound(tcx, p.map_bound(|p| p.trait_ref), clause)
+    })
+    .map_or((ParamEnvOrigin::Elaborated, None), |span| {
+      (ParamEnvOrigin::Supertrait, Some(span))
+    })
+}
+
+/// The item `param_env` was built from, the body owner or an item it's
+/// nested in. Closures and inline constants are checked with the
+/// param-env of their enclosing item.
+fn param_env_owner<'tcx>(
+  tcx: ty::TyCtxt<'tcx>,
+  body_owner: DefId,
+  param_env: ty::ParamEnv<'tcx>,
+) -> DefId {
+  let mut item = Some(body_owner);
+  while let Some(def_id) = item {
+    if matches!(tcx.def_kind(def_id), DefKind::Mod | DefKind::ForeignMod) {
+      break;
+    }
+    if tcx.param_env(def_id) == param_env {
+      return def_id;
+    }
+    item = tcx.opt_parent(def_id);
+  }
+
+  body_owner
+}
+
+/// Search the supertraits (and other implied bounds) of `trait_ref` for
+/// `clause`, returning the span where the trait declared it.
+fn find_supertrait_bound<'tcx>(
+  tcx: ty::TyCtxt<'tcx>,
+  trait_ref: ty::PolyTraitRef<'tcx>,
+  clause: ty::Clause<'tcx>,
+) -> Option<Span> {
+  let mut visited = HashSet::from([trait_ref.def_id()]);
+  let mut stack = vec![trait_ref];
+
+  while let Some(trait_ref) = stack.pop() {
+    let implied = tcx.explicit_implied_predicates_of(trait_ref.def_id());
+    for &(bound, span) in implied.skip_binder() {
+      let bound = bound.instantiate_supertrait(tcx, trait_ref);
+      if bound == clause {
+        return Some(span);
+      }
+
+      if let Some(p) = bound.as_trait_clause() {
+        if visited.insert(p.def_id()) {
+          stack.push(p.map_bound(|p| p.trait_ref));
+        }
+      }
+    }
+  }
+
+  None
+}
+
+/// The compiler adds `T: Sized` for each type parameter not marked `?Sized`,
+/// these bounds are given the span of the parameter itself.
+fn is_default_sized_bound<'tcx>(
+  tcx: ty::TyCtxt<'tcx>,
+  def_id: DefId,
+  clause: ty::Clause<'tcx>,
+  span: Span,
+) -> bool {
+  let Some(p) = clause.as_trait_clause() else {
+    return false;
+  };
+
+  let ty::Param(param) = p.self_ty().skip_binder().kind() else {
+    return false;
+  };
+
+  tcx.is_lang_item(p.def_id(), LangItem::Sized)
+    && tcx.def_span(tcx.generics_of(def_id).type_param(*param, tcx).def_id)
+      == span
+}
+
+/// Why the trait of a failed `DynCompatible` goal isn't dyn compatible.
+fn dyn_compatibility<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  goal: &solve::Goal<'tcx, ty::Predicate<'tcx>>,
+  result: EvaluationResult,
+) -> Option<DynCompatibility> {
+  if result.is_yes() {
+    return None;
+  }
+  let ty::PredicateKind::DynCompatible(def_id) =
+    goal.predicate.kind().skip_binder()
+  else {
+    return None;
+  };
+  dyn_compat::dyn_compatibility(infcx, def_id)
+}
+
+/// The author-provided diagnostic of a failed trait goal, if its trait
+/// has one. Also decides the goal's `on_unimplemented` heuristic.
+pub(crate) fn on_unimplemented<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  goal: &solve::Goal<'tcx, ty::Predicate<'tcx>>,
+  result: EvaluationResult,
+) -> Option<OnUnimplemented> {
+  use rustc_trait_selection::error_reporting::InferCtxtErrorExt;
+
+  if result.is_yes() {
+    return None;
+  }
+  let goal = infcx.resolve_vars_if_possible(*goal);
+  let trait_pred = goal.predicate.as_trait_clause()?;
+  let obligation = Obligation::new(
+    infcx.tcx,
+    ObligationCause::dummy(),
+    goal.param_env,
+    goal.predicate,
+  );
+  let note =
+    infcx
+      .err_ctxt()
+      .on_unimplemented_note(trait_pred, &obligation, &mut None);
+  (note.message.is_some() || note.label.is_some() || !note.notes.is_empty())
+    .then_some(OnUnimplemented {
+      message: note.message,
+      label: note.label,
+      notes: note.notes,
+    })
+}
@@ -1,5 +1,6 @@
 //! Proof tree types sent to the Argus frontend.
 
+mod auto_traits;
 mod format;
 mod interners;
 pub(super) mod serialize;
@@ -10,7 +11,8 @@ use std::collections::HashMap;
 use argus_ext::ty::PredicateExt;
 use argus_ser::{self as ser, interner::TyIdx};
 use index_vec::IndexVec;
-use rustc_infer::infer::InferCtxt;
+pub(crate) use interners::on_unimplemented;
+use rustc_infer::{infer::InferCtxt, traits::solve::GoalSource};
 use rustc_middle::ty;
 use serde::Serialize;
 use serde_json as json;
@@ -22,7 +24,7 @@ use crate::{
   aadebug, tls,
   types::{
     intermediate::{EvaluationResult, EvaluationResultDef},
-    ObligationNecessity,
+    DynCompatibility, ObligationNecessity, OutlivesExplanation,
   },
 };
 
@@ -59,11 +61,41 @@ pub struct GoalData {
   is_main_tv: bool,
   result: ResultIdx,
 
+  /// Message the trait's author attached with
+  /// `#[diagnostic::on_unimplemented]` or `#[rustc_on_unimplemented]`,
+  /// present on failed goals.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "OnUnimplemented | undefined"))]
+  on_unimplemented: Option<OnUnimplemented>,
+
+  /// Violations of the trait, present on failed `DynCompatible` goals.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DynCompatibility | undefined"))]
+  dyn_compatibility: Option<DynCompatibility>,
+
   #[cfg(debug_assertions)]
   #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
   debug_comparison: String,
 }
 
+/// The `#[diagnostic::on_unimplemented]` (or `#[rustc_on_unimplemented]`)
+/// attribute of a trait, with the goal's arguments substituted.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct OnUnimplemented {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub message: Option<String>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub label: Option<String>,
+
+  pub notes: Vec<String>,
+}
+
 #[derive(Serialize, Clone, Debug)]
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
@@ -73,11 +105,65 @@ pub enum CandidateData {
     hd: json::Value,
     is_user_visible: bool,
   },
-  ParamEnv(usize),
+  ParamEnv {
+    /// Index of the clause in the caller bounds.
+    idx: usize,
+    #[cfg_attr(feature = "testing", ts(type = "GroupedClauses"))]
+    clause: json::Value,
+    origin: ParamEnvOrigin,
+    /// Location of the bound, or the item that declared it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+    location: Option<ser::DefLocation>,
+  },
   // TODO remove variant once everything is structured
   Any(String),
 }
 
+/// How a param-env clause ended up in the caller bounds.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum ParamEnvOrigin {
+  /// Written on the item the param-env was built from, or one of its
+  /// parents.
+  Declared,
+  /// Added by the compiler, e.g., a default `Sized` bound or the
+  /// `Self: Trait` bound within a trait.
+  Implied,
+  /// Implied by a supertrait (or trait alias) of a declared bound.
+  Supertrait,
+  /// Any other elaboration of the declared bounds.
+  Elaborated,
+}
+
+/// Why a candidate required a nested goal.
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum GoalSourceKind {
+  Misc,
+  ImplWhereBound,
+  AliasBoundConstCondition,
+  InstantiateHigherRanked,
+  AliasWellFormed,
+  NormalizeGoal,
+}
+
+/// Data attached to the edge between a candidate and one of its subgoals.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct GoalSourceData {
+  pub kind: GoalSourceKind,
+
+  /// Location of the where-clause that introduced the subgoal.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+  pub where_clause: Option<ser::DefLocation>,
+}
+
 #[derive(Serialize, Clone, Debug)]
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
@@ -109,10 +195,21 @@ pub struct SerializedTree {
   #[cfg_attr(feature = "testing", ts(type = "TyVal[]"))]
   pub tys: IndexVec<TyIdx, json::Value>,
 
-  pub projection_values: HashMap<TyIdx, TyIdx>,
+  /// Normalization of each alias relation, keyed by the `AliasRelate` goal.
+  pub normalizations: HashMap<ProofNodeIdx, NormalizationTrace>,
+
+  /// Source of each goal that is the nested goal of a candidate.
+  pub goal_sources: HashMap<ProofNodeIdx, GoalSourceData>,
 
   pub all_impl_candidates: HashMap<ProofNodeIdx, Implementors>,
 
+  /// Why failed auto trait goals don't hold, keyed by the outermost goal of
+  /// each explanation.
+  pub auto_traits: HashMap<ProofNodeIdx, AutoTraitPath>,
+
+  /// Explanations of the outlives goals.
+  pub outlives: HashMap<ProofNodeIdx, OutlivesExplanation>,
+
   pub topology: TreeTopology,
 
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -121,6 +218,152 @@ pub struct SerializedTree {
   pub analysis: aadebug::AnalysisResults,
 }
 
+/// A path through the structure of a type down to the component that
+/// doesn't implement an auto trait, e.g.,
+/// `MyStruct.inner -> Arc<RefCell<_>>.0 -> RefCell<_>: !Sync`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct AutoTraitPath {
+  /// Starts at the goal's self type and ends at the offending type.
+  pub steps: Vec<AutoTraitStep>,
+  pub cause: AutoTraitCause,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct AutoTraitStep {
+  pub ty: TyIdx,
+
+  /// The auto trait required of `ty`, this changes along the path when an
+  /// impl requires another one, e.g., `&T: Send` requires `T: Sync`.
+  #[cfg_attr(feature = "testing", ts(type = "PathDefNoArgs"))]
+  pub auto_trait: json::Value,
+
+  /// How the type of the next step is reached from `ty`, absent on the
+  /// last step.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "AutoTraitComponent | undefined"))]
+  pub component: Option<AutoTraitComponent>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum AutoTraitComponent {
+  /// A field of a struct or union, or `Variant::field` of an enum.
+  Field {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+    location: Option<ser::DefLocation>,
+  },
+  TupleField {
+    index: usize,
+  },
+  /// A generic argument required by a where-clause of the type's impl.
+  GenericArg {
+    index: usize,
+  },
+  /// The element type of an array or slice.
+  Element,
+  /// The pointee of a reference or raw pointer.
+  Pointee,
+  Other,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum AutoTraitCause {
+  /// The type opts out with a negative impl, e.g., `impl !Send for Rc<T>`.
+  NegativeImpl {
+    candidate: CandidateIdx,
+  },
+  /// A where-clause of a user impl of the auto trait doesn't hold, e.g.,
+  /// `T: Foo` of `unsafe impl<T: Foo> Send for X<T>`.
+  WhereClause {
+    candidate: CandidateIdx,
+    #[cfg_attr(feature = "testing", ts(type = "GroupedClauses"))]
+    clause: json::Value,
+  },
+  /// A type parameter or alias without the auto trait as a bound.
+  MissingBound,
+  Unimplemented,
+}
+
+/// How an alias was normalized, or why it wasn't.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct NormalizationTrace {
+  pub alias: TyIdx,
+
+  /// The other side of the relation, i.e., what the alias should be.
+  pub expected: TyIdx,
+
+  /// What the alias normalized to, this differs from `expected` when
+  /// normalization succeeded but the relation itself failed.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub normalized: Option<TyIdx>,
+
+  pub outcome: NormalizationOutcome,
+
+  /// Projection candidates in the order the solver consulted them.
+  pub steps: Vec<NormalizationStep>,
+}
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum NormalizationOutcome {
+  Normalized,
+  /// No candidate applied, the alias was treated as rigid.
+  Rigid,
+  Ambiguous,
+  Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct NormalizationStep {
+  /// The `NormalizesTo` goal this candidate was consulted for.
+  pub goal: ProofNodeIdx,
+  pub candidate: ProofNodeIdx,
+  pub kind: NormalizationStepKind,
+
+  #[serde(with = "EvaluationResultDef")]
+  #[cfg_attr(feature = "testing", ts(type = "EvaluationResult"))]
+  pub result: EvaluationResult,
+
+  /// What the candidate normalized the alias to.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub normalized: Option<TyIdx>,
+
+  /// Goals the candidate depended on.
+  pub subgoals: Vec<ProofNodeIdx>,
+}
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum NormalizationStepKind {
+  Impl,
+  ParamEnv,
+  AliasBound,
+  Builtin,
+  Rigid,
+  Other,
+}
+
 #[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 #[cfg_attr(feature = "testing", derive(TS))]
@@ -131,6 +374,54 @@ pub struct Implementors {
   pub trait_: json::Value,
   pub impls: Vec<CandidateIdx>,
   pub inductive_impls: Vec<CandidateIdx>,
+  /// How each impl differs from the goal's trait ref, diffs are only
+  /// computed for goals that don't hold.
+  pub diffs: HashMap<CandidateIdx, ImplDiff>,
+}
+
+/// Structural comparison of an impl header against the goal's trait ref.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct ImplDiff {
+  /// Type arguments that don't unify on their own. This can be empty for a
+  /// unification failure when arguments only conflict with each other,
+  /// e.g., the goal `u32: From<u64>` against `impl<T> From<T> for T`.
+  pub mismatches: Vec<ArgMismatch>,
+
+  /// Why the impl doesn't apply, absent when it may.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "ImplFailure | undefined"))]
+  pub failure: Option<ImplFailure>,
+
+  /// Where-clauses of the impl that don't hold, as declared on the impl.
+  /// These are only checked when the header unifies.
+  #[cfg_attr(feature = "testing", ts(type = "GroupedClauses[]"))]
+  pub where_clauses: Vec<json::Value>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct ArgMismatch {
+  /// Index into the trait ref's generic arguments, `0` is the self type.
+  pub position: usize,
+  /// The argument in the goal.
+  pub expected: TyIdx,
+  /// The argument as written in the impl header.
+  pub found: TyIdx,
+}
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum ImplFailure {
+  /// The impl header doesn't unify with the goal.
+  Unification,
+  /// The header unifies but a where-clause of the impl doesn't hold.
+  WhereClause,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -156,6 +447,39 @@ impl CandidateData {
       is_user_visible,
     }
   }
+
+  fn new_param_env<'tcx>(
+    infcx: &InferCtxt<'tcx>,
+    idx: usize,
+    clause: ty::Clause<'tcx>,
+    origin: ParamEnvOrigin,
+    location: Option<ser::DefLocation>,
+  ) -> Self {
+    let grouped = ser::GroupedClauses::from_clause(infcx.tcx, clause);
+    let clause = tls::unsafe_access_interner(|ty_interner| {
+      ser::to_value_expect(infcx, ty_interner, &grouped)
+    });
+
+    Self::ParamEnv {
+      idx,
+      clause,
+      origin,
+      location,
+    }
+  }
+}
+
+impl From<GoalSource> for GoalSourceKind {
+  fn from(source: GoalSource) -> Self {
+    match source {
+      GoalSource::Misc => Self::Misc,
+      GoalSource::ImplWhereBound => Self::ImplWhereBound,
+      GoalSource::AliasBoundConstCondition => Self::AliasBoundConstCondition,
+      GoalSource::InstantiateHigherRanked => Self::InstantiateHigherRanked,
+      GoalSource::AliasWellFormed => Self::AliasWellFormed,
+      GoalSource::NormalizeGoal(..) => Self::NormalizeGoal,
+    }
+  }
 }
 
 impl From<&'static str> for CandidateData {
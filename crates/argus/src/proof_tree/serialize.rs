@@ -4,32 +4,37 @@ use argus_ext::{
   ty::{EvaluationResultExt, ImplCandidateExt, PredicateExt, TyExt},
 };
 use index_vec::IndexVec;
-use rustc_ast_ir::{try_visit, visit::VisitorResult};
 use rustc_hir::def_id::DefId;
-use rustc_infer::infer::InferCtxt;
+use rustc_infer::{infer::InferCtxt, traits::ObligationCause};
 use rustc_middle::ty;
 use rustc_span::Span;
 use rustc_trait_selection::{
   solve::inspect::{
-    InspectCandidate, InspectGoal, ProofTreeInferCtxtExt, ProofTreeVisitor,
+    InspectCandidate, InspectGoal, ProbeKind, ProofTreeInferCtxtExt,
+    ProofTreeVisitor,
   },
-  traits::solve,
+  traits::{solve, solve::CandidateSource, Obligation, ObligationCtxt},
 };
 
-use super::{interners::Interners, *};
-use crate::aadebug;
+use super::{
+  auto_traits::{self, RawAutoTraitCause, RawComponent},
+  interners::Interners,
+  *,
+};
+use crate::{aadebug, outlives, types::OutlivesExplanation};
 
 pub fn try_serialize<'tcx>(
   goal: solve::Goal<'tcx, ty::Predicate<'tcx>>,
   result: EvaluationResult,
   span: Span,
   infcx: &InferCtxt<'tcx>,
-  _def_id: DefId,
+  def_id: DefId,
 ) -> Result<SerializedTree> {
   super::format::dump_proof_tree(goal, span, infcx);
 
   infcx.probe(|_| {
-    let mut visitor = SerializedTreeVisitor::new(result.is_maybe());
+    let mut visitor =
+      SerializedTreeVisitor::new(def_id, span, result.is_maybe());
     infcx.visit_proof_tree(goal, &mut visitor);
     visitor.into_tree()
   })
@@ -41,88 +46,232 @@ pub struct SerializedTreeVisitor<'tcx> {
   pub nodes: IndexVec<ProofNodeIdx, Node>,
   pub topology: TreeTopology,
   pub cycle: Option<ProofCycle>,
-  pub projection_values: HashMap<TyIdx, TyIdx>,
+  pub normalizations: HashMap<ProofNodeIdx, NormalizationTrace>,
   pub all_impl_candidates: HashMap<ProofNodeIdx, Implementors>,
-
+  pub goal_sources: HashMap<ProofNodeIdx, GoalSourceData>,
+  pub auto_traits: HashMap<ProofNodeIdx, AutoTraitPath>,
+  pub outlives: HashMap<ProofNodeIdx, OutlivesExplanation>,
+
+  /// Owner of the body whose obligation is being serialized.
+  body_owner: DefId,
+  /// Span of the obligation being serialized.
+  obligation_span: Span,
+  /// Fully visited goals without inference variables, identical goals
+  /// encountered later reference these instead of being serialized again.
+  visited_goals: HashMap<(GoalIdx, ty::ParamEnv<'tcx>), ProofNodeIdx>,
   deferred_leafs: Vec<(ProofNodeIdx, EvaluationResult)>,
+  /// Number of failed auto trait goals enclosing the current goal, only
+  /// the outermost is explained.
+  auto_trait_depth: usize,
+  /// Predicates of the goals enclosing the current goal.
+  enclosing_goals: Vec<ty::Predicate<'tcx>>,
+  /// Impl and span of the where-clause that introduced the next goal.
+  required_by: Option<(DefId, Span)>,
   interners: Interners,
   aadebug: aadebug::Storage<'tcx>,
 }
 
-impl SerializedTreeVisitor<'_> {
-  pub fn new(maybe_ambiguous: bool) -> Self {
+impl<'tcx> SerializedTreeVisitor<'tcx> {
+  pub fn new(
+    body_owner: DefId,
+    obligation_span: Span,
+    maybe_ambiguous: bool,
+  ) -> Self {
     SerializedTreeVisitor {
       root: None,
       previous: None,
       nodes: IndexVec::default(),
       topology: TreeTopology::new(),
       cycle: None,
-      projection_values: HashMap::default(),
+      normalizations: HashMap::default(),
       all_impl_candidates: HashMap::default(),
+      goal_sources: HashMap::default(),
+      auto_traits: HashMap::default(),
+      outlives: HashMap::default(),
 
+      body_owner,
+      obligation_span,
+      visited_goals: HashMap::default(),
       deferred_leafs: Vec::default(),
+      auto_trait_depth: 0,
+      enclosing_goals: Vec::default(),
+      required_by: None,
       interners: Interners::default(),
       aadebug: aadebug::Storage::new(maybe_ambiguous),
     }
   }
 
-  fn check_goal_projection(&mut self, goal: &InspectGoal) {
-    // We only care about successful alias relations
-    if !goal.result().is_yes() {
+  /// Start a normalization trace if `goal` relates an alias to another type.
+  fn start_normalization(
+    &mut self,
+    here_idx: ProofNodeIdx,
+    goal: &InspectGoal<'_, 'tcx>,
+  ) {
+    let ty::PredicateKind::AliasRelate(t1, t2, _) =
+      goal.goal().predicate.kind().skip_binder()
+    else {
+      return;
+    };
+
+    let infcx = goal.infcx();
+    let (Some(mut alias), Some(mut expected)) = (
+      infcx.resolve_vars_if_possible(t1).as_type(),
+      infcx.resolve_vars_if_possible(t2).as_type(),
+    ) else {
       return;
+    };
+
+    if !alias.is_alias() {
+      // Keep the alias on the LHS, when both sides are aliases
+      // the RHS is what the alias should be.
+      std::mem::swap(&mut alias, &mut expected);
     }
 
-    let ty::PredicateKind::AliasRelate(
-      t1,
-      t2,
-      ty::AliasRelationDirection::Equate,
-    ) = goal.goal().predicate.kind().skip_binder()
-    else {
+    if !alias.is_alias() {
       return;
+    }
+
+    let (alias, expected) = crate::tls::unsafe_access_interner(|interner| {
+      (
+        ser::intern_ty(infcx, interner, alias),
+        ser::intern_ty(infcx, interner, expected),
+      )
+    });
+    self.normalizations.insert(here_idx, NormalizationTrace {
+      alias,
+      expected,
+      normalized: None,
+      outcome: NormalizationOutcome::Ambiguous,
+      steps: vec![],
+    });
+  }
+
+  /// The trace a `NormalizesTo` goal contributes to, that of the
+  /// `AliasRelate` goal whose candidate required it.
+  ///
+  /// NOTE: this must be called before `self.previous` is updated.
+  fn normalization_owner(
+    &self,
+    goal: &InspectGoal<'_, 'tcx>,
+  ) -> Option<ProofNodeIdx> {
+    if !matches!(
+      goal.goal().predicate.kind().skip_binder(),
+      ty::PredicateKind::NormalizesTo(..)
+    ) {
+      return None;
+    }
+
+    let owner = self.previous.and_then(|c| self.topology.parent(c))?;
+    self.normalizations.contains_key(&owner).then_some(owner)
+  }
+
+  /// The type a `NormalizesTo` candidate constrained the goal's term to.
+  ///
+  /// NOTE: the term is only constrained within the probe that instantiated
+  /// the candidate's nested goals.
+  fn normalized_by(candidate: &InspectCandidate<'_, 'tcx>) -> Option<TyIdx> {
+    let infcx = candidate.goal().infcx();
+    let ty::PredicateKind::NormalizesTo(normalizes_to) =
+      candidate.goal().goal().predicate.kind().skip_binder()
+    else {
+      return None;
     };
 
-    if let (Some(mut t1), Some(mut t2)) = (t1.as_type(), t2.as_type()) {
-      // Disallow projections involving two aliases
-      if !(t1.is_alias() && t2.is_alias()) && t1 != t2 {
-        if t2.is_alias() {
-          // We want the map to go from alias -> concrete, swap the
-          // types so that the alias is on the LHS. This doesn't change
-          // the semantics because we only save `Equate` relations.
-          std::mem::swap(&mut t1, &mut t2);
-        }
+    if !candidate.result().is_yes() {
+      return None;
+    }
 
-        if let Some((t1, t2)) = crate::tls::unsafe_access_interner(|interner| {
-          let idx1: TyIdx = interner.borrow().get_idx(&t1)?;
-          let idx2: TyIdx = interner.borrow().get_idx(&t2)?;
-          Some((idx1, idx2))
-        }) {
-          if t1 != t2 && !self.projection_values.contains_key(&t1) {
-            let not_empty = self.projection_values.insert(t1, t2);
-            debug_assert!(not_empty.is_none());
-          }
-        }
-      }
+    let ty = infcx
+      .resolve_vars_if_possible(normalizes_to.term)
+      .as_type()
+      .filter(|ty| !ty.is_ty_var())?;
+    Some(crate::tls::unsafe_access_interner(|interner| {
+      ser::intern_ty(infcx, interner, ty)
+    }))
+  }
+
+  fn push_normalization_step(
+    &mut self,
+    owner: ProofNodeIdx,
+    goal: ProofNodeIdx,
+    candidate_idx: ProofNodeIdx,
+    candidate: &InspectCandidate<'_, 'tcx>,
+    normalized: Option<TyIdx>,
+  ) {
+    let subgoals = self
+      .topology
+      .children(candidate_idx)
+      .filter(|&n| matches!(self.nodes[n], Node::Goal(..)))
+      .collect();
+    let result = candidate.result();
+    let step = NormalizationStep {
+      goal,
+      candidate: candidate_idx,
+      kind: normalization_step_kind(candidate),
+      result,
+      normalized,
+      subgoals,
+    };
+
+    if let Some(trace) = self.normalizations.get_mut(&owner) {
+      trace.normalized = trace.normalized.or(normalized);
+      trace.steps.push(step);
     }
   }
 
+  fn finish_normalization(
+    &mut self,
+    here_idx: ProofNodeIdx,
+    result: EvaluationResult,
+  ) {
+    let Some(trace) = self.normalizations.get_mut(&here_idx) else {
+      return;
+    };
+
+    let is_rigid = trace
+      .steps
+      .iter()
+      .any(|s| s.kind == NormalizationStepKind::Rigid && s.result.is_yes());
+    trace.outcome = if result.is_maybe() {
+      NormalizationOutcome::Ambiguous
+    } else if result.is_no() {
+      NormalizationOutcome::Failed
+    } else if trace.normalized.is_some() && !is_rigid {
+      NormalizationOutcome::Normalized
+    } else {
+      NormalizationOutcome::Rigid
+    };
+  }
+
   pub fn into_tree(self) -> Result<SerializedTree> {
     let SerializedTreeVisitor {
       root: Some(root),
       mut nodes,
       mut topology,
       cycle,
-      projection_values,
+      normalizations,
       mut interners,
       aadebug,
       deferred_leafs,
       all_impl_candidates,
+      goal_sources,
+      auto_traits,
+      outlives,
+      body_owner,
+      obligation_span,
       ..
     } = self
     else {
       bail!("missing root node!");
     };
 
-    let analysis = aadebug.into_results(root, &topology);
+    let analysis = aadebug.into_results(
+      root,
+      &topology,
+      body_owner,
+      obligation_span,
+      |infcx, def_id| interners.intern_impl(infcx, def_id),
+    );
 
     // Handle the deferred leafs (an inconvenience we'll deal with later)
     for (parent, res) in deferred_leafs {
@@ -141,8 +290,11 @@ impl SerializedTreeVisitor<'_> {
       candidates,
       results,
       tys,
-      projection_values,
+      normalizations,
+      goal_sources,
       all_impl_candidates,
+      auto_traits,
+      outlives,
       topology,
       cycle,
       analysis,
@@ -169,9 +321,151 @@ impl SerializedTreeVisitor<'_> {
       self.cycle = Some(to_root.into());
     }
   }
-}
 
-impl<'tcx> SerializedTreeVisitor<'tcx> {
+  /// Key under which a goal's subtree can be shared, only goals
+  /// without inference variables are guaranteed to have the same subtree.
+  fn shared_goal_key(
+    &self,
+    here_node: Node,
+    goal: &InspectGoal<'_, 'tcx>,
+  ) -> Option<(GoalIdx, ty::ParamEnv<'tcx>)> {
+    let Node::Goal(goal_idx) = here_node else {
+      return None;
+    };
+
+    (self.interners.goal(goal_idx).num_vars == 0)
+      .then_some((goal_idx, goal.goal().param_env))
+  }
+
+  /// Visit the nested goals of `candidate`, returning what it normalized
+  /// the goal's term to if it's a `NormalizesTo` candidate.
+  fn visit_nested_roots(
+    &mut self,
+    candidate: &InspectCandidate<'_, 'tcx>,
+  ) -> Option<TyIdx> {
+    let infcx = candidate.goal().infcx();
+    infcx.probe(|_| {
+      let (mut all_sub_goals, impl_args) =
+        candidate.instantiate_nested_goals_and_opt_impl_args(self.span());
+      let normalized = Self::normalized_by(candidate);
+      let where_clauses = impl_where_clauses(infcx, candidate, impl_args);
+      let impl_def_id = match candidate.kind() {
+        ProbeKind::TraitCandidate {
+          source: CandidateSource::Impl(impl_def_id),
+          ..
+        } => Some(impl_def_id),
+        _ => None,
+      };
+
+      // Put all successful subgoals at the front of the list.
+      let err_start_idx =
+        itertools::partition(&mut all_sub_goals, |g| g.result().is_yes());
+      let (successful_subgoals, failed_subgoals) =
+        all_sub_goals.split_at_mut(err_start_idx);
+
+      let cap = argus_ext::ty::retain_error_sources(
+        failed_subgoals,
+        InspectGoal::result,
+        |g| g.goal().predicate,
+        |g| g.infcx().tcx,
+      );
+
+      for goal in failed_subgoals[.. cap]
+        .iter()
+        .chain(successful_subgoals.iter())
+      {
+        // The first node pushed while visiting is the goal itself.
+        let goal_idx = self.nodes.next_idx();
+        let where_clause = where_clause_span(infcx, goal, &where_clauses);
+        self.required_by = impl_def_id.zip(where_clause);
+        self.visit_goal(goal);
+
+        let source = GoalSourceData {
+          kind: goal.source().into(),
+          where_clause: where_clause
+            .and_then(|span| ser::DefLocation::from_span(span, infcx.tcx)),
+        };
+        self.goal_sources.insert(goal_idx, source);
+      }
+
+      normalized
+    })
+  }
+
+  /// Explain the goal if it's the outermost failed auto trait goal.
+  fn explain_auto_trait(
+    &mut self,
+    idx: ProofNodeIdx,
+    goal: &InspectGoal<'_, 'tcx>,
+  ) {
+    if self.auto_trait_depth > 1 {
+      return;
+    }
+    let Some(path) = auto_traits::explain(goal) else {
+      return;
+    };
+
+    let infcx = goal.infcx();
+    let steps = path
+      .steps
+      .into_iter()
+      .map(|step| {
+        let (ty, auto_trait) = tls::unsafe_access_interner(|interner| {
+          (
+            ser::intern_ty(infcx, interner, step.ty),
+            ser::to_value_expect(
+              infcx,
+              interner,
+              &ser::PathDefNoArgs(step.auto_trait),
+            ),
+          )
+        });
+        let component = step.component.map(|component| match component {
+          RawComponent::Field { name, def_id } => AutoTraitComponent::Field {
+            name,
+            location: ser::DefLocation::from_def_id_tcx(def_id, infcx.tcx),
+          },
+          RawComponent::TupleField { index } => {
+            AutoTraitComponent::TupleField { index }
+          }
+          RawComponent::GenericArg { index } => {
+            AutoTraitComponent::GenericArg { index }
+          }
+          RawComponent::Element => AutoTraitComponent::Element,
+          RawComponent::Pointee => AutoTraitComponent::Pointee,
+          RawComponent::Other => AutoTraitComponent::Other,
+        });
+        AutoTraitStep {
+          ty,
+          auto_trait,
+          component,
+        }
+      })
+      .collect();
+
+    let cause = match path.cause {
+      RawAutoTraitCause::NegativeImpl(def_id) => AutoTraitCause::NegativeImpl {
+        candidate: self.interners.intern_impl(infcx, def_id),
+      },
+      RawAutoTraitCause::WhereClause {
+        impl_def_id,
+        clause,
+      } => {
+        let grouped = ser::GroupedClauses::from_clause(infcx.tcx, clause);
+        AutoTraitCause::WhereClause {
+          candidate: self.interners.intern_impl(infcx, impl_def_id),
+          clause: tls::unsafe_access_interner(|interner| {
+            ser::to_value_expect(infcx, interner, &grouped)
+          }),
+        }
+      }
+      RawAutoTraitCause::MissingBound => AutoTraitCause::MissingBound,
+      RawAutoTraitCause::Unimplemented => AutoTraitCause::Unimplemented,
+    };
+
+    self.auto_traits.insert(idx, AutoTraitPath { steps, cause });
+  }
+
   fn record_all_impls(
     &mut self,
     idx: ProofNodeIdx,
@@ -212,8 +506,26 @@ impl<'tcx> SerializedTreeVisitor<'tcx> {
       sort_by_count!(types, impl_candidates);
       sort_by_count!(lifetimes, impl_candidates);
 
+      // Diffing probes every impl, which is only worth it to explain why
+      // none of them applied.
+      let param_env = goal.goal().param_env;
+      let goal_trait_ref = tcx.instantiate_bound_regions_with_erased(tp);
+      let needs_diffs = !goal.result().is_yes();
+      let mut diffs = HashMap::default();
+
       for can in impl_candidates {
         let can_idx = self.interners.intern_impl(infcx, can.impl_def_id);
+        if needs_diffs {
+          diffs.insert(
+            can_idx,
+            impl_diff(
+              infcx,
+              param_env,
+              goal_trait_ref.trait_ref,
+              can.impl_def_id,
+            ),
+          );
+        }
         if can.is_inductive(tcx) {
           inductive_impls.push(can_idx);
         } else {
@@ -229,6 +541,7 @@ impl<'tcx> SerializedTreeVisitor<'tcx> {
         trait_,
         impls,
         inductive_impls,
+        diffs,
       });
     }
   }
@@ -246,16 +559,61 @@ impl<'tcx> ProofTreeVisitor<'tcx> for SerializedTreeVisitor<'tcx> {
 
     let here_node = self.interners.mk_goal_node(goal);
     let here_idx = self.nodes.push(here_node);
+    let shared_key = self.shared_goal_key(here_node, goal);
+    let required_by = self.required_by.take();
+
+    // Push node into the analysis tree.
+    self.aadebug.push_goal(here_idx, goal).unwrap();
+
+    // An identical goal was already serialized, reference its subtree.
+    if let Some(original) =
+      shared_key.and_then(|key| self.visited_goals.get(&key).copied())
+    {
+      log::trace!("sharing subtree of {original:?} with {here_idx:?}");
+      if let Some(prev) = self.previous {
+        self.topology.add(prev, here_idx);
+      }
+      self.topology.share(here_idx, original);
+
+      // Only the subtree is shared, the goal's own data is copied.
+      if let Some(implementors) = self.all_impl_candidates.get(&original) {
+        self
+          .all_impl_candidates
+          .insert(here_idx, implementors.clone());
+      }
+      if let Some(trace) = self.normalizations.get(&original) {
+        self.normalizations.insert(here_idx, trace.clone());
+      }
+      if let Some(path) = self.auto_traits.get(&original) {
+        self.auto_traits.insert(here_idx, path.clone());
+      }
+      self.deferred_leafs.push((here_idx, goal.result()));
+      return;
+    }
+
+    if let Some(explanation) = outlives::explain(
+      goal.infcx(),
+      goal.goal().predicate,
+      self.body_owner.as_local(),
+      self.enclosing_goals.last().copied(),
+      required_by,
+    ) {
+      self.outlives.insert(here_idx, explanation);
+    }
 
     // Record all the possible candidate impls for this goal.
     self.record_all_impls(here_idx, goal);
 
-    // Push node into the analysis tree.
-    self.aadebug.push_goal(here_idx, goal).unwrap();
+    let is_failed_auto_trait = auto_traits::failed_auto_trait(goal).is_some();
+    if is_failed_auto_trait {
+      self.auto_trait_depth += 1;
+      self.explain_auto_trait(here_idx, goal);
+    }
 
-    // After interning the goal we can check whether or not
-    // it's an successful alias relate predicate for two types.
-    self.check_goal_projection(goal);
+    // Trace the normalization of alias relations, the nested `NormalizesTo`
+    // goals record their candidates in the trace of the relation.
+    self.start_normalization(here_idx, goal);
+    let normalization_owner = self.normalization_owner(goal);
 
     if self.root.is_none() {
       self.root = Some(here_idx);
@@ -273,8 +631,10 @@ impl<'tcx> ProofTreeVisitor<'tcx> for SerializedTreeVisitor<'tcx> {
       }
     };
 
+    self.enclosing_goals.push(goal.goal().predicate);
     for c in goal.candidates() {
-      let here_candidate = self.interners.mk_candidate_node(&c);
+      let here_candidate =
+        self.interners.mk_candidate_node(&c, self.body_owner);
       let candidate_idx = self.nodes.push(here_candidate);
       self
         .aadebug
@@ -284,53 +644,203 @@ impl<'tcx> ProofTreeVisitor<'tcx> for SerializedTreeVisitor<'tcx> {
       self.topology.add(here_idx, candidate_idx);
       self.previous = Some(candidate_idx);
 
-      c.visit_nested_roots(self);
+      let normalized = self.visit_nested_roots(&c);
+
+      if let Some(owner) = normalization_owner {
+        self.push_normalization_step(
+          owner,
+          here_idx,
+          candidate_idx,
+          &c,
+          normalized,
+        );
+      }
 
       // FIXME: is this necessary now that we store all nodes?
       add_result_if_empty(self, candidate_idx);
     }
 
+    self.enclosing_goals.pop();
+
     add_result_if_empty(self, here_idx);
     self.previous = here_parent;
+    if is_failed_auto_trait {
+      self.auto_trait_depth -= 1;
+    }
+    self.finish_normalization(here_idx, goal.result());
+
+    // Only share the subtree once it's complete, otherwise a recursive
+    // occurrence of the goal would reference its own ancestor.
+    if let Some(key) = shared_key {
+      self.visited_goals.entry(key).or_insert(here_idx);
+    }
   }
 }
 
-// TODO: after we make the `visit_with` method public this can be a generic trait.
-trait InspectCandidateExt<'tcx> {
-  fn visit_nested_roots<V: ProofTreeVisitor<'tcx>>(
-    &self,
-    visitor: &mut V,
-  ) -> V::Result;
+fn normalization_step_kind(
+  candidate: &InspectCandidate,
+) -> NormalizationStepKind {
+  match candidate.kind() {
+    ProbeKind::TraitCandidate { source, .. } => match source {
+      CandidateSource::Impl(..) => NormalizationStepKind::Impl,
+      CandidateSource::ParamEnv(..) => NormalizationStepKind::ParamEnv,
+      CandidateSource::AliasBound => NormalizationStepKind::AliasBound,
+      CandidateSource::BuiltinImpl(..) => NormalizationStepKind::Builtin,
+      CandidateSource::CoherenceUnknowable => NormalizationStepKind::Other,
+    },
+    ProbeKind::RigidAlias { .. } => NormalizationStepKind::Rigid,
+    _ => NormalizationStepKind::Other,
+  }
 }
 
-impl<'tcx> InspectCandidateExt<'tcx> for InspectCandidate<'_, 'tcx> {
-  fn visit_nested_roots<V: ProofTreeVisitor<'tcx>>(
-    &self,
-    visitor: &mut V,
-  ) -> V::Result {
-    self.goal().infcx().probe(|_| {
-      let mut all_sub_goals = self.instantiate_nested_goals(visitor.span());
-      // Put all successful subgoals at the front of the list.
-      let err_start_idx =
-        itertools::partition(&mut all_sub_goals, |g| g.result().is_yes());
-      let (successful_subgoals, failed_subgoals) =
-        all_sub_goals.split_at_mut(err_start_idx);
+/// The instantiated where-clauses of an impl candidate, paired with their spans.
+fn impl_where_clauses<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  candidate: &InspectCandidate<'_, 'tcx>,
+  impl_args: Option<ty::GenericArgsRef<'tcx>>,
+) -> Vec<(ty::Predicate<'tcx>, Span)> {
+  let ProbeKind::TraitCandidate {
+    source: CandidateSource::Impl(impl_def_id),
+    ..
+  } = candidate.kind()
+  else {
+    return vec![];
+  };
+
+  let Some(impl_args) = impl_args else {
+    return vec![];
+  };
+
+  let tcx = infcx.tcx;
+  let instantiated = tcx.predicates_of(impl_def_id).instantiate(tcx, impl_args);
+  instantiated
+    .predicates
+    .into_iter()
+    .map(|clause| infcx.resolve_vars_if_possible(clause.as_predicate()))
+    .zip(instantiated.spans)
+    .collect()
+}
 
-      let cap = argus_ext::ty::retain_error_sources(
-        failed_subgoals,
-        InspectGoal::result,
-        |g| g.goal().predicate,
-        |g| g.infcx().tcx,
-      );
+/// Find the span of the where-clause that introduced `goal`.
+///
+/// Where-clauses are matched exactly. If the solver changed the goal (e.g.,
+/// by normalizing it) the where-clause isn't guessed, several bounds can
+/// mention the same item, as in `T: From<A> + From<B>`.
+fn where_clause_span<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  goal: &InspectGoal<'_, 'tcx>,
+  where_clauses: &[(ty::Predicate<'tcx>, Span)],
+) -> Option<Span> {
+  if !matches!(goal.source(), solve::GoalSource::ImplWhereBound) {
+    return None;
+  }
 
-      for goal in failed_subgoals[.. cap]
-        .iter()
-        .chain(successful_subgoals.iter())
-      {
-        try_visit!(visitor.visit_goal(goal));
+  let predicate = infcx.resolve_vars_if_possible(goal.goal().predicate);
+  where_clauses
+    .iter()
+    .find(|(wc, _)| *wc == predicate)
+    .map(|(_, span)| *span)
+}
+
+/// Compare the header and where-clauses of an impl against a goal.
+///
+/// Each type argument is unified on its own to find the positions that
+/// mismatch. Regions are ignored, they never rule out an impl.
+fn impl_diff<'tcx>(
+  infcx: &InferCtxt<'tcx>,
+  param_env: ty::ParamEnv<'tcx>,
+  goal: ty::TraitRef<'tcx>,
+  impl_def_id: DefId,
+) -> ImplDiff {
+  let tcx = infcx.tcx;
+  let goal = infcx.resolve_vars_if_possible(goal);
+  let goal = ty::fold::fold_regions(tcx, goal, |_, _| tcx.lifetimes.re_static);
+  let declared = tcx
+    .impl_trait_ref(impl_def_id)
+    .expect("impl candidate without a trait ref");
+
+  // Whether the goal's arguments at the `related` positions unify with a
+  // fresh instantiation of the impl, and the impl's `where_clause` holds.
+  let holds = |related: &[usize], where_clause: Option<usize>| {
+    infcx.probe(|_| {
+      let args = infcx.fresh_args_for_item(rustc_span::DUMMY_SP, impl_def_id);
+      let impl_trait_ref =
+        ty::fold::fold_regions(tcx, declared.instantiate(tcx, args), |_, _| {
+          tcx.lifetimes.re_static
+        });
+
+      let ocx = ObligationCtxt::new(infcx);
+      let cause = ObligationCause::dummy();
+      for &i in related {
+        let (Some(expected), Some(found)) = (
+          goal.args.get(i).and_then(|a| a.as_type()),
+          impl_trait_ref.args.get(i).and_then(|a| a.as_type()),
+        ) else {
+          continue;
+        };
+        if ocx.eq(&cause, param_env, expected, found).is_err() {
+          return false;
+        }
       }
 
-      V::Result::output()
+      if let Some(idx) = where_clause {
+        let predicate = tcx
+          .predicates_of(impl_def_id)
+          .instantiate(tcx, args)
+          .predicates[idx];
+        let predicate = ty::fold::fold_regions(tcx, predicate, |_, _| {
+          tcx.lifetimes.re_static
+        });
+        ocx.register_obligation(Obligation::new(
+          tcx, cause, param_env, predicate,
+        ));
+      }
+
+      // Ambiguity doesn't rule out the impl, only errors do.
+      ocx.select_where_possible().is_empty()
+    })
+  };
+
+  let positions = (0 .. goal.args.len()).collect::<Vec<_>>();
+  let declared = declared.instantiate_identity();
+  let mismatches = positions
+    .iter()
+    .filter(|&&i| !holds(&[i], None))
+    .filter_map(|&i| {
+      let expected = goal.args[i].as_type()?;
+      let found = declared.args.get(i)?.as_type()?;
+      Some(tls::unsafe_access_interner(|interner| ArgMismatch {
+        position: i,
+        expected: ser::intern_ty(infcx, interner, expected),
+        found: ser::intern_ty(infcx, interner, found),
+      }))
     })
+    .collect::<Vec<_>>();
+
+  if !mismatches.is_empty() || !holds(&positions, None) {
+    return ImplDiff {
+      mismatches,
+      failure: Some(ImplFailure::Unification),
+      where_clauses: vec![],
+    };
+  }
+
+  let declared_clauses =
+    tcx.predicates_of(impl_def_id).instantiate_identity(tcx);
+  let where_clauses = (0 .. declared_clauses.predicates.len())
+    .filter(|&idx| !holds(&positions, Some(idx)))
+    .map(|idx| {
+      let grouped =
+        ser::GroupedClauses::from_clause(tcx, declared_clauses.predicates[idx]);
+      tls::unsafe_access_interner(|interner| {
+        ser::to_value_expect(infcx, interner, &grouped)
+      })
+    })
+    .collect::<Vec<_>>();
+
+  ImplDiff {
+    mismatches,
+    failure: (!where_clauses.is_empty()).then_some(ImplFailure::WhereClause),
+    where_clauses,
   }
 }
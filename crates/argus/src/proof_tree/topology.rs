@@ -1,11 +1,6 @@
 //! Topology structures, mainly used by the `ProofTree`.
 
-use std::{
-  collections::{HashMap, HashSet},
-  fmt::Debug,
-  hash::Hash,
-  marker::PhantomData,
-};
+use std::{collections::HashMap, fmt::Debug, hash::Hash, marker::PhantomData};
 
 use serde::Serialize;
 #[cfg(feature = "testing")]
@@ -26,14 +21,23 @@ pub trait Idx: Copy + PartialEq + Eq + Hash + Debug + Serialize {}
 impl<T> Idx for T where T: Copy + PartialEq + Eq + Hash + Debug + Serialize {}
 
 /// Parent child relationships between structures.
+///
+/// Children are stored in the order they were added, this is the order
+/// in which the solver (and the visitor) produced them. A node may also
+/// *reference* a previously serialized node whose subtree is identical,
+/// in which case its only child is its result. The topology itself
+/// remains a tree, navigating it never follows references, those that want
+/// the shared subtree `resolve` the node first.
 // NOTE: instead of using a generic parameter `I: Idx` it's
 // more convenient to use `ProofNodeIdx` for ts-rs.
 #[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
 pub struct TreeTopology {
-  pub children: HashMap<ProofNodeIdx, HashSet<ProofNodeIdx>>,
+  pub children: HashMap<ProofNodeIdx, Vec<ProofNodeIdx>>,
   pub parent: HashMap<ProofNodeIdx, ProofNodeIdx>,
+  /// Map from a node to the (earlier) node whose subtree it shares.
+  pub shared: HashMap<ProofNodeIdx, ProofNodeIdx>,
 }
 
 #[derive(Clone, Debug)]
@@ -102,14 +106,37 @@ impl TreeTopology {
     Self {
       children: HashMap::default(),
       parent: HashMap::default(),
+      shared: HashMap::default(),
     }
   }
 
   pub fn add(&mut self, from: ProofNodeIdx, to: ProofNodeIdx) {
-    self.children.entry(from).or_default().insert(to);
+    let children = self.children.entry(from).or_default();
+    if !children.contains(&to) {
+      children.push(to);
+    }
     self.parent.insert(to, from);
   }
 
+  /// Mark the subtree of `node` as identical to that of `original`.
+  pub fn share(&mut self, node: ProofNodeIdx, original: ProofNodeIdx) {
+    debug_assert!(self.is_leaf(node), "shared nodes cannot have children");
+    self.shared.insert(node, original);
+  }
+
+  /// The node whose subtree is shared by `node`, if any.
+  pub fn shared_with(&self, node: ProofNodeIdx) -> Option<ProofNodeIdx> {
+    self.shared.get(&node).copied()
+  }
+
+  /// Follow shared references until reaching the node that owns its children.
+  pub fn resolve(&self, mut node: ProofNodeIdx) -> ProofNodeIdx {
+    while let Some(original) = self.shared_with(node) {
+      node = original;
+    }
+    node
+  }
+
   pub fn is_parent(&self, parent: ProofNodeIdx, child: ProofNodeIdx) -> bool {
     self.parent.get(&child).is_some_and(|p| *p == parent)
   }
@@ -125,6 +152,7 @@ impl TreeTopology {
     self.parent.get(&to).copied()
   }
 
+  /// Children of `from` in insertion order.
   pub fn children(
     &self,
     from: ProofNodeIdx,
@@ -179,3 +207,71 @@ impl TreeTopology {
     d
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn idx(i: usize) -> ProofNodeIdx {
+    ProofNodeIdx::from_usize(i)
+  }
+
+  #[test]
+  fn children_keep_insertion_order() {
+    let mut topology = TreeTopology::new();
+    topology.add(idx(0), idx(5));
+    topology.add(idx(0), idx(2));
+    topology.add(idx(0), idx(9));
+    topology.add(idx(0), idx(2));
+
+    let children = topology.children(idx(0)).collect::<Vec<_>>();
+    assert_eq!(children, vec![idx(5), idx(2), idx(9)]);
+  }
+
+  #[test]
+  fn shared_nodes_are_leaves() {
+    // 0 -> 1 -> 2 and 0 -> 3, where 3 shares the subtree of 1.
+    let mut topology = TreeTopology::new();
+    topology.add(idx(0), idx(1));
+    topology.add(idx(1), idx(2));
+    topology.add(idx(0), idx(3));
+    topology.share(idx(3), idx(1));
+
+    assert!(topology.is_leaf(idx(3)));
+    assert_eq!(topology.children(idx(3)).count(), 0);
+    assert_eq!(topology.resolve(idx(3)), idx(1));
+    assert_eq!(
+      topology
+        .children(topology.resolve(idx(3)))
+        .collect::<Vec<_>>(),
+      vec![idx(2)]
+    );
+
+    // Navigating upwards agrees with navigating downwards.
+    assert_eq!(topology.parent(idx(2)), Some(idx(1)));
+    assert_eq!(topology.depth(idx(2)), 2);
+    assert_eq!(topology.depth(idx(3)), 1);
+    assert_eq!(
+      topology
+        .path_to_root(idx(3))
+        .iter_inclusive()
+        .copied()
+        .collect::<Vec<_>>(),
+      vec![idx(3), idx(0)]
+    );
+  }
+
+  #[test]
+  fn resolve_follows_references() {
+    let mut topology = TreeTopology::new();
+    topology.add(idx(0), idx(1));
+    topology.add(idx(1), idx(2));
+    topology.add(idx(0), idx(3));
+    topology.add(idx(0), idx(4));
+    topology.share(idx(3), idx(1));
+    topology.share(idx(4), idx(3));
+
+    assert_eq!(topology.resolve(idx(4)), idx(1));
+    assert_eq!(topology.resolve(idx(2)), idx(2));
+  }
+}
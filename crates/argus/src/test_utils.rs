@@ -33,6 +33,11 @@ static SYSROOT: LazyLock<String> = LazyLock::new(|| {
   String::from_utf8(rustc_output).unwrap().trim().to_owned()
 });
 
+/// Sysroot of the toolchain running the tests.
+pub fn sysroot() -> &'static str {
+  &SYSROOT
+}
+
 pub const DUMMY_FILE_NAME: &str = "dummy.rs";
 
 thread_local! {
@@ -144,7 +149,7 @@ pub fn test_tree_for_target(
   inner().unwrap();
 }
 
-#[allow(clippy::missing_panics_doc)]
+#[allow(clippy::missing_panics_doc, clippy::unnecessary_debug_formatting)]
 pub fn run_in_dir(
   dir: impl AsRef<Path>,
   test_fn: impl Fn(&Path) + std::panic::RefUnwindSafe,
@@ -71,6 +71,8 @@ impl ExtensionCandidates {
 #[cfg_attr(feature = "testing", derive(TS))]
 #[cfg_attr(feature = "testing", ts(export))]
 pub struct Expr {
+  /// Range of the syntax the obligations are about, this can be smaller
+  /// than the snippet, e.g., only the operator of `a + b`.
   pub range: CharRange,
   pub snippet: String,
   #[cfg_attr(feature = "testing", ts(type = "ObligationIdx[]"))]
@@ -87,6 +89,48 @@ pub enum ExprKind {
   CallableExpr,
   Call,
   CallArg,
+  MethodReceiver,
+  FieldAccess,
+  /// The `?` operator.
+  Try,
+  /// The `await` keyword.
+  Await,
+  Closure,
+  /// The iterator of a `for` loop.
+  ForLoopIterator,
+  /// An overloadable unary, binary, or compound assignment operator.
+  Operator {
+    /// The trait of the operator, absent for `&&` and `||`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "OperatorTrait | undefined"))]
+    data: Option<OperatorTrait>,
+  },
+  Index,
+  StructLiteral,
+  /// A type or bound in an item's signature, outside of any body.
+  Signature,
+}
+
+/// The trait an operator is overloaded with, `Lhs: Trait<Rhs>`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct OperatorTrait {
+  #[serde(rename = "trait")]
+  #[cfg_attr(
+    feature = "testing",
+    ts(rename = "trait", type = "PathDefNoArgs")
+  )]
+  pub trait_: json::Value,
+
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub lhs: json::Value,
+
+  /// The right-hand side of binary operators.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "Ty | undefined"))]
+  pub rhs: Option<json::Value>,
 }
 
 #[derive(Serialize)]
@@ -119,6 +163,283 @@ pub struct TraitError {
   pub idx: ExprIdx,
   pub range: CharRange,
   pub hashes: Vec<ObligationHash>,
+  /// Locals that make a future fail an auto trait, e.g., `Send`.
+  pub held_across_await: Vec<HeldAcrossAwait>,
+  /// Traits used as `dyn Trait` by the failing obligations that aren't
+  /// dyn compatible.
+  pub dyn_compatibility: Vec<DynCompatibility>,
+}
+
+/// A local whose type doesn't implement an auto trait, held across an await
+/// point of a future that is required to implement it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct HeldAcrossAwait {
+  /// Name of the binding, temporaries don't have one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub name: Option<String>,
+
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub ty: json::Value,
+
+  #[cfg_attr(feature = "testing", ts(type = "PathDefNoArgs"))]
+  pub auto_trait: json::Value,
+
+  /// Range of the binding, or of the expression creating the temporary.
+  pub binding: CharRange,
+
+  /// Range of the first await point where the local is live.
+  pub await_point: CharRange,
+}
+
+/// Why a trait can't be used as a trait object, `dyn Trait`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct DynCompatibility {
+  #[serde(rename = "trait")]
+  #[cfg_attr(
+    feature = "testing",
+    ts(rename = "trait", type = "PathDefNoArgs")
+  )]
+  pub trait_: json::Value,
+  pub violations: Vec<DynCompatibilityViolation>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct DynCompatibilityViolation {
+  pub kind: DynCompatibilityViolationKind,
+
+  /// Name of the offending method, associated const or type.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub name: Option<String>,
+
+  /// The compiler's description, e.g., "method `f` has generic type
+  /// parameters".
+  pub message: String,
+
+  /// Where the violation is in the trait definition, empty for traits
+  /// of other crates.
+  #[cfg_attr(feature = "testing", ts(type = "DefLocation[]"))]
+  pub locations: Vec<ser::DefLocation>,
+
+  pub fixes: Vec<DynCompatibilityFix>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum DynCompatibilityViolationKind {
+  /// The trait requires `Self: Sized`.
+  SizedSelf,
+  /// A supertrait or where-clause uses `Self` as a type argument.
+  SupertraitSelf,
+  SupertraitNonLifetimeBinder,
+  /// An associated function without a `self` parameter.
+  StaticMethod,
+  GenericMethod,
+  ReferencesSelfInput,
+  ReferencesSelfOutput,
+  ReferencesImplTraitInTrait,
+  AsyncFn,
+  WhereClauseReferencesSelf,
+  UndispatchableReceiver,
+  AssocConst,
+  GenericAssocType,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum DynCompatibilityFix {
+  /// Insert `suggestion` at `location` to make the method take `&self`.
+  AddSelfParameter {
+    suggestion: String,
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation"))]
+    location: ser::DefLocation,
+  },
+  /// Insert `suggestion`, a `Self: Sized` bound, at `location` so the
+  /// method isn't callable on trait objects.
+  RequireSized {
+    suggestion: String,
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation"))]
+    location: ser::DefLocation,
+  },
+  /// Change the `self` type at `location` to `&Self`.
+  ChangeToRefSelf {
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation"))]
+    location: ser::DefLocation,
+  },
+  /// Split the item off into a separate trait.
+  MoveToAnotherTrait { name: String },
+}
+
+/// How a method call was resolved, replaying the compiler's probe: each
+/// adjusted receiver type in order, with the candidate methods tried at it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct MethodProbe {
+  /// Range of the method name and arguments.
+  pub range: CharRange,
+
+  /// The call expression, if it has obligations.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "ExprIdx | undefined"))]
+  pub expr: Option<ExprIdx>,
+
+  pub name: String,
+
+  /// Type of the receiver expression, before any adjustment.
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub receiver: json::Value,
+
+  /// The method the compiler picked, absent if none was found.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "PickedMethod | undefined"))]
+  pub picked: Option<PickedMethod>,
+
+  pub candidates: Vec<MethodCandidate>,
+
+  /// Receiver types tried, up to the one where a method applies.
+  pub steps: Vec<ProbeStep>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct PickedMethod {
+  #[cfg_attr(feature = "testing", ts(type = "PathDefNoArgs"))]
+  pub method: json::Value,
+
+  /// Adjustments applied to the receiver, in order.
+  pub adjustments: Vec<ReceiverAdjustment>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct MethodCandidate {
+  #[cfg_attr(feature = "testing", ts(type = "PathDefNoArgs"))]
+  pub method: json::Value,
+
+  pub kind: MethodCandidateKind,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+  pub location: Option<ser::DefLocation>,
+
+  /// The declared type of `self`, absent for associated functions.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "Ty | undefined"))]
+  pub receiver: Option<json::Value>,
+
+  /// Why the candidate is rejected regardless of the receiver type, these
+  /// candidates aren't tried at the steps.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "CandidateRejection | undefined"))]
+  pub rejection: Option<CandidateRejection>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum MethodCandidateKind {
+  Inherent,
+  Trait {
+    #[serde(rename = "trait")]
+    #[cfg_attr(
+      feature = "testing",
+      ts(rename = "trait", type = "PathDefNoArgs")
+    )]
+    trait_: json::Value,
+  },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum CandidateRejection {
+  /// An associated function without a `self` parameter.
+  NotAMethod,
+  /// The method isn't visible from the body.
+  Private,
+  /// The trait is implemented for the receiver but isn't imported.
+  NotInScope,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct ProbeStep {
+  /// The adjusted receiver type.
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub ty: json::Value,
+
+  /// Adjustments from the receiver expression to `ty`, in order.
+  pub adjustments: Vec<ReceiverAdjustment>,
+
+  pub candidates: Vec<StepCandidate>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct StepCandidate {
+  /// Index of the candidate in the probe's candidates.
+  pub candidate: usize,
+  pub outcome: StepOutcome,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum StepOutcome {
+  /// The method the compiler picked.
+  Picked,
+  /// The method applies but wasn't picked, e.g., because it's ambiguous
+  /// with another trait's method.
+  Applicable,
+  /// The type of `self` doesn't match the adjusted receiver.
+  ReceiverMismatch,
+  /// The receiver matches, but the impl's where-clauses, or the trait
+  /// bound of a trait method, don't hold.
+  Unsatisfied {
+    #[cfg_attr(feature = "testing", ts(type = "Predicate"))]
+    predicate: json::Value,
+  },
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum ReceiverAdjustment {
+  /// A dereference, through the `Deref` trait if `overloaded`.
+  Deref { overloaded: bool },
+  /// An autoref, `&` or `&mut`.
+  Borrow { mutable: bool },
+  /// An autoref to a raw pointer.
+  RawBorrow { mutable: bool },
+  /// An unsizing coercion, e.g., from an array to a slice.
+  Unsize,
 }
 
 #[derive(Serialize)]
@@ -147,6 +468,10 @@ pub struct ObligationsInBody {
   /// but not satisfied.
   pub trait_errors: Vec<TraitError>,
 
+  /// Method calls of bodies with errors, explaining how they were
+  /// resolved.
+  pub method_probes: Vec<MethodProbe>,
+
   #[cfg_attr(feature = "testing", ts(type = "Obligation[]"))]
   pub obligations: IndexVec<ObligationIdx, Obligation>,
 
@@ -175,6 +500,7 @@ impl ObligationsInBody {
       is_tainted,
       ambiguity_errors,
       trait_errors,
+      method_probes: Vec::default(),
       obligations,
       exprs,
       tys,
@@ -209,6 +535,211 @@ pub struct Obligation {
   #[serde(with = "EvaluationResultDef")]
   #[cfg_attr(feature = "testing", ts(type = "EvaluationResult"))]
   pub result: EvaluationResult,
+
+  /// Expected and found types of a failed projection.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "ProjectionMismatch | undefined"))]
+  pub projection_mismatch: Option<ProjectionMismatch>,
+
+  /// Why the region of an outlives obligation is required.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(
+    feature = "testing",
+    ts(type = "OutlivesExplanation | undefined")
+  )]
+  pub outlives: Option<OutlivesExplanation>,
+
+  /// The derive whose expansion introduced the obligation.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DeriveOrigin | undefined"))]
+  pub derive: Option<DeriveOrigin>,
+}
+
+/// The derive in an attribute list whose expansion introduced an
+/// obligation, e.g., `Serialize` in `#[derive(Debug, Serialize)]`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct DeriveOrigin {
+  /// Path of the derive macro as written in the attribute.
+  pub derive: String,
+
+  /// Range of the derive's path in the attribute list.
+  pub range: CharRange,
+
+  /// The field whose type the obligation is about, if it's about one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DeriveField | undefined"))]
+  pub field: Option<DeriveField>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct DeriveField {
+  /// Name of the field, its index for tuple fields.
+  pub name: String,
+
+  /// Name of the variant declaring the field, for enums.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+  pub variant: Option<String>,
+
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub ty: json::Value,
+  pub range: CharRange,
+}
+
+/// The chain of requirements behind an outlives obligation, e.g.,
+/// `T: 'static` is required because `spawn` requires `F: 'static` and `F`
+/// captures `T`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct OutlivesExplanation {
+  /// The region that must be outlived.
+  #[cfg_attr(feature = "testing", ts(type = "Region"))]
+  pub region: json::Value,
+  pub origin: RegionOrigin,
+
+  /// The item whose where-clause requires the obligation.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(
+    feature = "testing",
+    ts(type = "OutlivesRequirement | undefined")
+  )]
+  pub required_by: Option<OutlivesRequirement>,
+
+  /// From the obligation's type down to the component that must outlive
+  /// the region, empty for region obligations.
+  pub steps: Vec<OutlivesStep>,
+
+  /// Location of the signature type whose well-formedness implies the
+  /// obligation, e.g., `&'a T` implies `T: 'a`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+  pub implied_by: Option<ser::DefLocation>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum RegionOrigin {
+  Static,
+  /// The `'static` bound of a trait object, the default for `Box<dyn Any>`.
+  TraitObject,
+  /// A lifetime parameter of the item, or one of its parents.
+  Named {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+    location: Option<ser::DefLocation>,
+  },
+  /// A region inferred within the body, e.g., of a borrow.
+  Local,
+  Anonymous,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct OutlivesRequirement {
+  #[cfg_attr(feature = "testing", ts(type = "PathDefNoArgs"))]
+  pub item: json::Value,
+
+  /// Location of the bound.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+  pub location: Option<ser::DefLocation>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct OutlivesStep {
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub ty: json::Value,
+
+  /// How the type of the next step is reached from `ty`, absent on the
+  /// last step.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "OutlivesComponent | undefined"))]
+  pub component: Option<OutlivesComponent>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub enum OutlivesComponent {
+  /// A place captured by a closure or coroutine, e.g., `x` or `x.field`.
+  Capture {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "string | undefined"))]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+    location: Option<ser::DefLocation>,
+  },
+  GenericArg {
+    index: usize,
+  },
+  TupleField {
+    index: usize,
+  },
+  Element,
+  Pointee,
+}
+
+/// An associated type that doesn't normalize to the expected type, e.g.,
+/// `<I as Iterator>::Item == &str` where the item is `String`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct ProjectionMismatch {
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub expected: json::Value,
+
+  /// The normalized associated type.
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub found: json::Value,
+
+  /// The first component where the types differ, absent when they only
+  /// differ in regions.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "TyDiff | undefined"))]
+  pub diff: Option<TyDiff>,
+
+  /// Location of the bound that fixed the expected type.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  #[cfg_attr(feature = "testing", ts(type = "DefLocation | undefined"))]
+  pub location: Option<ser::DefLocation>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "testing", derive(TS))]
+#[cfg_attr(feature = "testing", ts(export))]
+pub struct TyDiff {
+  /// Indices of the type components leading from the outer types to the
+  /// differing ones, e.g., `[0, 1]` is the second argument of the first
+  /// argument. Components are the type arguments of an ADT, the pointee of
+  /// a reference or pointer, the element of an array or slice, the fields
+  /// of a tuple, and the inputs and output of a function pointer.
+  pub path: Vec<usize>,
+
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub expected: json::Value,
+
+  #[cfg_attr(feature = "testing", ts(type = "Ty"))]
+  pub found: json::Value,
 }
 
 #[derive(Serialize, Clone, Debug, PartialEq, Eq)]
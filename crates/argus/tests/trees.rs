@@ -0,0 +1,1203 @@
+#![feature(rustc_private)]
+use argus_lib::{analysis, test_utils as tu};
+
+const SHARED_SUBGOALS: &str = r#"
+trait Marker {}
+impl<A: Marker, B: Marker> Marker for (A, B) {}
+struct Elem;
+fn needs<T: Marker>() {}
+fn shared() {
+  needs::<((Elem, u8), (Elem, u8))>();
+}
+"#;
+
+#[test_log::test]
+fn shared_subtrees() {
+  tu::compile_normal(SHARED_SUBGOALS, |tcx| {
+    let mut shared = 0;
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let topology = &tree.topology;
+        for (&node, &original) in &topology.shared {
+          shared += 1;
+          assert!(original < node, "shared nodes reference earlier ones");
+          assert_eq!(tree.nodes[node], tree.nodes[original]);
+          assert_eq!(
+            tree.all_impl_candidates.contains_key(&node),
+            tree.all_impl_candidates.contains_key(&original)
+          );
+
+          // The goal's result is its only child.
+          let children = topology.children(node).collect::<Vec<_>>();
+          assert_eq!(children.len(), 1);
+          let child = serde_json::to_value(tree.nodes[children[0]]).unwrap();
+          assert!(child.get("Result").is_some(), "{child}");
+        }
+
+        // Shared or not, the topology is a tree.
+        for node in topology.iter() {
+          for child in topology.children(node) {
+            assert_eq!(topology.parent(child), Some(node));
+          }
+        }
+      }
+    });
+    assert!(shared > 0, "no subtrees were shared");
+  });
+}
+
+const SAME_TRAIT_BOUNDS: &str = r#"
+struct Wrap<T>(T);
+trait Convert {}
+impl<T> Convert for Wrap<T> where T: From<u8> + From<bool> {}
+struct Only;
+impl From<u8> for Only {
+  fn from(_: u8) -> Self {
+    Only
+  }
+}
+fn needs<C: Convert>() {}
+fn convert() {
+  needs::<Wrap<Only>>();
+}
+"#;
+
+#[test_log::test]
+fn where_clause_locations() {
+  let column_of =
+    |bound: &str| SAME_TRAIT_BOUNDS.lines().find_map(|line| line.find(bound));
+  let expected = [column_of("From<u8>"), column_of("From<bool>")];
+
+  tu::compile_normal(SAME_TRAIT_BOUNDS, move |tcx| {
+    let mut columns = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        for source in tree.goal_sources.values() {
+          let Some(location) = &source.where_clause else {
+            continue;
+          };
+          let location = serde_json::to_value(location).unwrap();
+          columns.push(location["r"]["start"]["column"].as_u64().unwrap());
+        }
+      }
+    });
+
+    // Each bound is located at itself, not at the first `From` bound.
+    for column in expected {
+      let column = column.unwrap() as u64;
+      assert!(columns.contains(&column), "{column} not in {columns:?}");
+    }
+  });
+}
+
+const PARAM_ENV_IN_CLOSURE: &str = r#"
+trait Super {}
+trait Sub: Super {}
+trait Missing {}
+trait Both {}
+impl<T: Super + Sub + Missing> Both for T {}
+fn needs<B: Both>(_: B) {}
+fn outer<T: Sub>(t: T) {
+  let f = move || needs(t);
+}
+"#;
+
+#[test_log::test]
+fn param_env_origins() {
+  tu::compile_normal(PARAM_ENV_IN_CLOSURE, |tcx| {
+    let mut origins = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        for candidate in &tree.candidates {
+          let candidate = serde_json::to_value(candidate).unwrap();
+          let Some(param_env) = candidate.get("ParamEnv") else {
+            continue;
+          };
+          let line = param_env["location"]["r"]["start"]["line"].as_u64();
+          origins.push((param_env["origin"].to_string(), line));
+        }
+      }
+    });
+
+    // `T: Sub` is declared on `outer`, and `T: Super` is implied by the
+    // supertrait of `Sub`, even within the closure.
+    let line_of = |needle: &str| {
+      PARAM_ENV_IN_CLOSURE
+        .lines()
+        .position(|line| line.contains(needle))
+        .map(|line| line as u64)
+    };
+    let declared = ("\"Declared\"".to_string(), line_of("fn outer"));
+    let supertrait = ("\"Supertrait\"".to_string(), line_of("trait Sub"));
+    assert!(origins.contains(&declared), "{origins:?}");
+    assert!(origins.contains(&supertrait), "{origins:?}");
+  });
+}
+
+const STUBBED_IMPLS: &str = r#"
+trait Marker {}
+trait Items {
+  type Out;
+  fn get(&self) -> Self::Out;
+}
+struct Local;
+fn needs<T: Marker + Items>() {}
+fn stubbed() {
+  needs::<Local>();
+}
+"#;
+
+/// Fixes found anywhere in the serialized `value`.
+fn fixes_in(value: &serde_json::Value, out: &mut Vec<serde_json::Value>) {
+  match value {
+    serde_json::Value::Object(map) => {
+      if let Some(serde_json::Value::Array(fixes)) = map.get("fixes") {
+        out.extend(
+          fixes
+            .iter()
+            .filter(|fix| fix.get("edits").is_some())
+            .cloned(),
+        );
+      }
+      map.values().for_each(|v| fixes_in(v, out));
+    }
+    serde_json::Value::Array(values) => {
+      values.iter().for_each(|v| fixes_in(v, out));
+    }
+    _ => {}
+  }
+}
+
+#[test_log::test]
+fn placeholder_fixes() {
+  tu::compile_normal(STUBBED_IMPLS, |tcx| {
+    let mut impls = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let mut fixes = vec![];
+        fixes_in(&serde_json::to_value(&tree.analysis).unwrap(), &mut fixes);
+        for fix in fixes {
+          if fix["kind"]["type"] != "ImplTrait" {
+            continue;
+          }
+          let replacement = fix["edits"][0]["replacement"].to_string();
+          impls.push((replacement, fix["applicability"]["type"].clone()));
+        }
+      }
+    });
+
+    // Stubbed items need to be written, empty impls are complete.
+    let applicability = |name: &str| {
+      impls
+        .iter()
+        .find(|(replacement, _)| replacement.contains(name))
+        .map(|(_, applicability)| applicability.clone())
+    };
+    assert_eq!(
+      applicability("impl Marker for Local"),
+      Some("MachineApplicable".into()),
+      "{impls:?}"
+    );
+    assert_eq!(
+      applicability("impl Items for Local"),
+      Some("HasPlaceholders".into()),
+      "{impls:?}"
+    );
+  });
+}
+
+const DERIVES_AND_IMPORTS: &str = r#"
+mod traits {
+  pub trait Remote {}
+}
+#[derive(Clone)]
+struct Cloned;
+struct Plain;
+fn needs_eq<T: PartialEq>() {}
+fn needs_copy<T: Copy>() {}
+fn needs_ord<T: Ord>() {}
+fn needs_remote<T: traits::Remote>() {}
+fn fixes() {
+  needs_eq::<Plain>();
+  needs_copy::<Cloned>();
+  needs_ord::<Plain>();
+  needs_remote::<Plain>();
+}
+"#;
+
+#[test_log::test]
+fn derives_and_imports() {
+  tu::compile_normal(DERIVES_AND_IMPORTS, |tcx| {
+    let mut derives = vec![];
+    let mut impls = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let mut fixes = vec![];
+        fixes_in(&serde_json::to_value(&tree.analysis).unwrap(), &mut fixes);
+        for fix in fixes {
+          let replacement = fix["edits"][0]["replacement"].as_str().unwrap();
+          let replacement = replacement.trim().to_string();
+          let applicability =
+            fix["applicability"]["type"].as_str().unwrap().to_string();
+          match fix["kind"]["type"].as_str() {
+            Some("Derive") => derives.push((replacement, applicability)),
+            Some("ImplTrait") => impls.push((replacement, applicability)),
+            _ => {}
+          }
+        }
+      }
+    });
+
+    // `Clone` is already derived.
+    derives.dedup();
+    assert_eq!(derives, [(
+      "#[derive(Copy)]".to_string(),
+      "MachineApplicable".to_string()
+    )]);
+
+    // `Remote` isn't in scope where the impl is inserted.
+    let remote = impls
+      .iter()
+      .find(|(replacement, _)| replacement.contains("impl Remote for Plain"))
+      .unwrap_or_else(|| panic!("no impl of `Remote` in {impls:?}"));
+    assert_eq!(remote.1, "MaybeIncorrect");
+  });
+}
+
+const HANDLER_PARAMS: &str = r#"
+trait FromRequest {}
+impl FromRequest for u8 {}
+struct Body;
+trait Handler<T> {}
+#[diagnostic::do_not_recommend]
+impl<F, T1> Handler<(T1,)> for F
+where
+  F: Fn(T1),
+  T1: FromRequest,
+{
+}
+#[diagnostic::do_not_recommend]
+impl<F, T1, T2> Handler<(T1, T2)> for F
+where
+  F: Fn(T1, T2),
+  T1: FromRequest,
+  T2: FromRequest,
+{
+}
+fn route<T, H: Handler<T>>(_: H) {}
+fn handler(_: Body, _: u8) {}
+fn routes() {
+  route(handler);
+}
+"#;
+
+/// Values of the goal kinds named `kind` found anywhere in `value`.
+fn goal_kinds_in(
+  value: &serde_json::Value,
+  kind: &str,
+  out: &mut Vec<serde_json::Value>,
+) {
+  match value {
+    serde_json::Value::Object(map) => {
+      if map.get("type").is_some_and(|ty| ty == kind) {
+        out.push(value.clone());
+      }
+      map.values().for_each(|v| goal_kinds_in(v, kind, out));
+    }
+    serde_json::Value::Array(values) => {
+      values.iter().for_each(|v| goal_kinds_in(v, kind, out));
+    }
+    _ => {}
+  }
+}
+
+#[test_log::test]
+fn handler_params() {
+  tu::compile_normal(HANDLER_PARAMS, |tcx| {
+    let mut kinds = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let analysis = serde_json::to_value(&tree.analysis).unwrap();
+        goal_kinds_in(&analysis, "FnToTrait", &mut kinds);
+      }
+    });
+
+    // Only the first parameter fails its bound, the other has the same
+    // place in the impl but a type that implements it.
+    kinds.retain(|kind| kind["_trait"]["type"] == "Local");
+    assert!(!kinds.is_empty());
+    for kind in kinds {
+      let positions = kind["params"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|param| param["position"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+      assert_eq!(positions, [0], "{kind}");
+      assert!(kind["params"][0]["goal"].is_u64(), "{kind}");
+    }
+  });
+}
+
+const CLOSURE_SIGNATURE: &str = r"
+trait Handler {}
+impl<F: Fn(u8) -> bool> Handler for F {}
+fn handle<H: Handler>(_: H) {}
+fn closures() {
+  handle(|_: u16| true);
+}
+";
+
+#[test_log::test]
+fn closure_signature() {
+  tu::compile_normal(CLOSURE_SIGNATURE, |tcx| {
+    let mut signatures = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let analysis = serde_json::to_value(&tree.analysis).unwrap();
+        let tys = serde_json::to_value(&tree.tys).unwrap();
+        let mut kinds = vec![];
+        goal_kinds_in(&analysis, "FnToTrait", &mut kinds);
+        kinds.retain(|kind| kind["_trait"]["type"] == "Local");
+        for kind in kinds {
+          let param = &kind["signature"]["params"][0];
+          let ty = |value: &serde_json::Value| {
+            tys[value.as_u64().unwrap() as usize].clone()
+          };
+          signatures.push((
+            param["source"]["type"].clone(),
+            ty(&param["inferred"]),
+            ty(&param["expected"]),
+            param["matches"].clone(),
+          ));
+          assert!(kind["signature"]["inferred"].is_u64(), "{kind}");
+          assert!(kind["signature"]["expected"].is_u64(), "{kind}");
+        }
+      }
+    });
+
+    assert!(!signatures.is_empty());
+    for (source, inferred, expected, matches) in signatures {
+      assert_eq!(source, "Annotated");
+      assert_eq!(inferred, serde_json::json!({ "Uint": "U16" }));
+      assert_eq!(expected, serde_json::json!({ "Uint": "U8" }));
+      assert_eq!(matches, false);
+    }
+  });
+}
+
+const AMBIGUOUS_VAR: &str = r#"
+trait Pick {}
+impl Pick for u8 {}
+impl Pick for u16 {}
+trait Both {}
+impl<T: Pick + Clone + Default> Both for (T, T) {}
+fn pick<T>() -> T {
+  todo!()
+}
+fn both<T: Both>(_: T) {}
+fn ambiguous() {
+  let p = pick();
+  both((p, p));
+}
+"#;
+
+#[test_log::test]
+fn ambiguity_order() {
+  tu::compile_normal(AMBIGUOUS_VAR, |tcx| {
+    let mut explained = 0;
+    tu::for_each_body(tcx, |body_id, tcx| {
+      // Each bundle hashes the topology with its own random state.
+      let [first, second] = [(), ()].map(|()| {
+        let bundle = analysis::bundle(tcx, body_id).unwrap();
+        let mut ambiguities = bundle
+          .trees
+          .values()
+          .filter_map(|tree| {
+            let ambiguity = tree.analysis.ambiguity.as_ref()?;
+            Some(serde_json::to_value(ambiguity).unwrap())
+          })
+          .collect::<Vec<_>>();
+        ambiguities.sort_by_key(ToString::to_string);
+        ambiguities
+      });
+      assert_eq!(first, second);
+
+      for ambiguity in first {
+        for var in ambiguity["vars"].as_array().unwrap() {
+          let goals = var["goals"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|goal| goal.as_u64().unwrap())
+            .collect::<Vec<_>>();
+          let mut sorted = goals.clone();
+          sorted.sort_unstable();
+          assert_eq!(goals, sorted);
+          explained += goals.len();
+        }
+      }
+    });
+    assert!(explained > 1, "too few goals to order");
+  });
+}
+
+const OPERATORS: &str = r#"
+struct Meters;
+impl std::ops::Add for Meters {
+  type Output = Meters;
+  fn add(self, _: Meters) -> Meters {
+    Meters
+  }
+}
+fn operators() {
+  let _ = Meters + 1u8;
+  let _ = -Meters;
+}
+"#;
+
+#[test_log::test]
+fn operator_traits() {
+  tu::compile_normal(OPERATORS, |tcx| {
+    let mut operators = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      let ty = |idx: &serde_json::Value| {
+        bundle.body.tys[idx.as_u64().unwrap() as usize].to_string()
+      };
+      for expr in &bundle.body.exprs {
+        let kind = serde_json::to_value(&expr.kind).unwrap();
+        let Some(data) = kind.get("Operator").map(|op| &op["data"]) else {
+          continue;
+        };
+
+        let name = data["trait"]["path"].as_array().unwrap().last().unwrap()
+          ["name"]
+          .clone();
+        let rhs = data.get("rhs").map(ty);
+        operators.push((expr.range.start.line, name, ty(&data["lhs"]), rhs));
+      }
+    });
+
+    let [(add_line, add, lhs, rhs), (neg_line, neg, operand, none)] =
+      &operators[..]
+    else {
+      panic!("expected two operators {operators:#?}");
+    };
+    assert_eq!((*add_line, add.as_str()), (9, Some("Add")));
+    assert!(lhs.contains("Meters") && rhs.as_ref().unwrap().contains("U8"));
+    assert_eq!((*neg_line, neg.as_str()), (10, Some("Neg")));
+    assert!(operand.contains("Meters") && none.is_none());
+  });
+}
+
+const HELD_ACROSS_AWAIT: &str = r#"
+use std::rc::Rc;
+async fn tick() {}
+async fn hold() {
+  let first = Rc::new(1);
+  tick().await;
+  let second = Rc::new(2);
+  tick().await;
+  drop((first, second));
+}
+fn needs_send<F: Send>(_: F) {}
+fn spawn() {
+  needs_send(hold());
+}
+"#;
+
+#[test_log::test]
+fn held_locals_of_the_same_type() {
+  tu::compile_normal(HELD_ACROSS_AWAIT, |tcx| {
+    let mut names = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for error in &bundle.body.trait_errors {
+        for local in &error.held_across_await {
+          names.push(local.name.clone());
+        }
+      }
+    });
+
+    names.sort();
+    assert_eq!(names, [
+      Some("first".to_string()),
+      Some("second".to_string())
+    ]);
+  });
+}
+
+const AUTO_TRAIT_CAUSES: &str = r#"
+trait Foo {}
+struct Guarded<T>(*const T);
+unsafe impl<T: Foo> Send for Guarded<T> {}
+trait Tr {
+  type Assoc;
+}
+impl Tr for u8 {
+  type Assoc = std::rc::Rc<u8>;
+}
+struct Holder<T: Tr> {
+  count: u32,
+  held: <T as Tr>::Assoc,
+}
+fn needs_send<T: Send>() {}
+fn guarded() {
+  needs_send::<Guarded<u8>>();
+}
+fn holder() {
+  needs_send::<Holder<u8>>();
+}
+"#;
+
+#[test_log::test]
+fn auto_trait_causes() {
+  tu::compile_normal(AUTO_TRAIT_CAUSES, |tcx| {
+    let mut paths = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        for path in tree.auto_traits.values() {
+          paths.push(serde_json::to_value(path).unwrap());
+        }
+      }
+    });
+
+    let where_clause = paths
+      .iter()
+      .find(|path| path["cause"]["type"] == "WhereClause")
+      .unwrap_or_else(|| panic!("no where-clause cause in {paths:#?}"));
+    assert!(where_clause["cause"]["clause"].to_string().contains("Foo"));
+
+    let fields = paths
+      .iter()
+      .flat_map(|path| path["steps"].as_array().unwrap())
+      .filter(|step| step["component"]["type"] == "Field")
+      .map(|step| &step["component"]["name"])
+      .collect::<Vec<_>>();
+    assert_eq!(fields, ["held"]);
+  });
+}
+
+const OUTLIVES_REQUIRED_BY: &str = r"
+struct Wrapper<T>(T);
+trait Register {}
+impl<T: Copy + 'static> Register for Wrapper<T> {}
+fn register<R: Register>(_: R) {}
+fn owned() {
+  register(Wrapper(String::new()));
+}
+";
+
+#[test_log::test]
+fn outlives_required_by() {
+  tu::compile_normal(OUTLIVES_REQUIRED_BY, |tcx| {
+    let mut explanations = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        for explanation in tree.outlives.values() {
+          explanations.push(serde_json::to_value(explanation).unwrap());
+        }
+      }
+    });
+
+    let explanation = explanations
+      .iter()
+      .find(|explanation| explanation["origin"]["type"] == "Static")
+      .unwrap_or_else(|| panic!("no 'static bound in {explanations:#?}"));
+    let required_by = &explanation["requiredBy"];
+    assert!(required_by["item"].to_string().contains("Register"));
+    assert_eq!(required_by["location"]["r"]["start"]["line"], 3);
+  });
+}
+
+const METHOD_PROBE_STEPS: &str = r"
+mod hidden {
+  pub trait Missing {
+    fn missing(&self);
+  }
+  impl Missing for u8 {
+    fn missing(&self) {}
+  }
+}
+struct Wrapper(u8);
+impl std::ops::Deref for Wrapper {
+  type Target = u8;
+  fn deref(&self) -> &u8 {
+    &self.0
+  }
+}
+fn probes() {
+  let w = Wrapper(0);
+  w.missing();
+  let _ = w.pow(2);
+}
+";
+
+#[test_log::test]
+fn method_probe_steps() {
+  tu::compile_normal(METHOD_PROBE_STEPS, |tcx| {
+    let mut probes = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      let body = serde_json::to_value(&bundle.body).unwrap();
+      probes.extend(body["methodProbes"].as_array().unwrap().clone());
+    });
+
+    // The resolved call without failed obligations isn't probed.
+    let [probe] = &probes[..] else {
+      panic!("expected a single probe in {probes:#?}");
+    };
+    assert_eq!(probe["name"], "missing");
+
+    let borrow =
+      |mutable| serde_json::json!({ "type": "Borrow", "mutable": mutable });
+    let deref = serde_json::json!({ "type": "Deref", "overloaded": true });
+    let adjustments = probe["steps"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|step| step["adjustments"].clone())
+      .collect::<Vec<_>>();
+    assert_eq!(adjustments, [
+      serde_json::json!([]),
+      serde_json::json!([borrow(false)]),
+      serde_json::json!([borrow(true)]),
+      serde_json::json!([deref]),
+      serde_json::json!([deref, borrow(false)]),
+      serde_json::json!([deref, borrow(true)]),
+    ]);
+
+    let rejections = probe["candidates"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|candidate| candidate["rejection"]["type"].clone())
+      .collect::<Vec<_>>();
+    assert_eq!(rejections, ["NotInScope"]);
+  });
+}
+
+const DERIVED_FIELDS: &str = r"
+struct NotClone;
+#[derive(Clone)]
+struct Named {
+  count: u8,
+  inner: NotClone,
+}
+#[derive(Clone)]
+struct Tuple(u8, NotClone);
+#[derive(Clone)]
+enum Choice {
+  Count(u8),
+  Inner { inner: NotClone },
+}
+";
+
+#[test_log::test]
+fn derived_fields() {
+  tu::compile_normal(DERIVED_FIELDS, |tcx| {
+    // Bodies of derived impls are expansions, which `for_each_body` skips.
+    let mut fields = vec![];
+    for def_id in tcx.hir_body_owners() {
+      let body_id = tcx.hir_body_owned_by(def_id).id();
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      let body = serde_json::to_value(&bundle.body).unwrap();
+      for obligation in body["obligations"].as_array().unwrap() {
+        let derive = &obligation["derive"];
+        if derive.is_null() || obligation["result"] != "no" {
+          continue;
+        }
+        assert_eq!(derive["derive"], "Clone");
+        let field = &derive["field"];
+        let field = (field["name"].clone(), field["variant"].clone());
+        if !fields.contains(&field) {
+          fields.push(field);
+        }
+      }
+    }
+
+    fields.sort_by_key(|(name, variant)| format!("{name}{variant}"));
+    let field = |name: &str, variant: Option<&str>| {
+      (serde_json::json!(name), serde_json::json!(variant))
+    };
+    assert_eq!(fields, [
+      field("1", None),
+      field("inner", Some("Inner")),
+      field("inner", None),
+    ]);
+  });
+}
+
+const FAILED_NORMALIZATION: &str = r"
+trait Trait {
+  type Assoc;
+}
+impl Trait for u8 {
+  type Assoc = u16;
+}
+fn needs<T: Trait<Assoc = u32>>() {}
+fn mismatch() {
+  needs::<u8>();
+}
+";
+
+#[test_log::test]
+fn failed_normalization() {
+  tu::compile_normal(FAILED_NORMALIZATION, |tcx| {
+    let mut traces = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let tys = serde_json::to_value(&tree.tys).unwrap();
+        let ty =
+          |idx: &serde_json::Value| tys[idx.as_u64().unwrap() as usize].clone();
+        for trace in tree.normalizations.values() {
+          let trace = serde_json::to_value(trace).unwrap();
+          let steps = trace["steps"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|step| {
+              (
+                step["kind"].clone(),
+                step["result"].clone(),
+                ty(&step["normalized"]),
+              )
+            })
+            .collect::<Vec<_>>();
+          traces.push((
+            ty(&trace["expected"]),
+            ty(&trace["normalized"]),
+            trace["outcome"].clone(),
+            steps,
+          ));
+        }
+      }
+    });
+
+    // The impl normalized the alias, but not to what the bound expects.
+    let u16 = serde_json::json!({ "Uint": "U16" });
+    let u32 = serde_json::json!({ "Uint": "U32" });
+    assert!(!traces.is_empty());
+    for (expected, normalized, outcome, steps) in traces {
+      assert_eq!(expected, u32);
+      assert_eq!(normalized, u16);
+      assert_eq!(outcome, "Failed");
+      assert_eq!(steps, [(
+        serde_json::json!("Impl"),
+        serde_json::json!("yes"),
+        u16.clone()
+      )]);
+    }
+  });
+}
+
+const NEAR_MISS_IMPLS: &str = r"
+trait Marker {}
+trait Convert<T> {}
+struct Wrap<T>(T);
+impl<T> Convert<u8> for Wrap<T> {}
+impl<T: Marker> Convert<u16> for Wrap<T> {}
+fn needs<C: Convert<u16>>() {}
+fn convert() {
+  needs::<Wrap<bool>>();
+}
+";
+
+#[test_log::test]
+fn impl_diffs() {
+  tu::compile_normal(NEAR_MISS_IMPLS, |tcx| {
+    let mut mismatches = vec![];
+    let mut where_clauses = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let tys = serde_json::to_value(&tree.tys).unwrap();
+        let ty =
+          |idx: &serde_json::Value| tys[idx.as_u64().unwrap() as usize].clone();
+        for implementors in tree.all_impl_candidates.values() {
+          for diff in implementors.diffs.values() {
+            let diff = serde_json::to_value(diff).unwrap();
+            match diff["failure"].as_str() {
+              Some("Unification") => {
+                for mismatch in diff["mismatches"].as_array().unwrap() {
+                  mismatches.push((
+                    mismatch["position"].clone(),
+                    ty(&mismatch["expected"]),
+                    ty(&mismatch["found"]),
+                  ));
+                }
+              }
+              Some("WhereClause") => {
+                assert_eq!(diff["mismatches"], serde_json::json!([]));
+                where_clauses.push(diff["whereClauses"].to_string());
+              }
+              _ => {}
+            }
+          }
+        }
+      }
+    });
+
+    // The impl for `u8` differs in the trait's argument, the generic impl
+    // unifies but requires `bool: Marker`.
+    mismatches.dedup();
+    assert_eq!(mismatches, [(
+      serde_json::json!(1),
+      serde_json::json!({ "Uint": "U16" }),
+      serde_json::json!({ "Uint": "U8" }),
+    )]);
+    assert!(!where_clauses.is_empty());
+    for clauses in where_clauses {
+      assert!(clauses.contains("Marker"), "{clauses}");
+    }
+  });
+}
+
+const FOREIGN_TRAIT_AND_TYPE: &str = r"
+fn show<T: std::fmt::Display>(_: T) {}
+fn foreign() {
+  show(Vec::<u8>::new());
+}
+";
+
+#[test_log::test]
+fn newtype_for_foreign_impls() {
+  tu::compile_normal(FOREIGN_TRAIT_AND_TYPE, |tcx| {
+    let mut goals = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let analysis = serde_json::to_value(&tree.analysis).unwrap();
+        for set in analysis["problematicSets"].as_array().unwrap() {
+          goals.extend(set["goals"].as_array().unwrap().iter().cloned());
+        }
+      }
+    });
+
+    // The orphan rules forbid `impl Display for Vec<u8>`, so only a
+    // wrapper type can implement it.
+    let newtypes = goals
+      .iter()
+      .filter(|goal| goal["kind"]["type"] == "Newtype")
+      .collect::<Vec<_>>();
+    let mut wrapped = vec![];
+    for goal in newtypes {
+      for fix in goal["fixes"].as_array().unwrap() {
+        assert_eq!(fix["kind"]["type"], "Newtype", "{goal:#}");
+        for edit in fix["edits"].as_array().unwrap() {
+          wrapped.push(edit["replacement"].as_str().unwrap().to_string());
+        }
+      }
+    }
+    assert!(
+      wrapped.contains(&"VecWrapper(Vec::<u8>::new())".to_string()),
+      "{wrapped:#?}"
+    );
+
+    let mut fixes = vec![];
+    for goal in &goals {
+      fixes_in(goal, &mut fixes);
+    }
+    assert!(
+      fixes.iter().all(|fix| fix["kind"]["type"] != "ImplTrait"
+        || !fix.to_string().contains("Display for Vec")),
+      "{fixes:#?}"
+    );
+  });
+}
+
+const DUPLICATE_VERSIONS: &str = r"
+struct Dog;
+impl speak_one::Speak for Dog {}
+fn needs<T: speak_two::Speak>() {}
+fn speak() {
+  needs::<Dog>();
+}
+";
+
+/// Compile `source` as version `version` of the crate `speak` into `dir`,
+/// in a directory named as registry sources are.
+fn compile_speak(dir: &std::path::Path, version: &str, source: &str) -> String {
+  let src = dir.join(format!("speak-{version}")).join("src");
+  std::fs::create_dir_all(&src).unwrap();
+  std::fs::write(src.join("lib.rs"), source).unwrap();
+  let rlib = dir.join(format!("libspeak-{version}.rlib"));
+  let status = std::process::Command::new("rustc")
+    .args([
+      "--crate-type",
+      "rlib",
+      "--crate-name",
+      "speak",
+      "--edition=2021",
+    ])
+    .arg(format!("-Cmetadata={version}"))
+    .arg("-o")
+    .arg(&rlib)
+    .arg(src.join("lib.rs"))
+    .status()
+    .unwrap();
+  assert!(status.success());
+  rlib.display().to_string()
+}
+
+#[test_log::test]
+fn duplicate_crate_versions() {
+  let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("versions");
+  let one = compile_speak(&dir, "1.0.0", "pub trait Speak {}");
+  let two = compile_speak(&dir, "2.0.0", "pub trait Speak {}");
+  let args = format!(
+    "--crate-type lib --sysroot {} --extern speak_one={one} --extern \
+     speak_two={two}",
+    tu::sysroot()
+  );
+
+  tu::compile(DUPLICATE_VERSIONS, &args, |tcx| {
+    let mut mismatches = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let analysis = serde_json::to_value(&tree.analysis).unwrap();
+        goal_kinds_in(&analysis, "DuplicateCrate", &mut mismatches);
+      }
+    });
+
+    // `Dog` implements the trait of the version the goal doesn't require.
+    assert!(!mismatches.is_empty());
+    for kind in mismatches {
+      let mismatch = &kind["mismatch"];
+      assert_eq!(mismatch["item"]["type"], "Trait");
+      assert_eq!(mismatch["path"], "speak::Speak");
+      assert_eq!(mismatch["required"]["version"], "2.0.0");
+      assert_eq!(mismatch["implemented"]["version"], "1.0.0");
+      assert_eq!(mismatch["implemented"]["source"], one.as_str());
+    }
+  });
+}
+
+const AUTHOR_DIAGNOSTICS: &str = r#"
+#[diagnostic::on_unimplemented(
+  message = "`{Self}` can't be rendered",
+  label = "not renderable"
+)]
+trait Render {}
+trait Inner {}
+#[diagnostic::do_not_recommend]
+impl<T: Inner> Render for Vec<T> {}
+fn render<T: Render>() {}
+fn draw() {
+  render::<u8>();
+  render::<Vec<u8>>();
+}
+"#;
+
+#[test_log::test]
+fn author_diagnostics() {
+  tu::compile_normal(AUTHOR_DIAGNOSTICS, |tcx| {
+    let mut messages = vec![];
+    tu::for_each_body(tcx, |body_id, tcx| {
+      let bundle = analysis::bundle(tcx, body_id).unwrap();
+      for tree in bundle.trees.values() {
+        let analysis = serde_json::to_value(&tree.analysis).unwrap();
+        // The data of the goal at proof node `idx`.
+        let goal_data = |idx: &serde_json::Value| {
+          let (_, node) = tree
+            .nodes
+            .iter_enumerated()
+            .find(|(i, _)| serde_json::to_value(i).unwrap() == *idx)
+            .unwrap();
+          let goal = &serde_json::to_value(node).unwrap()["Goal"];
+          let (_, data) = tree
+            .goals
+            .iter_enumerated()
+            .find(|(i, _)| serde_json::to_value(i).unwrap() == *goal)
+            .unwrap();
+          serde_json::to_value(data).unwrap()
+        };
+        let mut blamed = vec![];
+        for set in analysis["problematicSets"].as_array().unwrap() {
+          for goal in set["goals"].as_array().unwrap() {
+            let data = goal_data(&goal["idx"]);
+            // The ranking and the surfaced message have the same source.
+            assert_eq!(
+              goal["onUnimplemented"] == true,
+              !data["onUnimplemented"].is_null(),
+              "{goal:#}"
+            );
+            let path = &data["value"]["predicate"]["value"]["Clause"]["Trait"]
+              ["trait_ref"]["path"];
+            blamed
+              .push(path.as_array().unwrap().last().unwrap()["name"].clone());
+            if let Some(message) = data["onUnimplemented"]["message"].as_str() {
+              assert_eq!(data["onUnimplemented"]["label"], "not renderable");
+              messages.push(message.to_string());
+            }
+          }
+        }
+
+        // The where-clause of the `do_not_recommend` impl isn't blamed,
+        // the goal it was considered for is.
+        let root = goal_data(&serde_json::to_value(tree.root).unwrap());
+        let root_message = root["onUnimplemented"]["message"].as_str();
+        if root_message.is_some_and(|m| m.contains("Vec")) {
+          assert_eq!(blamed, ["Render"]);
+        }
+      }
+    });
+
+    messages.sort();
+    assert_eq!(messages, [
+      "`std::vec::Vec<u8>` can't be rendered",
+      "`u8` can't be rendered"
+    ]);
+  });
+}
+
+const ITEM_OBLIGATIONS: &str = r"
+trait Shape {}
+trait Named: Shape {}
+struct Circle;
+impl Named for Circle {}
+struct Packet {
+  data: [u8],
+  len: usize,
+}
+trait Store {
+  type Key: Clone;
+}
+struct Token;
+impl Store for Circle {
+  type Key = Token;
+}
+";
+
+#[test_log::test]
+fn item_obligations() {
+  tu::compile_normal(ITEM_OBLIGATIONS, |tcx| {
+    let mut errors = vec![];
+    for (_, item) in argus_lib::find_bodies::find_items(tcx) {
+      let obligations = analysis::item_obligations(tcx, item).unwrap();
+      let Some(bundle) = analysis::item_bundle(tcx, item).unwrap() else {
+        assert!(obligations.is_none());
+        continue;
+      };
+      assert!(obligations.is_some());
+      assert!(!bundle.trees.is_empty());
+
+      let body = serde_json::to_value(&bundle.body).unwrap();
+      for error in body["traitErrors"].as_array().unwrap() {
+        let expr = &body["exprs"][error["idx"].as_u64().unwrap() as usize];
+        for obligation in body["obligations"].as_array().unwrap() {
+          if !error["hashes"]
+            .as_array()
+            .unwrap()
+            .contains(&obligation["hash"])
+          {
+            continue;
+          }
+          let path = &obligation["obligation"]["predicate"]["value"]["Clause"]
+            ["Trait"]["trait_ref"]["path"];
+          let name = &path.as_array().unwrap().last().unwrap()["name"];
+          errors.push((
+            expr["snippet"].as_str().unwrap().to_string(),
+            name.as_str().unwrap().to_string(),
+          ));
+        }
+      }
+    }
+
+    // The supertrait bound of the impl header is reported at the self
+    // type, and only the last field of a struct may be unsized.
+    errors.sort();
+    assert_eq!(errors, [
+      ("Circle".to_string(), "Shape".to_string()),
+      ("Token".to_string(), "Clone".to_string()),
+      ("[u8]".to_string(), "Sized".to_string()),
+    ]);
+  });
+}
+
+const DYN_INCOMPATIBLE: &str = r"
+trait Shape {
+  fn scale<T>(&self, by: T);
+  fn duplicate(&self) -> Self;
+  fn unit();
+}
+fn draw(_: &dyn Shape) {}
+";
+
+#[test_log::test]
+fn dyn_compatibility() {
+  tu::compile_normal(DYN_INCOMPATIBLE, |tcx| {
+    let mut violations = vec![];
+    for (_, item) in argus_lib::find_bodies::find_items(tcx) {
+      let Some(bundle) = analysis::item_bundle(tcx, item).unwrap() else {
+        continue;
+      };
+      let body = serde_json::to_value(&bundle.body).unwrap();
+      for error in body["traitErrors"].as_array().unwrap() {
+        for compatibility in error["dynCompatibility"].as_array().unwrap() {
+          violations
+            .extend(compatibility["violations"].as_array().unwrap().clone());
+        }
+      }
+    }
+
+    let kinds = violations
+      .iter()
+      .map(|violation| violation["kind"].as_str().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(kinds, [
+      "GenericMethod",
+      "ReferencesSelfOutput",
+      "StaticMethod"
+    ]);
+
+    // Each method is exempted by a single `where Self: Sized` at the end of
+    // its signature, static methods only get the compiler's suggestion.
+    let lines = DYN_INCOMPATIBLE.lines().collect::<Vec<_>>();
+    let mut exempted = vec![];
+    for violation in &violations {
+      let fixes = violation["fixes"].as_array().unwrap();
+      let sized = fixes
+        .iter()
+        .filter(|fix| fix["type"] == "RequireSized")
+        .collect::<Vec<_>>();
+      assert_eq!(sized.len(), 1, "{violation:#}");
+      let start = &sized[0]["location"]["r"]["start"];
+      let line = lines[start["line"].as_u64().unwrap() as usize];
+      let (head, tail) =
+        line.split_at(start["column"].as_u64().unwrap() as usize);
+      let suggestion = sized[0]["suggestion"].as_str().unwrap();
+      exempted.push(format!("{head}{suggestion}{tail}"));
+    }
+    assert_eq!(exempted, [
+      "  fn scale<T>(&self, by: T) where Self: Sized;",
+      "  fn duplicate(&self) -> Self where Self: Sized;",
+      "  fn unit() where Self: Sized;",
+    ]);
+
+    // Static methods can take `&self` instead, the others can't.
+    let has_self_parameter = violations
+      .iter()
+      .map(|violation| {
+        violation["fixes"]
+          .as_array()
+          .unwrap()
+          .iter()
+          .any(|fix| fix["type"] == "AddSelfParameter")
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(has_self_parameter, [false, false, true]);
+  });
+}
@@ -73,10 +73,12 @@ class Path<T, D extends Direction> {
 function makeTreeView(
   root: ProofNodeIdx,
   cf: (n: ProofNodeIdx) => ControlFlow,
-  childrenOf: (n: ProofNodeIdx) => ProofNodeIdx[]
+  childrenOf: (n: ProofNodeIdx) => ProofNodeIdx[],
+  sharedWith: (n: ProofNodeIdx) => ProofNodeIdx | undefined
 ): TreeView | undefined {
   const children: MultiRecord<ProofNodeIdx, ProofNodeIdx> = {};
   const parent: Record<ProofNodeIdx, ProofNodeIdx> = {};
+  const shared: Record<ProofNodeIdx, ProofNodeIdx> = {};
   const addChildRel = (from: ProofNodeIdx, to: ProofNodeIdx) => {
     if (children[from]) {
       children[from].push(to);
@@ -97,6 +99,10 @@ function makeTreeView(
         if (prev !== undefined) {
           addChildRel(prev, curr);
         }
+        const original = sharedWith(curr);
+        if (original !== undefined) {
+          shared[curr] = original;
+        }
         newPrev = curr;
         break;
       }
@@ -113,7 +119,7 @@ function makeTreeView(
 
   if (children[root] !== undefined) {
     return {
-      topology: { children, parent }
+      topology: { children, parent, shared }
     };
   }
 }
@@ -126,7 +132,7 @@ class TopologyBuilder {
     readonly root: ProofNodeIdx,
     readonly tree: TreeInfo
   ) {
-    this.topo = { children: {}, parent: {} };
+    this.topo = { children: {}, parent: {}, shared: {} };
   }
 
   public toView(): TreeViewWithRoot {
@@ -237,6 +243,9 @@ export class TreeInfo {
     const childrenOf = (n: ProofNodeIdx) => {
       return tree.topology.children[n] ?? [];
     };
+    const sharedWith = (n: ProofNodeIdx) => {
+      return tree.topology.shared[n];
+    };
     const cf = (n: ProofNodeIdx): ControlFlow => {
       if (showHidden) {
         return "keep";
@@ -259,7 +268,7 @@ export class TreeInfo {
       return "keep";
     };
 
-    const view = makeTreeView(tree.root, cf, childrenOf);
+    const view = makeTreeView(tree.root, cf, childrenOf, sharedWith);
     if (view !== undefined) {
       return new TreeInfo(tree, showHidden, view);
     }
@@ -379,9 +388,27 @@ export class TreeInfo {
     return this.view.topology.parent[n];
   }
 
+  /**
+   * The node whose subtree `n` shares, if `n` is a goal identical to one
+   * serialized before it.
+   */
+  public sharedWith(n: ProofNodeIdx): ProofNodeIdx | undefined {
+    return this.view.topology.shared[n];
+  }
+
+  /**
+   * Children of `n` in the view, shared goals have the children of the
+   * goal they reference.
+   */
   public children(n: ProofNodeIdx): ProofNodeIdx[] {
     const nodesToUnifyFailures = this.nodesInUnificationFailurePath();
-    const children = this.view.topology.children[n] ?? [];
+    const original = this.sharedWith(n);
+    const children =
+      (original !== undefined
+        ? this.view.topology.children[original]
+        : undefined) ??
+      this.view.topology.children[n] ??
+      [];
     return _.difference(children, nodesToUnifyFailures);
   }
 
@@ -453,9 +480,12 @@ export class TreeInfo {
    * Define the heuristic used for inertia in the system. Previously we were
    * using `momentum / velocity` but this proved too sporadic. Some proof trees
    * were deep, needlessely, and this threw a wrench in the order.
+   *
+   * The combination is now computed by the backend from the configured
+   * weights, by default it's the momentum alone.
    */
   public static setInertia = (set: SetHeuristic) => {
-    return set.momentum;
+    return set.inertia;
   };
 
   public setDepth(set: SetHeuristic) {
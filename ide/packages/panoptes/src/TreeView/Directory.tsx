@@ -80,6 +80,30 @@ export const CollapsibleElement = ({
   );
 };
 
+/**
+ * Reference from a shared goal to the identical goal whose subtree it shows.
+ */
+const SharedReference = ({ original }: { original: ProofNodeIdx }) => {
+  const scrollToOriginal = (e: React.MouseEvent<HTMLElement>) => {
+    e.preventDefault();
+    e.stopPropagation();
+    document
+      .querySelector<HTMLSpanElement>(`.proof-node-${original}`)
+      ?.scrollIntoView({
+        block: "start",
+        inline: "nearest",
+        behavior: "smooth"
+      });
+  };
+
+  return (
+    // biome-ignore lint/a11y/useKeyWithClickEvents: TODO
+    <span className="SharedReference" onClick={scrollToOriginal}>
+      (see above)
+    </span>
+  );
+};
+
 export const DirNode = ({
   idx,
   Children
@@ -90,6 +114,7 @@ export const DirNode = ({
   const tree = useContext(TreeAppContext.TreeContext)!;
   const { Wrappers, startOpenP } = useContext(TreeAppContext.TreeRenderContext);
   const node = tree.node(idx);
+  const original = tree.sharedWith(idx);
 
   const arrows: ElementPair = [<IcoTriangleDown />, <IcoTriangleRight />];
   const dots: ElementPair = [<IcoDot />, <IcoDot />];
@@ -97,6 +122,7 @@ export const DirNode = ({
   const infoChild = (
     <span className={`proof-node-${idx}`}>
       <Node node={node} />
+      {original !== undefined ? <SharedReference original={original} /> : null}
     </span>
   );
   const info = (
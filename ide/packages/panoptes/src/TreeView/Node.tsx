@@ -2,6 +2,7 @@ import type {
   CandidateIdx,
   EvaluationResult,
   Node as NodeTy,
+  ParamEnvOrigin,
   ResultIdx
 } from "@argus/common/bindings";
 import { TreeAppContext } from "@argus/common/context";
@@ -13,7 +14,11 @@ import {
   IcoEyeClosed,
   IcoLoop
 } from "@argus/print/Icons";
-import { PrintGoal, PrintImplHeader } from "@argus/print/lib";
+import {
+  PrintGoal,
+  PrintImplHeader,
+  PrintParamEnvClause
+} from "@argus/print/lib";
 import React, { useContext } from "react";
 
 export const ResultRaw = ({ result }: { result: EvaluationResult }) => {
@@ -50,6 +55,15 @@ export const Result = ({ idx }: { idx: ResultIdx }) => {
   return <ResultRaw result={result} />;
 };
 
+const paramEnvOriginLabel = (origin: ParamEnvOrigin) =>
+  origin === "Declared"
+    ? "where clause"
+    : origin === "Implied"
+      ? "implied bound"
+      : origin === "Supertrait"
+        ? "supertrait bound"
+        : "elaborated bound";
+
 export const Candidate = ({ idx }: { idx: CandidateIdx }) => {
   const tree = useContext(TreeAppContext.TreeContext)!;
   const candidate = tree.candidate(idx);
@@ -69,7 +83,13 @@ export const Candidate = ({ idx }: { idx: CandidateIdx }) => {
       </>
     );
   } else if ("ParamEnv" in candidate) {
-    throw new Error("paramEnv not implemented");
+    const { clause, origin, location } = candidate.ParamEnv;
+    return (
+      <>
+        {paramEnvOriginLabel(origin)}{" "}
+        <PrintParamEnvClause clause={clause} location={location} />
+      </>
+    );
   } else {
     throw new Error("Unknown candidate type", candidate);
   }
@@ -1,7 +1,8 @@
 import TreeInfo from "@argus/common/TreeInfo";
-import type { SerializedTree } from "@argus/common/bindings";
+import type { SerializedTree, Ty } from "@argus/common/bindings";
 import { TreeAppContext } from "@argus/common/context";
 import { TyCtxt } from "@argus/print/context";
+import _ from "lodash";
 import React from "react";
 
 import BottomUp from "./BottomUp";
@@ -19,6 +20,21 @@ const ErrorMessage = () => (
   </div>
 );
 
+// Substitute aliases with the type they normalized to when printing.
+const normalizedProjections = (tree: SerializedTree) => {
+  const projections: Record<Ty, Ty> = {};
+  for (const trace of _.values(tree.normalizations)) {
+    if (
+      trace.outcome === "Normalized" &&
+      trace.normalized !== undefined &&
+      projections[trace.alias] === undefined
+    ) {
+      projections[trace.alias] = trace.normalized;
+    }
+  }
+  return projections;
+};
+
 const TreeApp = ({
   tree,
   showHidden = false
@@ -40,7 +56,7 @@ const TreeApp = ({
 
   const tyCtx = {
     interner: internedTys,
-    projections: tree.projectionValues
+    projections: normalizedProjections(tree)
   };
 
   // --------------------------------------
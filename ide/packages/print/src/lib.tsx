@@ -1,7 +1,9 @@
 import type {
   DefinedPath,
+  DefLocation,
   ExtensionCandidates,
   GoalData,
+  GroupedClauses,
   ImplHeader,
   Obligation,
   Ty,
@@ -15,7 +17,10 @@ import MonoSpace from "./MonoSpace";
 import ReportBugUrl from "./ReportBugUrl";
 import "./lib.css";
 import { AllowToggle } from "./context";
-import { PrintImplHeader as UnsafePrintImplHeader } from "./private/argus";
+import {
+  PrintImplHeader as UnsafePrintImplHeader,
+  PrintParamEnvClause as UnsafePrintParamEnvClause
+} from "./private/argus";
 import { PrintDefinitionPath as UnsafePrintDefPath } from "./private/path";
 import {
   PrintGoalPredicate as UnsafePrintGoalPredicate,
@@ -106,6 +111,23 @@ export const PrintImplHeader = ({ impl }: { impl: ImplHeader }) => (
   </AllowToggle.Provider>
 );
 
+export const PrintParamEnvClause = ({
+  clause,
+  location
+}: {
+  clause: GroupedClauses;
+  location?: DefLocation;
+}) => (
+  <AllowToggle.Provider value={true}>
+    <PrintWithFallback
+      object={clause}
+      Content={() => (
+        <UnsafePrintParamEnvClause o={clause} location={location} />
+      )}
+    />
+  </AllowToggle.Provider>
+);
+
 export const PrintGoal = ({ o }: { o: GoalData }) => {
   const debugString =
     o.debugComparison === undefined ? null : (
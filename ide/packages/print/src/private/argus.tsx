@@ -1,6 +1,7 @@
 import type {
   ClauseBound,
   ClauseWithBounds,
+  DefLocation,
   GroupedClauses,
   ImplHeader,
   PolyClauseKind,
@@ -91,6 +92,24 @@ export const PrintImplHeader = ({ o }: { o: ImplHeader }) => {
   );
 };
 
+export const PrintParamEnvClause = ({
+  o,
+  location
+}: {
+  o: GroupedClauses;
+  location?: DefLocation;
+}) => {
+  const LocationAction = useContext(LocationActionable);
+  const clauses = (
+    <PrintClauses grouped={o.grouped} ungrouped={o.other} tysWOBound={[]} />
+  );
+  return location === undefined ? (
+    clauses
+  ) : (
+    <LocationAction location={location}>{clauses}</LocationAction>
+  );
+};
+
 export const PrintClauses = ({
   grouped,
   ungrouped,